@@ -23,6 +23,20 @@ pub const TRACING_BUFFER_CAPACITY: usize = 1024;
 /// Size of a buffer for `ghci` output. Used to implement the TUI.
 pub const GHCI_BUFFER_CAPACITY: usize = 1024;
 
+/// Capacity (in entries) of the channel broadcasting compilation logs from the `ghci` session.
+///
+/// Receivers which fall behind skip to the newest log, so this can be small.
+pub const COMPILATION_LOG_CHANNEL_CAPACITY: usize = 4;
+
 /// Initial capacity for the TUI scrollback buffer, containing data written from `ghci` and
 /// `tracing` log messages.
 pub const TUI_SCROLLBACK_CAPACITY: usize = 16 * 1024;
+
+/// Number of completed reloads kept in the TUI's reload history.
+pub const TUI_RELOAD_HISTORY_CAPACITY: usize = 64;
+
+/// Maximum size (in bytes) of the output kept for each `ghci` hook command in the compilation log.
+///
+/// Test suites can print a lot, and the compilation log is cloned for every consumer, so only the
+/// end of the output is kept.
+pub const HOOK_OUTPUT_CAPACITY: usize = 4 * 1024;
@@ -4,14 +4,18 @@ use std::time::Duration;
 use camino::Utf8PathBuf;
 use clap::builder::ValueParserFactory;
 use clap::Parser;
+use clap::ValueHint;
 use clap_complete::Shell;
 use tracing_subscriber::fmt::format::FmtSpan;
 
 use crate::clap::FmtSpanParserFactory;
 use crate::clap::RustBacktrace;
 use crate::clonable_command::ClonableCommand;
+use crate::ghci::GhciCommand;
 use crate::ignore::GlobMatcher;
 use crate::normal_path::NormalPath;
+use crate::notify::NotifyMethod;
+use crate::project::Project;
 
 /// Ghciwatch loads a GHCi session for a Haskell project and reloads it
 /// when source files change.
@@ -60,6 +64,9 @@ pub struct Opts {
     /// This is used to launch the underlying GHCi session that `ghciwatch` controls.
     ///
     /// May contain quoted arguments which will be parsed in a `sh`-like manner.
+    ///
+    /// Defaults to `stack repl` if a `stack.yaml` is in the current directory and `cabal repl`
+    /// otherwise, loading the component given in `hie.yaml`, if any.
     #[arg(long, value_name = "SHELL_COMMAND")]
     pub command: Option<ClonableCommand>,
 
@@ -72,9 +79,33 @@ pub struct Opts {
     /// A file to write compilation errors to.
     ///
     /// The output format is compatible with `ghcid`'s `--outputfile` option.
-    #[arg(long, alias = "outputfile", alias = "errors")]
+    #[arg(long, alias = "outputfile", alias = "errors", value_hint = ValueHint::FilePath)]
     pub error_file: Option<Utf8PathBuf>,
 
+    /// A file to write compilation errors to as JSON.
+    ///
+    /// This contains the same information as `--error-file`, structured for consumption by
+    /// tools: each diagnostic's severity, path, span, GHC error code, and message, and the
+    /// compilation summary.
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub error_file_json: Option<Utf8PathBuf>,
+
+    /// A file to record how long each module takes to compile.
+    ///
+    /// These times are used to estimate how long compilation will take, which is shown along
+    /// with compilation progress. Defaults to `ghciwatch/compile-times.json` in
+    /// `$XDG_CACHE_HOME` or `~/.cache`.
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub compile_times_file: Option<Utf8PathBuf>,
+
+    /// A file to write a report of the slowest modules to compile to as JSON.
+    ///
+    /// The report is updated after each compilation. It lists every module compiled in this
+    /// session with its total, mean, and latest compile times and how they're trending, slowest
+    /// first. The slowest modules are also logged when `ghciwatch` exits.
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub compile_profile: Option<Utf8PathBuf>,
+
     /// Evaluate Haskell code in comments.
     ///
     /// This parses line commands starting with `-- $>` or multiline commands delimited by `{- $>`
@@ -96,6 +127,83 @@ pub struct Opts {
     #[arg(long, hide = true)]
     pub tui: bool,
 
+    /// Run a Language Server Protocol server on stdin and stdout.
+    ///
+    /// The server publishes diagnostics from each reload and clears diagnostics for files which
+    /// compile cleanly. It doesn't provide any other language features, so it can be used
+    /// alongside (or instead of) a full language server like HLS.
+    ///
+    /// `ghci` output is written to stderr in this mode.
+    #[arg(long, conflicts_with_all = ["tui", "clear"])]
+    pub lsp: bool,
+
+    /// Listen for commands on a Unix-domain socket at the given path.
+    ///
+    /// Each line sent to the socket is a JSON request like `{"command": "reload"}`. Supported
+    /// commands are `reload`, `restart`, `run-tests`, `eval` (with an `expr` field), `status`,
+    /// and `module-graph` (which includes the loaded modules' import graph). Each request gets a
+    /// line of JSON in reply, containing the resulting compilation log.
+    ///
+    /// A socket left at the path by a previous run is replaced, but any other file is an error.
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
+    pub control_socket: Option<Utf8PathBuf>,
+
+    /// Load the GHCi session once, run the test hooks and eval commands, write the error file,
+    /// and exit without watching for changes.
+    ///
+    /// Exits with a non-zero status if compilation fails, if any tests fail, or if any `ghci`
+    /// hook throws an exception. Useful for running the same session setup and hooks in CI or a
+    /// pre-commit hook as in development.
+    #[arg(
+        long,
+        visible_alias = "check",
+        conflicts_with_all = ["tui", "lsp", "control_socket"],
+    )]
+    pub once: bool,
+
+    /// With `--once`, also exit with a non-zero status if compilation produces any warnings.
+    #[arg(long, requires = "once")]
+    pub fail_on_warnings: bool,
+
+    /// A configuration file to load options from.
+    ///
+    /// Keys in the configuration file are the names of long options, like `command` or
+    /// `test-ghci`. Options given on the command line take precedence over the configuration file.
+    ///
+    /// Defaults to `ghciwatch.toml` in the current directory, if it exists. Relative paths in the
+    /// configuration file are relative to the file's directory.
+    /// Changes to the file are applied while `ghciwatch` is running.
+    #[arg(long, value_name = "PATH")]
+    pub config: Option<NormalPath>,
+
+    /// Use the options in the `[profiles.NAME]` table of the configuration file, in addition to
+    /// the options at the top level.
+    #[arg(long, value_name = "NAME")]
+    pub profile: Option<String>,
+
+    /// Run a GHCi session for the `[profiles.NAME]` table of the configuration file, alongside
+    /// the other sessions.
+    ///
+    /// Can be given multiple times to run several GHCi sessions (e.g. for a library, its test
+    /// suite, and an executable) from one `ghciwatch` process, sharing one file watcher. Each file
+    /// change is sent to the sessions whose `--watch` paths or globs match it. Other options given
+    /// on the command line apply to every session.
+    #[arg(long = "session", value_name = "NAME", conflicts_with = "profile")]
+    pub sessions: Vec<String>,
+
+    /// The name of the session these options are for, if several sessions are running. Set by
+    /// [`crate::config::parse_session_opts`].
+    #[arg(skip)]
+    pub session: Option<String>,
+
+    /// The Cabal or Stack project detected in the current directory, if any. Projects aren't
+    /// detected if `--command` or `--file` is given.
+    ///
+    /// This is used for the default `--command`, the default `--watch` paths, and extra restart
+    /// globs for the project's files. Set by [`Opts::init`].
+    #[arg(skip)]
+    pub project: Option<Project>,
+
     /// Generate Markdown CLI documentation.
     #[cfg(feature = "clap-markdown")]
     #[arg(long, hide = true)]
@@ -114,6 +222,14 @@ pub struct Opts {
     #[command(flatten)]
     pub hooks: crate::hooks::HookOpts,
 
+    /// Options for running only the tests affected by a reload.
+    #[command(flatten)]
+    pub tests: TestOpts,
+
+    /// Options for notifications when compilation starts failing or is fixed.
+    #[command(flatten)]
+    pub notify: NotifyOpts,
+
     /// Options to modify file watching.
     #[command(flatten)]
     pub watch: WatchOpts,
@@ -151,9 +267,23 @@ pub struct WatchOpts {
     /// A path to watch for changes.
     ///
     /// Directories are watched recursively. Can be given multiple times.
-    #[arg(long = "watch", value_name = "PATH")]
+    ///
+    /// Defaults to the `hs-source-dirs` of the `.cabal` files (or the `source-dirs` of the
+    /// `package.yaml` files) in the current directory, or `src` if there are none.
+    #[arg(long = "watch", value_name = "PATH", value_hint = ValueHint::AnyPath)]
     pub paths: Vec<NormalPath>,
 
+    /// Watch the GHCi session's module import search paths, as listed by `:show paths`.
+    ///
+    /// The watched directories are updated after the session starts up or restarts, so projects
+    /// with several source directories (like `src`, `app`, and `test`) don't need a `--watch`
+    /// for each one. Build directories like `dist-newstyle` and `.stack-work` are skipped.
+    ///
+    /// Any `--watch` paths are watched as well. If none are given, nothing else is watched by
+    /// default.
+    #[arg(long)]
+    pub watch_search_paths: bool,
+
     /// Reload the GHCi session when paths matching this glob change.
     ///
     /// By default, only changes to Haskell source files trigger reloads. If you'd like to exclude
@@ -171,7 +301,8 @@ pub struct WatchOpts {
 
     /// Restart the GHCi session when paths matching this glob change.
     ///
-    /// By default, only changes to `.cabal` or `.ghci` files will trigger restarts.
+    /// By default, only changes to `.cabal` or `.ghci` files, or to the detected project's
+    /// `cabal.project`, `stack.yaml`, `package.yaml`, or `hie.yaml` files, will trigger restarts.
     ///
     /// See `--reload-globs` for more details.
     ///
@@ -192,6 +323,85 @@ impl WatchOpts {
     pub fn restart_globs(&self) -> miette::Result<GlobMatcher> {
         GlobMatcher::from_globs(self.restart_globs.iter())
     }
+
+    /// Build the restart and reload globs into one matcher, used to decide which session a
+    /// changed path is sent to.
+    pub fn watched_globs(&self) -> miette::Result<GlobMatcher> {
+        GlobMatcher::from_globs(self.restart_globs.iter().chain(&self.reload_globs))
+    }
+}
+
+/// Options for running only the tests affected by a reload.
+#[derive(Debug, Clone, clap::Args)]
+#[clap(next_help_heading = "Targeted test options")]
+pub struct TestOpts {
+    /// A `ghci` command to run the tests in a single test module, like
+    /// `Test.Hspec.hspec {module}.spec`.
+    ///
+    /// `{module}` is replaced with the name of the test module.
+    ///
+    /// When this is given, reloads run this command once for each test module affected by the
+    /// changed modules or the modules which import them, instead of the `--test-ghci` and
+    /// `--test-shell` hooks. Startups, restarts, and manual reloads still run the full test suite.
+    #[arg(long, value_name = "GHCI_CMD")]
+    pub targeted_test_ghci: Option<GhciCommand>,
+
+    /// Suffixes which name the test module for a source module.
+    ///
+    /// With the default suffixes, changing `My.Module` runs the tests in `My.ModuleSpec` and
+    /// `My.ModuleTest`, if they're loaded. Changing a test module runs its own tests.
+    ///
+    /// Can be given multiple times.
+    #[arg(
+        long = "test-module-suffix",
+        value_name = "SUFFIX",
+        default_values = ["Spec", "Test"],
+    )]
+    pub test_module_suffixes: Vec<String>,
+
+    /// Run the tests in `TEST_MODULE` when `MODULE` changes, in addition to any test modules
+    /// found by suffix.
+    ///
+    /// Can be given multiple times.
+    #[arg(
+        long = "test-module",
+        value_name = "MODULE=TEST_MODULE",
+        value_parser = parse_test_module_mapping,
+    )]
+    pub test_modules: Vec<(String, String)>,
+}
+
+fn parse_test_module_mapping(input: &str) -> Result<(String, String), String> {
+    match input.split_once('=') {
+        Some((module, test_module)) if !module.is_empty() && !test_module.is_empty() => {
+            Ok((module.to_owned(), test_module.to_owned()))
+        }
+        _ => Err(format!(
+            "Expected a mapping like `My.Module=My.ModuleSpec`, but got `{input}`"
+        )),
+    }
+}
+
+/// Options for notifications when compilation starts failing or is fixed.
+#[derive(Debug, Clone, clap::Args)]
+#[clap(next_help_heading = "Notification options")]
+pub struct NotifyOpts {
+    /// Notify you when compilation starts failing or is fixed.
+    ///
+    /// `bell` rings the terminal bell. `osc9` and `osc777` send desktop notifications through
+    /// the terminal, if it supports those escape sequences.
+    ///
+    /// Can be given multiple times.
+    #[arg(long = "notify", value_name = "METHOD")]
+    pub methods: Vec<NotifyMethod>,
+
+    /// A shell command to run when compilation starts failing or is fixed, like `notify-send
+    /// ghciwatch`.
+    ///
+    /// A summary of the compilation is written to the command's stdin, like `ghciwatch:
+    /// Compilation failed: 1 error, 2 warnings`.
+    #[arg(long, value_name = "SHELL_CMD")]
+    pub notify_command: Option<ClonableCommand>,
 }
 
 // TODO: Possibly set `RUST_LIB_BACKTRACE` from `RUST_BACKTRACE` as well, so that `full`
@@ -236,7 +446,7 @@ pub struct LoggingOpts {
     /// Path to write JSON logs to.
     ///
     /// JSON logs are not yet stable and the format may change on any release.
-    #[arg(long, value_name = "PATH")]
+    #[arg(long, value_name = "PATH", value_hint = ValueHint::FilePath)]
     pub log_json: Option<Utf8PathBuf>,
 }
 
@@ -244,10 +454,30 @@ impl Opts {
     /// Perform late initialization of the command-line arguments. If `init` isn't called before
     /// the arguments are used, the behavior is undefined.
     pub fn init(&mut self) -> miette::Result<()> {
+        if self.file.is_none() && self.command.is_none() {
+            self.project = Project::detect(&crate::current_dir_utf8()?)?;
+        }
+
         if let Some(file) = &self.file {
             self.watch.paths.push(file.clone());
-        } else if self.watch.paths.is_empty() {
-            self.watch.paths.push(NormalPath::from_cwd("src")?);
+        } else if self.watch.paths.is_empty() && !self.watch.watch_search_paths {
+            match &self.project {
+                Some(project) if !project.source_dirs.is_empty() => {
+                    for dir in &project.source_dirs {
+                        self.watch.paths.push(NormalPath::from_cwd(dir)?);
+                    }
+                }
+                _ => {
+                    self.watch.paths.push(NormalPath::from_cwd("src")?);
+                }
+            }
+        }
+
+        if let Some(project) = &self.project {
+            // Put these first so they can be overridden by ignore globs.
+            self.watch
+                .restart_globs
+                .splice(0..0, project.restart_globs());
         }
 
         // These help our libraries (particularly `color-eyre`) see these options.
@@ -104,6 +104,14 @@ impl ClonableCommand {
         self
     }
 
+    /// Set an environment variable for this command. See [`StdCommand::env`].
+    pub fn env(mut self, key: impl Into<OsString>, value: impl Into<OsString>) -> Self {
+        if let Some(env) = &mut self.env {
+            env.insert(key.into(), Some(value.into()));
+        }
+        self
+    }
+
     /// Create a new [`std::process::Command`] from this command's configuration.
     pub fn as_std(&self) -> StdCommand {
         let mut ret = StdCommand::new(&self.program);
@@ -0,0 +1,458 @@
+//! Loading options from a `ghciwatch.toml` configuration file.
+//!
+//! The configuration file maps long option names to values, like this:
+//!
+//! ```toml
+//! command = "cabal v2-repl lib:test-dev"
+//! watch = ["src", "test"]
+//! reload-glob = ["**/*.persistentmodels"]
+//!
+//! [profiles.test]
+//! test-ghci = ["TestMain.testMain"]
+//! ```
+//!
+//! The options are translated to command-line arguments and parsed along with the real
+//! command-line arguments, so every option is supported with the same syntax and validation.
+//! Options given on the command line take precedence over options in a profile, which take
+//! precedence over options at the top level of the file.
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use clap::parser::ValueSource;
+use clap::ArgAction;
+use clap::ArgMatches;
+use clap::CommandFactory;
+use clap::FromArgMatches;
+use clap::Parser;
+use clap::ValueHint;
+use miette::miette;
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+use toml_edit::DocumentMut;
+use toml_edit::Item;
+use toml_edit::Table;
+use toml_edit::Value;
+
+use crate::cli::Opts;
+use crate::normal_path::NormalPath;
+
+/// The name of the configuration file to search for.
+pub const CONFIG_FILE_NAME: &str = "ghciwatch.toml";
+
+/// Keys which are meaningless in a configuration file.
+const RESERVED_KEYS: [&str; 2] = ["config", "profile"];
+
+/// Parse [`Opts`] from the command-line arguments, merged with options from the configuration
+/// file.
+///
+/// Like [`clap::Parser::parse`], this exits the process if the command-line arguments are
+/// invalid.
+///
+/// If a configuration file is loaded, its path is stored in [`Opts::config`].
+pub fn parse_opts() -> miette::Result<Opts> {
+    parse_opts_from(std::env::args_os().collect(), None)
+}
+
+/// Parse [`Opts`] for each session given with [`Opts::sessions`].
+///
+/// Each session uses the configuration profile of the same name, and has late initialization
+/// performed with [`Opts::init`]. If no sessions were given, this returns `opts` unchanged.
+pub fn parse_session_opts(opts: &Opts) -> miette::Result<Vec<Opts>> {
+    if opts.sessions.is_empty() {
+        return Ok(vec![opts.clone()]);
+    }
+
+    opts.sessions
+        .iter()
+        .map(|session| reload_opts(Some(session)))
+        .collect()
+}
+
+/// Parse [`Opts`] again, after the configuration file changes.
+///
+/// This is like [`parse_opts`], but also performs late initialization with [`Opts::init`]. If a
+/// `session` is given, its configuration profile is used instead of the `--profile` option.
+pub fn reload_opts(session: Option<&str>) -> miette::Result<Opts> {
+    let mut opts = parse_opts_from(std::env::args_os().collect(), session)?;
+    opts.session = session.map(ToOwned::to_owned);
+    opts.init()?;
+    Ok(opts)
+}
+
+fn parse_opts_from(args: Vec<OsString>, session: Option<&str>) -> miette::Result<Opts> {
+    let matches = Opts::command().get_matches_from(&args);
+    let mut cli = Opts::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    if let Some(session) = session {
+        cli.profile = Some(session.to_owned());
+    }
+
+    let path = match &cli.config {
+        Some(path) => Some(path.clone()),
+        None => find_config_file(crate::current_dir_utf8()?)
+            .map(NormalPath::from_cwd)
+            .transpose()?,
+    };
+    let Some(path) = path else {
+        if let Some(profile) = &cli.profile {
+            return Err(miette!(
+                "Profile `{profile}` was requested, but no {CONFIG_FILE_NAME} was found"
+            ));
+        }
+        return Ok(cli);
+    };
+
+    let contents = std::fs::read_to_string(&path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to read {path}"))?;
+    let options = config_options(&contents, cli.profile.as_deref())
+        .wrap_err_with(|| format!("Failed to load {path}"))?;
+    let config_dir = path
+        .relative()
+        .parent()
+        .map(Utf8Path::to_owned)
+        .unwrap_or_default();
+    let config_args = config_args(&matches, &options, &config_dir)
+        .wrap_err_with(|| format!("Failed to load {path}"))?;
+
+    let mut args = args.into_iter();
+    let merged = args
+        .next()
+        .into_iter()
+        .chain(config_args.into_iter().map(OsString::from))
+        .chain(args);
+    let mut opts = Opts::try_parse_from(merged)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Invalid options in {path}"))?;
+    opts.config = Some(path);
+    opts.profile = cli.profile;
+    Ok(opts)
+}
+
+/// Find the configuration file in the given directory.
+///
+/// We don't look in parent directories: commands in the configuration file (like `command` and
+/// shell hooks) run in the current directory, so they'd behave differently depending on where
+/// `ghciwatch` is started.
+fn find_config_file(dir: Utf8PathBuf) -> Option<Utf8PathBuf> {
+    Some(dir.join(CONFIG_FILE_NAME)).filter(|path| path.is_file())
+}
+
+/// Parse a configuration file and select the options for the given profile.
+///
+/// Options may be at the top level of the file or in a `[tool.ghciwatch]` table.
+fn config_options(
+    contents: &str,
+    profile: Option<&str>,
+) -> miette::Result<BTreeMap<String, Value>> {
+    let document = contents.parse::<DocumentMut>().into_diagnostic()?;
+    let root = match document
+        .get("tool")
+        .and_then(|tool| tool.get("ghciwatch"))
+        .and_then(Item::as_table)
+    {
+        Some(table) => table,
+        None => document.as_table(),
+    };
+
+    let mut options = table_options(root)?;
+
+    let profiles = root.get("profiles").and_then(Item::as_table);
+    if let Some(profile) = profile {
+        let table = profiles
+            .and_then(|profiles| profiles.get(profile))
+            .and_then(Item::as_table)
+            .ok_or_else(|| miette!("No `[profiles.{profile}]` table found"))?;
+        options.extend(table_options(table)?);
+    }
+
+    Ok(options)
+}
+
+/// Get the options in a table, skipping nested tables like `[profiles]`.
+fn table_options(table: &Table) -> miette::Result<BTreeMap<String, Value>> {
+    let mut options = BTreeMap::new();
+    for (key, item) in table.iter() {
+        match item {
+            Item::Value(value) => {
+                options.insert(key.to_owned(), value.clone());
+            }
+            Item::Table(_) if key == "profiles" || key == "tool" => {}
+            _ => {
+                return Err(miette!(
+                    "`{key}` must be a string, number, boolean, or array"
+                ));
+            }
+        }
+    }
+    Ok(options)
+}
+
+/// Translate configuration options into command-line arguments.
+///
+/// Options which were given on the command line (according to `matches`), or which conflict with
+/// options given on the command line, are skipped. Relative
+/// paths for path-valued options are made relative to `config_dir`, the configuration file's
+/// directory.
+fn config_args(
+    matches: &ArgMatches,
+    options: &BTreeMap<String, Value>,
+    config_dir: &Utf8Path,
+) -> miette::Result<Vec<String>> {
+    let command = Opts::command();
+    let mut args = Vec::new();
+    let cli_args = command
+        .get_arguments()
+        .filter(|arg| matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine))
+        .collect::<Vec<_>>();
+
+    for (key, value) in options {
+        let arg = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(key.as_str()))
+            .filter(|_| !RESERVED_KEYS.contains(&key.as_str()))
+            .ok_or_else(|| miette!("Unknown option `{key}`"))?;
+
+        if matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine) {
+            tracing::debug!(
+                key,
+                "Option given on the command line, ignoring config value"
+            );
+            continue;
+        }
+
+        // Clap declares conflicts on one side only, so we check both directions.
+        if let Some(cli_arg) = cli_args.iter().find(|cli_arg| {
+            command.get_arg_conflicts_with(arg).contains(cli_arg)
+                || command.get_arg_conflicts_with(cli_arg).contains(&arg)
+        }) {
+            tracing::debug!(
+                key,
+                conflict = %cli_arg.get_id(),
+                "Option conflicts with an option given on the command line, ignoring config value"
+            );
+            continue;
+        }
+
+        if !arg.get_action().takes_values() {
+            match value.as_bool() {
+                Some(true) => args.push(format!("--{key}")),
+                Some(false) => {}
+                None => return Err(miette!("`{key}` must be a boolean")),
+            }
+            continue;
+        }
+
+        let is_path = matches!(
+            arg.get_value_hint(),
+            ValueHint::AnyPath | ValueHint::FilePath | ValueHint::DirPath
+        );
+        let value_arg = |value: &Value| -> miette::Result<String> {
+            let value = scalar(key, value)?;
+            let value = if is_path {
+                config_dir.join(value).into_string()
+            } else {
+                value
+            };
+            Ok(format!("--{key}={value}"))
+        };
+
+        match value {
+            Value::Array(array) => {
+                if !matches!(arg.get_action(), ArgAction::Append) {
+                    return Err(miette!("`{key}` only accepts a single value"));
+                }
+                for value in array {
+                    args.push(value_arg(value)?);
+                }
+            }
+            value => {
+                args.push(value_arg(value)?);
+            }
+        }
+    }
+
+    Ok(args)
+}
+
+/// Format a scalar value as a command-line argument.
+fn scalar(key: &str, value: &Value) -> miette::Result<String> {
+    match value {
+        Value::String(value) => Ok(value.value().clone()),
+        Value::Integer(value) => Ok(value.value().to_string()),
+        Value::Float(value) => Ok(value.value().to_string()),
+        Value::Boolean(value) => Ok(value.value().to_string()),
+        _ => Err(miette!("`{key}` must be a string, number, or boolean")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    const CONFIG: &str = indoc!(
+        r#"
+        command = "cabal v2-repl lib:test-dev"
+        watch = ["src", "test"]
+        clear = true
+        no-interrupt-reloads = false
+        debounce = "1s"
+
+        [profiles.test]
+        command = "cabal v2-repl test:tests"
+        test-ghci = ["TestMain.testMain"]
+        "#
+    );
+
+    fn args(config: &str, profile: Option<&str>, cli: &[&str]) -> miette::Result<Vec<String>> {
+        let matches = Opts::command()
+            .get_matches_from(std::iter::once("ghciwatch").chain(cli.iter().copied()));
+        config_args(
+            &matches,
+            &config_options(config, profile)?,
+            Utf8Path::new(""),
+        )
+    }
+
+    #[test]
+    fn test_config_args() {
+        assert_eq!(
+            args(CONFIG, None, &[]).unwrap(),
+            [
+                "--clear",
+                "--command=cabal v2-repl lib:test-dev",
+                "--debounce=1s",
+                "--watch=src",
+                "--watch=test",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_args_profile() {
+        assert_eq!(
+            args(CONFIG, Some("test"), &["--watch", "lib"]).unwrap(),
+            [
+                "--clear",
+                "--command=cabal v2-repl test:tests",
+                "--debounce=1s",
+                "--test-ghci=TestMain.testMain",
+            ]
+        );
+
+        assert!(args(CONFIG, Some("puppy"), &[]).is_err());
+    }
+
+    #[test]
+    fn test_config_args_tool_table() {
+        assert_eq!(
+            args(
+                indoc!(
+                    r#"
+                    [tool.ghciwatch]
+                    after-startup-ghci = [":set args --match=/Puppy/"]
+                    "#
+                ),
+                None,
+                &[]
+            )
+            .unwrap(),
+            ["--after-startup-ghci=:set args --match=/Puppy/"]
+        );
+    }
+
+    #[test]
+    fn test_config_args_paths() {
+        let matches = Opts::command().get_matches_from(["ghciwatch"]);
+        let options = config_options(
+            indoc!(
+                r#"
+                command = "cabal v2-repl"
+                watch = ["src", "/nix/store/haskell"]
+                error-file = "ghcid.txt"
+                "#
+            ),
+            None,
+        )
+        .unwrap();
+        assert_eq!(
+            config_args(&matches, &options, Utf8Path::new("..")).unwrap(),
+            [
+                "--command=cabal v2-repl",
+                "--error-file=../ghcid.txt",
+                "--watch=../src",
+                "--watch=/nix/store/haskell",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_config_args_conflicts() {
+        // `FILE` conflicts with `--command`, and `--lsp` conflicts with `--clear`.
+        assert_eq!(
+            args(CONFIG, None, &["--lsp", "src/Main.hs"]).unwrap(),
+            ["--debounce=1s", "--watch=src", "--watch=test"]
+        );
+    }
+
+    #[test]
+    fn test_config_args_errors() {
+        assert!(args("puppy = true", None, &[]).is_err());
+        assert!(args("profile = \"test\"", None, &[]).is_err());
+        assert!(args("clear = \"yes\"", None, &[]).is_err());
+        assert!(args("command = [\"ghci\", \"ghci\"]", None, &[]).is_err());
+        assert!(args("[watch]\npath = \"src\"", None, &[]).is_err());
+    }
+
+    #[test]
+    fn test_parse_opts_from() {
+        let dir = std::env::temp_dir().join(format!("ghciwatch-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(CONFIG_FILE_NAME);
+        std::fs::write(&path, CONFIG).unwrap();
+
+        let opts = parse_opts_from(
+            [
+                "ghciwatch",
+                "--config",
+                path.to_str().unwrap(),
+                "--debounce",
+                "2s",
+            ]
+            .into_iter()
+            .map(OsString::from)
+            .collect(),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            opts.command.unwrap().to_string(),
+            "cabal v2-repl lib:test-dev"
+        );
+        assert!(opts.clear);
+        assert_eq!(opts.watch.debounce, std::time::Duration::from_secs(2));
+        assert_eq!(opts.watch.paths.len(), 2);
+
+        let opts = parse_opts_from(
+            ["ghciwatch", "--config", path.to_str().unwrap()]
+                .into_iter()
+                .map(OsString::from)
+                .collect(),
+            Some("test"),
+        )
+        .unwrap();
+        assert_eq!(opts.profile.as_deref(), Some("test"));
+        assert_eq!(
+            opts.command.unwrap().to_string(),
+            "cabal v2-repl test:tests"
+        );
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,292 @@
+//! A Unix-domain socket for controlling the `ghci` session from other programs.
+//!
+//! The protocol is line-delimited JSON. Each request is a line like this:
+//!
+//! ```json
+//! {"command": "eval", "expr": "1 + 1"}
+//! ```
+//!
+//! Each request gets a line of JSON in reply, either `{"ok": true, "log": ..., "output": ...}` or
+//! `{"ok": false, "error": "..."}`.
+
+use std::os::unix::fs::FileTypeExt;
+
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use miette::miette;
+use miette::Context;
+use miette::IntoDiagnostic;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncWriteExt;
+use tokio::io::BufReader;
+use tokio::net::UnixListener;
+use tokio::net::UnixStream;
+use tokio::sync::mpsc;
+use tracing::instrument;
+
+use crate::ghci::manager::GhciEvent;
+use crate::ghci::manager::GhciResponse;
+use crate::ghci::GhciCommand;
+use crate::shutdown::ShutdownHandle;
+
+/// A request sent to the control socket.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "command", rename_all = "kebab-case")]
+enum ControlRequest {
+    /// Reload every module.
+    Reload,
+    /// Restart the `ghci` session.
+    Restart,
+    /// Run the test hooks.
+    RunTests,
+    /// Evaluate an expression or `ghci` command.
+    Eval {
+        /// The expression to evaluate.
+        expr: String,
+    },
+    /// Get the most recent compilation log.
+    Status,
+    /// Get the import graph of the loaded modules.
+    ModuleGraph,
+}
+
+impl ControlRequest {
+    /// Convert this request into an event for the `ghci` session, replying to the given channel.
+    fn into_event(self, reply: mpsc::Sender<miette::Result<GhciResponse>>) -> GhciEvent {
+        let reply = Some(reply);
+        match self {
+            ControlRequest::Reload => GhciEvent::ReloadAll { reply },
+            ControlRequest::Restart => GhciEvent::Restart { reply },
+            ControlRequest::RunTests => GhciEvent::RunTests { reply },
+            ControlRequest::Eval { expr } => GhciEvent::Eval {
+                command: GhciCommand(expr),
+                reply,
+            },
+            ControlRequest::Status => GhciEvent::Status { reply },
+            ControlRequest::ModuleGraph => GhciEvent::ModuleGraph { reply },
+        }
+    }
+}
+
+/// A reply sent from the control socket.
+#[derive(Debug, Serialize)]
+struct ControlResponse {
+    ok: bool,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    response: Option<GhciResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+impl From<miette::Result<GhciResponse>> for ControlResponse {
+    fn from(result: miette::Result<GhciResponse>) -> Self {
+        match result {
+            Ok(response) => Self {
+                ok: true,
+                response: Some(response),
+                error: None,
+            },
+            Err(err) => Self {
+                ok: false,
+                response: None,
+                error: Some(err.to_string()),
+            },
+        }
+    }
+}
+
+/// Listen for requests on a Unix-domain socket at `path` and forward them to the `ghci` session.
+#[instrument(level = "debug", skip(handle, ghci_sender))]
+pub async fn run_control_socket(
+    mut handle: ShutdownHandle,
+    path: Utf8PathBuf,
+    ghci_sender: mpsc::Sender<GhciEvent>,
+) -> miette::Result<()> {
+    remove_stale_socket(&path)?;
+
+    let listener = UnixListener::bind(&path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to listen on control socket {path}"))?;
+    tracing::debug!(%path, "Listening on control socket");
+
+    loop {
+        tokio::select! {
+            _ = handle.on_shutdown_requested() => {
+                break;
+            }
+            connection = listener.accept() => {
+                let (stream, _) = connection.into_diagnostic()?;
+                tokio::task::spawn(handle_connection(stream, ghci_sender.clone()));
+            }
+        }
+    }
+
+    remove_socket(&path);
+
+    Ok(())
+}
+
+/// Remove a socket left over from a previous run, which would make `bind` fail.
+///
+/// Anything else at `path` is left alone, so a typo in `--control-socket` can't delete the user's
+/// files.
+fn remove_stale_socket(path: &Utf8Path) -> miette::Result<()> {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            return Ok(());
+        }
+        Err(err) => {
+            return Err(err)
+                .into_diagnostic()
+                .wrap_err_with(|| format!("Failed to read metadata for {path}"));
+        }
+    };
+    if !metadata.file_type().is_socket() {
+        return Err(miette!(
+            "Control socket path {path} already exists and is not a socket"
+        ));
+    }
+    tracing::debug!(%path, "Removing stale control socket");
+    std::fs::remove_file(path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to remove stale control socket {path}"))
+}
+
+fn remove_socket(path: &Utf8Path) {
+    if let Err(err) = std::fs::remove_file(path) {
+        tracing::debug!(%path, "Failed to remove control socket: {err}");
+    }
+}
+
+/// Respond to requests from a single client until it disconnects.
+#[instrument(level = "debug", skip_all)]
+async fn handle_connection(stream: UnixStream, ghci_sender: mpsc::Sender<GhciEvent>) {
+    if let Err(err) = handle_connection_inner(stream, ghci_sender).await {
+        tracing::debug!("Control socket connection failed: {err}");
+    }
+}
+
+async fn handle_connection_inner(
+    stream: UnixStream,
+    ghci_sender: mpsc::Sender<GhciEvent>,
+) -> miette::Result<()> {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Some(line) = lines.next_line().await.into_diagnostic()? {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = match serde_json::from_str::<ControlRequest>(&line) {
+            Ok(request) => {
+                tracing::debug!(?request, "Received control socket request");
+                handle_request(request, &ghci_sender).await.into()
+            }
+            Err(err) => ControlResponse::from(Err(miette!("Invalid request: {err}"))),
+        };
+
+        let mut response = serde_json::to_vec(&response).into_diagnostic()?;
+        response.push(b'\n');
+        writer.write_all(&response).await.into_diagnostic()?;
+    }
+
+    Ok(())
+}
+
+async fn handle_request(
+    request: ControlRequest,
+    ghci_sender: &mpsc::Sender<GhciEvent>,
+) -> miette::Result<GhciResponse> {
+    let (reply_sender, mut reply_receiver) = mpsc::channel(1);
+    ghci_sender
+        .send(request.into_event(reply_sender))
+        .await
+        .map_err(|_| miette!("ghci event channel closed"))?;
+    reply_receiver
+        .recv()
+        .await
+        .ok_or_else(|| miette!("ghci session stopped before replying"))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::ghci::CompilationLog;
+
+    #[test]
+    fn test_parse_request() {
+        assert_eq!(
+            serde_json::from_str::<ControlRequest>(r#"{"command": "reload"}"#).unwrap(),
+            ControlRequest::Reload
+        );
+        assert_eq!(
+            serde_json::from_str::<ControlRequest>(r#"{"command": "run-tests"}"#).unwrap(),
+            ControlRequest::RunTests
+        );
+        assert_eq!(
+            serde_json::from_str::<ControlRequest>(r#"{"command": "module-graph"}"#).unwrap(),
+            ControlRequest::ModuleGraph
+        );
+        assert_eq!(
+            serde_json::from_str::<ControlRequest>(r#"{"command": "eval", "expr": "1 + 1"}"#)
+                .unwrap(),
+            ControlRequest::Eval {
+                expr: "1 + 1".to_owned()
+            }
+        );
+
+        // Negative cases.
+        assert!(serde_json::from_str::<ControlRequest>(r#"{"command": "puppy"}"#).is_err());
+        assert!(serde_json::from_str::<ControlRequest>(r#"{"command": "eval"}"#).is_err());
+        assert!(serde_json::from_str::<ControlRequest>("reload").is_err());
+    }
+
+    #[test]
+    fn test_serialize_response() {
+        assert_eq!(
+            serde_json::to_string(&ControlResponse::from(Ok(GhciResponse {
+                log: CompilationLog::default(),
+                output: Some("2\n".to_owned()),
+                module_graph: None,
+            })))
+            .unwrap(),
+            r#"{"ok":true,"log":{"summary":null,"diagnostics":[]},"output":"2\n"}"#
+        );
+        assert_eq!(
+            serde_json::to_string(&ControlResponse::from(Err(miette!("Oh no")))).unwrap(),
+            r#"{"ok":false,"error":"Oh no"}"#
+        );
+    }
+
+    #[test]
+    fn test_remove_stale_socket() {
+        let dir = Utf8PathBuf::try_from(
+            std::env::temp_dir().join(format!("ghciwatch-control-socket-{}", std::process::id())),
+        )
+        .unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let path = dir.join("ghciwatch.sock");
+        remove_stale_socket(&path).unwrap();
+
+        drop(std::os::unix::net::UnixListener::bind(&path).unwrap());
+        remove_stale_socket(&path).unwrap();
+        assert!(!path.exists());
+
+        // Regular files are left alone.
+        let path = dir.join("MyLib.hs");
+        std::fs::write(&path, "module MyLib where\n").unwrap();
+        let result = remove_stale_socket(&path);
+        let contents = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_err());
+        assert_eq!(contents, "module MyLib where\n");
+    }
+}
@@ -1,14 +1,35 @@
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::buffers::HOOK_OUTPUT_CAPACITY;
+use crate::ghci::parse::parse_hook_failure;
+use crate::ghci::parse::parse_test_output;
 use crate::ghci::parse::CompilationResult;
 use crate::ghci::parse::CompilationSummary;
 use crate::ghci::parse::GhcDiagnostic;
 use crate::ghci::parse::GhcMessage;
+use crate::ghci::parse::HookFailure;
+use crate::ghci::parse::PositionRange;
 use crate::ghci::parse::Severity;
+use crate::ghci::parse::TestResults;
+use crate::ghci::GhciOutput;
 
 /// A log of messages from compilation, used to write the error log.
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct CompilationLog {
     pub summary: Option<CompilationSummary>,
     pub diagnostics: Vec<GhcDiagnostic>,
+    /// Results parsed from the output of test commands, if any.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tests: Option<TestResults>,
+    /// The modules expected to be recompiled by a reload, according to the import graph: the
+    /// changed modules and every module which imports them.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub affected_modules: Vec<String>,
+    /// The results of the `ghci` hook commands run after compilation, like test hooks.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub hooks: Vec<HookResult>,
 }
 
 impl CompilationLog {
@@ -16,6 +37,136 @@ impl CompilationLog {
     pub fn result(&self) -> Option<CompilationResult> {
         self.summary.map(|summary| summary.result)
     }
+
+    /// Did any tests fail?
+    pub fn tests_failed(&self) -> bool {
+        self.tests.as_ref().is_some_and(|tests| tests.failed > 0)
+    }
+
+    /// Did any `ghci` hook commands fail?
+    pub fn hooks_failed(&self) -> bool {
+        self.hooks.iter().any(|hook| hook.failure.is_some())
+    }
+
+    /// Describe the reasons this compilation fails a `--once` check, if any.
+    ///
+    /// Failed compilation, failed tests, and failed `ghci` hook commands always fail the check.
+    /// Warnings only fail it if `fail_on_warnings` is set.
+    pub fn check_failures(&self, fail_on_warnings: bool) -> Vec<String> {
+        let mut failures = Vec::new();
+
+        if let Some(CompilationResult::Err) = self.result() {
+            failures.push("Compilation failed".to_owned());
+        }
+
+        let warnings = self
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Warning)
+            .count();
+        if fail_on_warnings && warnings > 0 {
+            failures.push(format!(
+                "Compilation produced {warnings} {}",
+                if warnings == 1 { "warning" } else { "warnings" }
+            ));
+        }
+
+        if let Some(tests) = self.tests.as_ref().filter(|tests| tests.failed > 0) {
+            failures.push(format!("Tests failed: {tests}"));
+        }
+
+        for hook in &self.hooks {
+            if let Some(failure) = &hook.failure {
+                failures.push(format!(
+                    "{} command `{}` {failure}",
+                    hook.event, hook.command
+                ));
+            }
+        }
+
+        failures
+    }
+
+    /// Record the result of a `ghci` hook command from its output.
+    ///
+    /// Returns the recorded result.
+    pub fn record_hook(
+        &mut self,
+        event: impl ToString,
+        command: impl ToString,
+        duration: Duration,
+        output: &GhciOutput,
+    ) -> &HookResult {
+        let output = format!("{}{}", output.stdout, output.stderr);
+        self.hooks.push(HookResult {
+            event: event.to_string(),
+            command: command.to_string(),
+            duration_ms: duration.as_millis() as u64,
+            failure: parse_hook_failure(&output),
+            output: tail(&output, HOOK_OUTPUT_CAPACITY).to_owned(),
+        });
+        self.hooks.last().expect("We just pushed a hook result")
+    }
+
+    /// Record the results of a test suite from its output, if it contains a summary.
+    pub fn record_test_output(&mut self, output: &str) {
+        if let Some(results) = parse_test_output(output) {
+            self.tests
+                .get_or_insert_with(Default::default)
+                .merge(results);
+        }
+    }
+}
+
+/// The result of running a `ghci` hook command.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct HookResult {
+    /// The hook's event, like `test` or `after-reload`.
+    pub event: String,
+    /// The command which was run.
+    pub command: String,
+    /// How long the command took, in milliseconds.
+    pub duration_ms: u64,
+    /// How the command failed, if it did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub failure: Option<HookFailure>,
+    /// The end of the output the command printed on `stdout` and `stderr`, at most
+    /// [`HOOK_OUTPUT_CAPACITY`] bytes.
+    pub output: String,
+}
+
+/// Get the end of `text`, at most `max_len` bytes long.
+///
+/// If `text` is longer than that, the result starts at a line boundary if possible.
+fn tail(text: &str, max_len: usize) -> &str {
+    if text.len() <= max_len {
+        return text;
+    }
+    let mut start = text.len() - max_len;
+    while !text.is_char_boundary(start) {
+        start += 1;
+    }
+    let tail = &text[start..];
+    match tail.split_once('\n') {
+        Some((_, rest)) if !rest.is_empty() => rest,
+        _ => tail,
+    }
+}
+
+impl HookResult {
+    /// Format this result's failure, if any, as an error diagnostic for the error log.
+    pub fn to_diagnostic(&self) -> Option<GhcDiagnostic> {
+        let failure = self.failure.as_ref()?;
+        Some(GhcDiagnostic {
+            severity: Severity::Error,
+            path: None,
+            span: PositionRange::default(),
+            message: format!(
+                "\n    {} command `{}` {failure}\n",
+                self.event, self.command
+            ),
+        })
+    }
 }
 
 impl Extend<GhcMessage> for CompilationLog {
@@ -55,3 +206,66 @@ impl Extend<GhcMessage> for CompilationLog {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_tail() {
+        assert_eq!(tail("short\n", 10), "short\n");
+        assert_eq!(tail("first\nsecond\nthird\n", 15), "second\nthird\n");
+        assert_eq!(tail("one long line", 4), "line");
+        // Don't split a character in half.
+        assert_eq!(tail("λλλ", 3), "λ");
+    }
+
+    #[test]
+    fn test_check_failures() {
+        let mut log = CompilationLog {
+            summary: Some(CompilationSummary {
+                result: CompilationResult::Ok,
+                modules_loaded: 3,
+            }),
+            diagnostics: vec![
+                GhcDiagnostic::example(Severity::Warning),
+                GhcDiagnostic::example(Severity::Warning),
+            ],
+            ..Default::default()
+        };
+        assert_eq!(log.check_failures(false), Vec::<String>::new());
+        assert_eq!(
+            log.check_failures(true),
+            vec!["Compilation produced 2 warnings".to_owned()]
+        );
+
+        log.summary = Some(CompilationSummary {
+            result: CompilationResult::Err,
+            modules_loaded: 2,
+        });
+        log.diagnostics = vec![GhcDiagnostic::example(Severity::Error)];
+        log.tests = Some(TestResults {
+            passed: 2,
+            failed: 1,
+            pending: 0,
+            failures: Vec::new(),
+        });
+        log.hooks = vec![HookResult {
+            event: "test".to_owned(),
+            command: "TestMain.testMain".to_owned(),
+            duration_ms: 12,
+            failure: Some(HookFailure::ExitFailure { code: 1 }),
+            output: "*** Exception: ExitFailure 1\n".to_owned(),
+        }];
+        assert_eq!(
+            log.check_failures(true),
+            vec![
+                "Compilation failed".to_owned(),
+                "Tests failed: 2 passed, 1 failed".to_owned(),
+                "test command `TestMain.testMain` exited with `ExitFailure 1`".to_owned(),
+            ]
+        );
+    }
+}
@@ -0,0 +1,223 @@
+//! A report of which modules take the longest to compile.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::time::Duration;
+
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+use serde::Serialize;
+
+use crate::format_bulleted_list;
+
+/// How many modules to list when logging a [`CompileProfile`].
+const LOGGED_MODULES: usize = 10;
+
+/// The compile times of a module in this session.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleTimes {
+    /// The module's name.
+    pub name: String,
+    /// How long each compilation of the module took, in order.
+    pub durations: Vec<Duration>,
+}
+
+/// A report of the modules compiled in this session, slowest first.
+///
+/// Modules are ordered by their total compile time, so modules which are recompiled on most
+/// reloads rank above modules which are slow but rarely recompiled.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct CompileProfile {
+    pub modules: Vec<ModuleProfile>,
+}
+
+/// A module's entry in a [`CompileProfile`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ModuleProfile {
+    /// The module's name.
+    pub name: String,
+    /// The module's source path, relative to the current directory if possible.
+    pub path: Utf8PathBuf,
+    /// How many times the module was compiled.
+    pub compilations: usize,
+    /// The total time spent compiling the module, in milliseconds.
+    pub total_ms: u64,
+    /// The mean compile time, in milliseconds.
+    pub mean_ms: u64,
+    /// The longest compile time, in milliseconds.
+    pub max_ms: u64,
+    /// The most recent compile time, in milliseconds.
+    pub last_ms: u64,
+    /// The most recent compile time minus the mean of the earlier ones, in milliseconds.
+    ///
+    /// Positive if the module is getting slower to compile. `None` if the module has only been
+    /// compiled once.
+    pub trend_ms: Option<i64>,
+    /// How long each compilation took, in milliseconds, in order.
+    pub durations_ms: Vec<u64>,
+}
+
+impl CompileProfile {
+    /// Construct a report from module compile times keyed by absolute path.
+    pub fn new(times: &BTreeMap<Utf8PathBuf, ModuleTimes>, cwd: &Utf8Path) -> Self {
+        let mut modules = times
+            .iter()
+            .filter_map(|(path, times)| ModuleProfile::new(path, times, cwd))
+            .collect::<Vec<_>>();
+        modules.sort_by(|a, b| b.total_ms.cmp(&a.total_ms).then(a.name.cmp(&b.name)));
+        Self { modules }
+    }
+
+    /// Write the report to the given path as JSON.
+    pub async fn write(&self, path: &Utf8Path) -> miette::Result<()> {
+        let json = serde_json::to_vec_pretty(self).into_diagnostic()?;
+        tokio::fs::write(path, json)
+            .await
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to write compile profile to {path}"))
+    }
+
+    /// Log the slowest modules.
+    pub fn log(&self) {
+        if self.modules.is_empty() {
+            return;
+        }
+        tracing::info!(
+            "Slowest modules to compile:\n{}",
+            format_bulleted_list(self.modules.iter().take(LOGGED_MODULES))
+        );
+    }
+}
+
+impl ModuleProfile {
+    fn new(path: &Utf8Path, times: &ModuleTimes, cwd: &Utf8Path) -> Option<Self> {
+        let durations_ms = times
+            .durations
+            .iter()
+            .map(|duration| duration.as_millis() as u64)
+            .collect::<Vec<_>>();
+        let (&last_ms, earlier) = durations_ms.split_last()?;
+        let total_ms = durations_ms.iter().sum::<u64>();
+        let trend_ms = (!earlier.is_empty()).then(|| {
+            let earlier_mean = earlier.iter().sum::<u64>() / earlier.len() as u64;
+            last_ms as i64 - earlier_mean as i64
+        });
+
+        Some(Self {
+            name: times.name.clone(),
+            path: path.strip_prefix(cwd).unwrap_or(path).to_owned(),
+            compilations: durations_ms.len(),
+            total_ms,
+            mean_ms: total_ms / durations_ms.len() as u64,
+            max_ms: durations_ms.iter().copied().max().unwrap_or_default(),
+            last_ms,
+            trend_ms,
+            durations_ms,
+        })
+    }
+}
+
+impl Display for ModuleProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {:.2?} total over {} {}, last {:.2?}",
+            self.name,
+            Duration::from_millis(self.total_ms),
+            self.compilations,
+            if self.compilations == 1 {
+                "compilation"
+            } else {
+                "compilations"
+            },
+            Duration::from_millis(self.last_ms),
+        )?;
+        if let Some(trend_ms) = self.trend_ms {
+            let sign = if trend_ms < 0 { '-' } else { '+' };
+            write!(
+                f,
+                " ({sign}{:.2?})",
+                Duration::from_millis(trend_ms.unsigned_abs())
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn times(name: &str, millis: &[u64]) -> ModuleTimes {
+        ModuleTimes {
+            name: name.to_owned(),
+            durations: millis.iter().copied().map(Duration::from_millis).collect(),
+        }
+    }
+
+    #[test]
+    fn test_compile_profile() {
+        let profile = CompileProfile::new(
+            &[
+                ("/puppy/src/A.hs".into(), times("A", &[1000, 1200, 2000])),
+                ("/puppy/src/B.hs".into(), times("B", &[5000])),
+                ("/puppy/src/C.hs".into(), times("C", &[])),
+                ("/elsewhere/D.hs".into(), times("D", &[100, 50])),
+            ]
+            .into(),
+            "/puppy".into(),
+        );
+
+        assert_eq!(
+            profile.modules,
+            vec![
+                ModuleProfile {
+                    name: "B".to_owned(),
+                    path: "src/B.hs".into(),
+                    compilations: 1,
+                    total_ms: 5000,
+                    mean_ms: 5000,
+                    max_ms: 5000,
+                    last_ms: 5000,
+                    trend_ms: None,
+                    durations_ms: vec![5000],
+                },
+                ModuleProfile {
+                    name: "A".to_owned(),
+                    path: "src/A.hs".into(),
+                    compilations: 3,
+                    total_ms: 4200,
+                    mean_ms: 1400,
+                    max_ms: 2000,
+                    last_ms: 2000,
+                    trend_ms: Some(900),
+                    durations_ms: vec![1000, 1200, 2000],
+                },
+                ModuleProfile {
+                    name: "D".to_owned(),
+                    path: "/elsewhere/D.hs".into(),
+                    compilations: 2,
+                    total_ms: 150,
+                    mean_ms: 75,
+                    max_ms: 100,
+                    last_ms: 50,
+                    trend_ms: Some(-50),
+                    durations_ms: vec![100, 50],
+                },
+            ]
+        );
+
+        assert_eq!(
+            profile.modules[1].to_string(),
+            "A: 4.20s total over 3 compilations, last 2.00s (+900.00ms)"
+        );
+        assert_eq!(
+            profile.modules[0].to_string(),
+            "B: 5.00s total over 1 compilation, last 5.00s"
+        );
+    }
+}
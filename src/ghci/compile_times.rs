@@ -0,0 +1,470 @@
+//! Per-module compile times, used to estimate how long a compilation will take.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+use tracing::instrument;
+
+use super::compile_profile::CompileProfile;
+use super::compile_profile::ModuleTimes;
+use super::parse::CompilingModule;
+use super::parse::CompilingProgress;
+use super::ReloadProgress;
+use crate::normal_path::NormalPath;
+
+/// How often to log compilation progress, if it's logged at all.
+const PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Get the default path to persist compile times to: `ghciwatch/compile-times.json` in
+/// `$XDG_CACHE_HOME` or `~/.cache`.
+pub fn default_compile_times_path() -> Option<Utf8PathBuf> {
+    let cache_dir = std::env::var_os("XDG_CACHE_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            std::env::var_os("HOME")
+                .filter(|home| !home.is_empty())
+                .map(|home| PathBuf::from(home).join(".cache"))
+        })?;
+    let cache_dir = Utf8PathBuf::try_from(cache_dir).ok()?;
+    Some(cache_dir.join("ghciwatch").join("compile-times.json"))
+}
+
+/// A cheaply-clonable handle for timing module compilation and estimating how much longer a
+/// compilation will take.
+///
+/// Compile times are keyed by each module's absolute source path, so one file can hold the
+/// compile times of several projects. They're written to the file after each compilation.
+#[derive(Debug, Clone, Default)]
+pub struct CompileTimer(Arc<Mutex<CompileTimes>>);
+
+impl CompileTimer {
+    /// Load compile times from the given file, if any.
+    ///
+    /// If `log_progress` is set, compilation progress is periodically logged, e.g. when progress
+    /// isn't displayed in the TUI.
+    pub fn load(path: Option<Utf8PathBuf>, log_progress: bool) -> miette::Result<Self> {
+        let times = match &path {
+            Some(path) => match std::fs::read_to_string(path) {
+                Ok(contents) => parse_compile_times(&contents)
+                    .wrap_err_with(|| format!("Failed to parse compile times from {path}"))
+                    .unwrap_or_else(|err| {
+                        tracing::warn!("{err:?}");
+                        Default::default()
+                    }),
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => Default::default(),
+                Err(err) => {
+                    tracing::warn!("Failed to read compile times from {path}: {err}");
+                    Default::default()
+                }
+            },
+            None => Default::default(),
+        };
+
+        Ok(Self(Arc::new(Mutex::new(CompileTimes {
+            cwd: crate::current_dir_utf8()?,
+            path,
+            log_progress,
+            times,
+            ..Default::default()
+        }))))
+    }
+
+    /// Start timing a compilation.
+    ///
+    /// `expected` is the absolute paths of the modules expected to be compiled. If `None`, every
+    /// module with a known compile time in the current directory is expected.
+    pub fn start(&self, expected: Option<BTreeSet<Utf8PathBuf>>) {
+        self.lock().start(expected, Instant::now());
+    }
+
+    /// Record that a module has started compiling, and estimate how much longer the compilation
+    /// will take.
+    pub fn compiling(
+        &self,
+        progress: CompilingProgress,
+        module: &CompilingModule,
+    ) -> ReloadProgress {
+        let now = Instant::now();
+        let mut times = self.lock();
+        let progress = times.compiling(progress, module, now);
+        if progress.remaining.is_some() && times.should_log(now) {
+            tracing::info!("{progress}");
+        }
+        progress
+    }
+
+    /// Finish timing a compilation and write the compile times to the cache file, if any.
+    #[instrument(skip_all, level = "debug")]
+    pub async fn finish(&self) {
+        let Some((path, unsaved)) = self.lock().finish(Instant::now()) else {
+            return;
+        };
+        if let Err(err) = write_compile_times(&path, unsaved).await {
+            tracing::debug!("{err:?}");
+        }
+    }
+
+    /// Get a report of the modules compiled so far in this session, slowest first.
+    pub fn profile(&self) -> CompileProfile {
+        let times = self.lock();
+        CompileProfile::new(&times.profile, &times.cwd)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, CompileTimes> {
+        self.0.lock().expect("Compile times lock is poisoned")
+    }
+}
+
+#[derive(Debug, Default)]
+struct CompileTimes {
+    /// The directory relative module paths are resolved in.
+    cwd: Utf8PathBuf,
+    /// The file compile times are persisted to.
+    path: Option<Utf8PathBuf>,
+    /// Whether to log progress.
+    log_progress: bool,
+    /// The most recent compile time of each module.
+    times: BTreeMap<Utf8PathBuf, Duration>,
+    /// Compile times which haven't been written to `path` yet.
+    unsaved: BTreeMap<Utf8PathBuf, Duration>,
+    /// The modules expected to be compiled in the current compilation.
+    expected: BTreeSet<Utf8PathBuf>,
+    /// The modules compiled so far in the current compilation.
+    compiled: BTreeSet<Utf8PathBuf>,
+    /// The module being compiled and when it started compiling.
+    current: Option<(Utf8PathBuf, Instant)>,
+    /// When progress was last logged, or when the current compilation started.
+    last_logged: Option<Instant>,
+    /// Every compile time of each module in this session, for [`CompileTimer::profile`].
+    profile: BTreeMap<Utf8PathBuf, ModuleTimes>,
+}
+
+impl CompileTimes {
+    fn start(&mut self, expected: Option<BTreeSet<Utf8PathBuf>>, now: Instant) {
+        self.expected = expected.unwrap_or_else(|| {
+            self.times
+                .keys()
+                .filter(|path| path.starts_with(&self.cwd))
+                .cloned()
+                .collect()
+        });
+        self.compiled.clear();
+        self.current = None;
+        self.last_logged = Some(now);
+    }
+
+    fn compiling(
+        &mut self,
+        progress: CompilingProgress,
+        module: &CompilingModule,
+        now: Instant,
+    ) -> ReloadProgress {
+        self.finish_current(now);
+        let path = NormalPath::new(&module.path, &self.cwd)
+            .map(NormalPath::into_absolute)
+            .unwrap_or_else(|_| self.cwd.join(&module.path));
+        self.profile.entry(path.clone()).or_default().name = module.name.clone();
+        self.current = Some((path, now));
+
+        ReloadProgress {
+            progress,
+            module: module.name.clone(),
+            remaining: self.estimate(progress, now),
+        }
+    }
+
+    /// Record the compile time of the module being compiled, if any.
+    fn finish_current(&mut self, now: Instant) {
+        if let Some((path, start)) = self.current.take() {
+            let duration = now.saturating_duration_since(start);
+            self.times.insert(path.clone(), duration);
+            self.unsaved.insert(path.clone(), duration);
+            self.profile
+                .entry(path.clone())
+                .or_default()
+                .durations
+                .push(duration);
+            self.compiled.insert(path);
+        }
+    }
+
+    /// Finish the current compilation, returning the compile times to persist, if any.
+    fn finish(&mut self, now: Instant) -> Option<(Utf8PathBuf, BTreeMap<Utf8PathBuf, Duration>)> {
+        self.finish_current(now);
+        self.expected.clear();
+        self.compiled.clear();
+        self.last_logged = None;
+        let path = self.path.clone()?;
+        if self.unsaved.is_empty() {
+            return None;
+        }
+        Some((path, std::mem::take(&mut self.unsaved)))
+    }
+
+    /// Estimate how long the rest of the compilation will take.
+    ///
+    /// This sums the compile times of the expected modules which haven't been compiled yet, using
+    /// the average compile time for modules which don't have one yet. Returns `None` if no
+    /// relevant compile times are known.
+    fn estimate(&self, progress: CompilingProgress, now: Instant) -> Option<Duration> {
+        let (current, started) = self.current.as_ref()?;
+        let pending = self
+            .expected
+            .iter()
+            .filter(|path| *path != current && !self.compiled.contains(*path))
+            .map(|path| self.times.get(path).copied())
+            .collect::<Vec<_>>();
+
+        let known = pending
+            .iter()
+            .flatten()
+            .chain(self.compiled.iter().filter_map(|path| self.times.get(path)))
+            .chain(self.times.get(current))
+            .collect::<Vec<_>>();
+        if known.is_empty() {
+            return None;
+        }
+        let average = known.iter().copied().sum::<Duration>() / known.len() as u32;
+
+        // `ghci` tells us how many modules are left, which is more reliable than our guess.
+        let remaining = progress.total.saturating_sub(progress.current);
+        let pending_time = pending
+            .iter()
+            .take(remaining)
+            .map(|time| time.unwrap_or(average))
+            .sum::<Duration>();
+        let unexpected_time = average * remaining.saturating_sub(pending.len()) as u32;
+        let current_time = self
+            .times
+            .get(current)
+            .copied()
+            .unwrap_or(average)
+            .saturating_sub(now.saturating_duration_since(*started));
+
+        Some(pending_time + unexpected_time + current_time)
+    }
+
+    /// Should progress be logged now? Progress is logged at most once per
+    /// [`PROGRESS_LOG_INTERVAL`], so that quick compilations aren't logged at all.
+    fn should_log(&mut self, now: Instant) -> bool {
+        if !self.log_progress {
+            return false;
+        }
+        match self.last_logged {
+            Some(last_logged)
+                if now.saturating_duration_since(last_logged) < PROGRESS_LOG_INTERVAL =>
+            {
+                false
+            }
+            _ => {
+                self.last_logged = Some(now);
+                true
+            }
+        }
+    }
+}
+
+/// Parse compile times from JSON, an object mapping paths to milliseconds.
+fn parse_compile_times(contents: &str) -> miette::Result<BTreeMap<Utf8PathBuf, Duration>> {
+    let millis: BTreeMap<Utf8PathBuf, u64> = serde_json::from_str(contents).into_diagnostic()?;
+    Ok(millis
+        .into_iter()
+        .map(|(path, millis)| (path, Duration::from_millis(millis)))
+        .collect())
+}
+
+/// Merge compile times into the given file.
+///
+/// The file is re-read first so that concurrent `ghciwatch` sessions don't clobber each other's
+/// compile times.
+async fn write_compile_times(
+    path: &Utf8Path,
+    times: BTreeMap<Utf8PathBuf, Duration>,
+) -> miette::Result<()> {
+    let mut millis = match tokio::fs::read_to_string(path).await {
+        Ok(contents) => parse_compile_times(&contents).unwrap_or_default(),
+        Err(_) => Default::default(),
+    }
+    .into_iter()
+    .map(|(path, duration)| (path, duration.as_millis() as u64))
+    .collect::<BTreeMap<_, _>>();
+    millis.extend(
+        times
+            .into_iter()
+            .map(|(path, duration)| (path, duration.as_millis() as u64)),
+    );
+
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to create {parent}"))?;
+    }
+    // Write to a temporary file and rename it so readers never see a partial file.
+    let temp_path = path.with_extension("json.tmp");
+    tokio::fs::write(&temp_path, serde_json::to_vec(&millis).into_diagnostic()?)
+        .await
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to write compile times to {temp_path}"))?;
+    tokio::fs::rename(&temp_path, path)
+        .await
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to write compile times to {path}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn module(name: &str) -> CompilingModule {
+        CompilingModule {
+            name: name.to_owned(),
+            path: format!("src/{name}.hs").into(),
+        }
+    }
+
+    fn progress(current: usize, total: usize) -> CompilingProgress {
+        CompilingProgress { current, total }
+    }
+
+    fn compile_times(times: &[(&str, u64)]) -> CompileTimes {
+        CompileTimes {
+            cwd: "/puppy".into(),
+            path: Some("/cache/compile-times.json".into()),
+            times: times
+                .iter()
+                .map(|(name, secs)| {
+                    (
+                        Utf8PathBuf::from(format!("/puppy/src/{name}.hs")),
+                        Duration::from_secs(*secs),
+                    )
+                })
+                .chain([("/doggy/src/A.hs".into(), Duration::from_secs(100))])
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_estimate() {
+        let start = Instant::now();
+        let mut times = compile_times(&[("A", 2), ("B", 4), ("C", 6)]);
+        times.start(None, start);
+        assert_eq!(times.expected.len(), 3);
+
+        let reload = times.compiling(progress(1, 3), &module("A"), start);
+        assert_eq!(reload.module, "A");
+        assert_eq!(reload.remaining, Some(Duration::from_secs(12)));
+
+        // `A` took 1 second longer than last time.
+        let now = start + Duration::from_secs(3);
+        let reload = times.compiling(progress(2, 3), &module("B"), now);
+        assert_eq!(reload.remaining, Some(Duration::from_secs(10)));
+        assert_eq!(
+            times.times[Utf8Path::new("/puppy/src/A.hs")],
+            Duration::from_secs(3)
+        );
+
+        // An unexpected module takes the average time.
+        let now = start + Duration::from_secs(7);
+        let reload = times.compiling(progress(3, 4), &module("D"), now);
+        assert_eq!(
+            reload.remaining,
+            Some(Duration::from_secs(6) + Duration::from_secs(13) / 3)
+        );
+
+        let now = start + Duration::from_secs(8);
+        let (path, unsaved) = times.finish(now).unwrap();
+        assert_eq!(path, "/cache/compile-times.json");
+        assert_eq!(
+            unsaved.keys().collect::<Vec<_>>(),
+            ["/puppy/src/A.hs", "/puppy/src/B.hs", "/puppy/src/D.hs"]
+        );
+        assert_eq!(
+            times.times[Utf8Path::new("/puppy/src/D.hs")],
+            Duration::from_secs(1)
+        );
+        assert!(times.finish(now).is_none());
+
+        assert_eq!(
+            CompileProfile::new(&times.profile, &times.cwd)
+                .modules
+                .iter()
+                .map(|module| (module.name.as_str(), module.total_ms))
+                .collect::<Vec<_>>(),
+            [("B", 4000), ("A", 3000), ("D", 1000)]
+        );
+    }
+
+    #[test]
+    fn test_estimate_expected() {
+        let start = Instant::now();
+        let mut times = compile_times(&[("A", 2), ("B", 4), ("C", 6)]);
+        times.start(Some(["/puppy/src/C.hs".into()].into()), start);
+
+        let reload = times.compiling(progress(1, 2), &module("B"), start);
+        assert_eq!(reload.remaining, Some(Duration::from_secs(10)));
+
+        // Modules without a compile time have no estimate.
+        let mut times = compile_times(&[]);
+        times.start(None, start);
+        let reload = times.compiling(progress(1, 2), &module("A"), start);
+        assert_eq!(reload.remaining, None);
+    }
+
+    #[test]
+    fn test_should_log() {
+        let start = Instant::now();
+        let mut times = CompileTimes {
+            log_progress: true,
+            ..Default::default()
+        };
+        times.start(None, start);
+        assert!(!times.should_log(start + Duration::from_millis(500)));
+        assert!(times.should_log(start + Duration::from_millis(1500)));
+        assert!(!times.should_log(start + Duration::from_millis(2000)));
+        assert!(times.should_log(start + Duration::from_millis(2500)));
+    }
+
+    #[test]
+    fn test_parse_compile_times() {
+        assert_eq!(
+            parse_compile_times(r#"{"/puppy/src/A.hs": 1500}"#).unwrap(),
+            [("/puppy/src/A.hs".into(), Duration::from_millis(1500))].into()
+        );
+        assert!(parse_compile_times("[]").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_compile_times() {
+        let dir =
+            std::env::temp_dir().join(format!("ghciwatch-compile-times-{}", std::process::id()));
+        let path = Utf8PathBuf::try_from(dir.join("cache").join("compile-times.json")).unwrap();
+
+        write_compile_times(&path, [("/a.hs".into(), Duration::from_millis(10))].into())
+            .await
+            .unwrap();
+        write_compile_times(&path, [("/b.hs".into(), Duration::from_millis(20))].into())
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            r#"{"/a.hs":10,"/b.hs":20}"#
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -1,5 +1,6 @@
 use camino::Utf8PathBuf;
 use miette::IntoDiagnostic;
+use miette::WrapErr;
 use tokio::fs::File;
 use tokio::io::AsyncWriteExt;
 use tokio::io::BufWriter;
@@ -12,19 +13,25 @@ use super::CompilationLog;
 ///
 /// This produces `ghcid`-compatible output, which can be consumed by `ghcid` plugins in your
 /// editor of choice.
+///
+/// A structured JSON version of the log can be written as well, for tools which would rather not
+/// parse GHC's output themselves.
 pub struct ErrorLog {
     path: Option<Utf8PathBuf>,
+    json_path: Option<Utf8PathBuf>,
 }
 
 impl ErrorLog {
-    /// Construct a new error log writer for the given path.
-    pub fn new(path: Option<Utf8PathBuf>) -> Self {
-        Self { path }
+    /// Construct a new error log writer for the given paths.
+    pub fn new(path: Option<Utf8PathBuf>, json_path: Option<Utf8PathBuf>) -> Self {
+        Self { path, json_path }
     }
 
     /// Write the error log, if any, with the given compilation summary and diagnostic messages.
     #[instrument(skip(self, log), name = "error_log_write", level = "debug")]
     pub async fn write(&mut self, log: &CompilationLog) -> miette::Result<()> {
+        self.write_json(log).await?;
+
         let path = match &self.path {
             Some(path) => path,
             None => {
@@ -38,7 +45,9 @@ impl ErrorLog {
 
         if let Some(summary) = log.summary {
             // `ghcid` only writes the headline if there's no errors.
-            if let CompilationResult::Ok = summary.result {
+            if let (CompilationResult::Ok, false) =
+                (summary.result, log.tests_failed() || log.hooks_failed())
+            {
                 tracing::debug!(%path, "Writing 'All good'");
                 let modules_loaded = if summary.modules_loaded != 1 {
                     format!("{} modules", summary.modules_loaded)
@@ -60,10 +69,55 @@ impl ErrorLog {
                 .into_diagnostic()?;
         }
 
+        // Write test failures like compilation errors, so tools treat them the same way.
+        if let Some(tests) = &log.tests {
+            for diagnostic in tests.to_diagnostics() {
+                tracing::debug!(%diagnostic, "Writing test failure");
+                writer
+                    .write_all(diagnostic.to_string().as_bytes())
+                    .await
+                    .into_diagnostic()?;
+            }
+        }
+
+        // Likewise for `ghci` hook commands which threw exceptions.
+        for diagnostic in log.hooks.iter().filter_map(|hook| hook.to_diagnostic()) {
+            tracing::debug!(%diagnostic, "Writing hook failure");
+            writer
+                .write_all(diagnostic.to_string().as_bytes())
+                .await
+                .into_diagnostic()?;
+        }
+
         // This is load-bearing! If we don't properly flush/shutdown the handle, nothing gets
         // written!
         writer.shutdown().await.into_diagnostic()?;
 
         Ok(())
     }
+
+    /// Write the JSON error log, if any.
+    #[instrument(skip_all, level = "debug")]
+    async fn write_json(&mut self, log: &CompilationLog) -> miette::Result<()> {
+        let path = match &self.json_path {
+            Some(path) => path,
+            None => {
+                tracing::debug!("No JSON error log path, not writing");
+                return Ok(());
+            }
+        };
+
+        let mut json = serde_json::to_vec_pretty(log)
+            .into_diagnostic()
+            .wrap_err("Failed to serialize compilation log")?;
+        json.push(b'\n');
+
+        tracing::debug!(%path, diagnostics = log.diagnostics.len(), "Writing JSON error log");
+        tokio::fs::write(path, json)
+            .await
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to write {path}"))?;
+
+        Ok(())
+    }
 }
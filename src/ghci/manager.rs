@@ -1,25 +1,35 @@
 //! Subsystem for [`Ghci`] to support graceful shutdown.
 
 use std::collections::BTreeSet;
+use std::collections::VecDeque;
 use std::sync::Arc;
 
 use miette::miette;
 use miette::Context;
 use miette::IntoDiagnostic;
+use serde::Serialize;
 use tokio::sync::mpsc;
 use tokio::sync::oneshot;
 use tokio::sync::Mutex;
 use tracing::instrument;
 
 use crate::event_filter::FileEvent;
+use crate::format_bulleted_list;
 use crate::ghci::CompilationLog;
 use crate::hooks;
 use crate::hooks::LifecycleEvent;
 use crate::shutdown::ShutdownHandle;
 
 use super::Ghci;
+use super::GhciCommand;
 use super::GhciOpts;
 use super::GhciReloadKind;
+use super::ModuleGraph;
+
+/// A channel to send the result of a [`GhciEvent`] on.
+///
+/// Events from the file watcher don't need a reply, so this is optional.
+pub type GhciReply = Option<mpsc::Sender<miette::Result<GhciResponse>>>;
 
 /// An event sent to [`Ghci`].
 #[derive(Debug, Clone)]
@@ -29,21 +39,70 @@ pub enum GhciEvent {
         /// The file events to respond to.
         events: BTreeSet<FileEvent>,
     },
+    /// Reload every module in the `ghci` session, whether or not it has changed.
+    ReloadAll {
+        /// Where to send the resulting compilation log.
+        reply: GhciReply,
+    },
+    /// Restart the `ghci` session.
+    Restart {
+        /// Where to send the resulting compilation log.
+        reply: GhciReply,
+    },
+    /// Run the test hooks.
+    RunTests {
+        /// Where to send the most recent compilation log.
+        reply: GhciReply,
+    },
+    /// Evaluate a command in the `ghci` session.
+    Eval {
+        /// The command to evaluate.
+        command: GhciCommand,
+        /// Where to send the command's output and diagnostics.
+        reply: GhciReply,
+    },
+    /// Toggle evaluating commands in comments, as with `--enable-eval`.
+    ToggleEval {
+        /// Where to send the most recent compilation log.
+        reply: GhciReply,
+    },
+    /// Reload `ghciwatch`'s configuration file, restarting the `ghci` session if its command
+    /// changed.
+    ReloadConfig,
+    /// Get the most recent compilation log.
+    Status {
+        /// Where to send the most recent compilation log.
+        reply: GhciReply,
+    },
+    /// Get the import graph of the loaded modules.
+    ModuleGraph {
+        /// Where to send the import graph and the most recent compilation log.
+        reply: GhciReply,
+    },
 }
 
-impl GhciEvent {
-    /// When we interrupt an event to reload, add the file events together so that we don't lose
-    /// work.
-    fn merge(&mut self, other: GhciEvent) {
-        match (self, other) {
-            (
-                GhciEvent::Reload { events },
-                GhciEvent::Reload {
-                    events: other_events,
-                },
-            ) => {
-                events.extend(other_events);
-            }
+/// The result of a [`GhciEvent`].
+#[derive(Debug, Clone, Serialize)]
+pub struct GhciResponse {
+    /// The compilation log for the event.
+    ///
+    /// For events which don't compile anything, this is the log from the most recent startup,
+    /// reload, or restart.
+    pub log: CompilationLog,
+    /// Output from an evaluated command.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    /// The import graph of the loaded modules, if requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub module_graph: Option<ModuleGraph>,
+}
+
+impl From<CompilationLog> for GhciResponse {
+    fn from(log: CompilationLog) -> Self {
+        Self {
+            log,
+            output: None,
+            module_graph: None,
         }
     }
 }
@@ -59,6 +118,12 @@ pub async fn run_ghci(
     // is a little different each time, so the `select!`s can't be consolidated.
 
     let no_interrupt_reloads = opts.no_interrupt_reloads;
+    let once = opts.once;
+    let fail_on_warnings = opts.fail_on_warnings;
+    let compile_profile = opts
+        .compile_profile_path
+        .is_some()
+        .then(|| opts.compile_timer.clone());
     let mut ghci = Ghci::new(handle.clone(), opts)
         .await
         .wrap_err("Failed to start `ghci`")?;
@@ -68,18 +133,36 @@ pub async fn run_ghci(
     tokio::select! {
         _ = handle.on_shutdown_requested() => {
             ghci.stop().await.wrap_err("Failed to quit ghci")?;
+            if once {
+                // Don't let an interrupted check pass.
+                return Err(miette!("Interrupted before ghci finished loading"));
+            }
         }
         startup_result = ghci.initialize(&mut log, [LifecycleEvent::Startup(hooks::When::After)]) => {
             startup_result?;
         }
     }
 
+    if once {
+        // Without the file watcher, the remaining tasks finish once `ghci` exits.
+        ghci.stop().await.wrap_err("Failed to quit ghci")?;
+        if let Some(compile_timer) = compile_profile {
+            compile_timer.profile().log();
+        }
+        let failures = log.check_failures(fail_on_warnings);
+        if failures.is_empty() {
+            tracing::info!("Check passed");
+            return Ok(());
+        }
+        return Err(miette!("Check failed:\n{}", format_bulleted_list(failures)));
+    }
+
     let ghci = Arc::new(Mutex::new(ghci));
-    // The event to respond to. If we interrupt a reload, we may begin the loop with `Some(_)` in
-    // here.
-    let mut maybe_event = None;
-    loop {
-        let mut event = match maybe_event.take() {
+    // Events to respond to. If we interrupt a reload, or if an event arrives from the control
+    // socket while we're busy, we may begin the loop with events in here.
+    let mut queue = VecDeque::new();
+    'events: loop {
+        let mut event = match queue.pop_front() {
             Some(event) => event,
             None => {
                 // If we don't already have an event to respond to, wait for filesystem events.
@@ -92,7 +175,7 @@ pub async fn run_ghci(
                         ret.ok_or_else(|| miette!("ghci event channel closed"))?
                     }
                 };
-                tracing::debug!(?event, "Received ghci event from watcher");
+                tracing::debug!(?event, "Received ghci event");
                 event
             }
         };
@@ -107,37 +190,76 @@ pub async fn run_ghci(
             event.clone(),
             reload_sender,
         )));
-        tokio::select! {
-            _ = handle.on_shutdown_requested() => {
-                // Cancel any in-progress reloads. This releases the lock so we don't block here.
-                task.abort();
-                ghci.lock().await.stop().await.wrap_err("Failed to quit ghci")?;
-                break;
-            }
-            Some(new_event) = receiver.recv() => {
-                tracing::debug!(?new_event, "Received ghci event from watcher while reloading");
-                if !no_interrupt_reloads && should_interrupt(reload_receiver).await {
-                    // Merge the events together so we don't lose progress.
-                    // Then, the next iteration of the loop will pick up the `maybe_event` value
-                    // and respond immediately.
-                    event.merge(new_event);
-                    maybe_event = Some(event);
-
-                    // Cancel the in-progress reload. This releases the `ghci` lock to prevent a deadlock.
+        // Wait for the task to finish, queueing any events which arrive in the meantime. We don't
+        // start the next event until this one is done, so events are handled in order and errors
+        // aren't lost.
+        let mut reload_receiver = Some(reload_receiver);
+        loop {
+            tokio::select! {
+                _ = handle.on_shutdown_requested() => {
+                    // Cancel any in-progress reloads. This releases the lock so we don't block here.
                     task.abort();
+                    ghci.lock().await.stop().await.wrap_err("Failed to quit ghci")?;
+                    break 'events;
+                }
+                Some(new_event) = receiver.recv() => {
+                    tracing::debug!(?new_event, "Received ghci event while reloading");
+                    match (&mut event, new_event) {
+                        (
+                            GhciEvent::Reload { events },
+                            GhciEvent::Reload { events: new_events },
+                        ) => {
+                            // We only decide whether to interrupt once per reload.
+                            let interrupt = match reload_receiver.take() {
+                                Some(reload_receiver) => {
+                                    !no_interrupt_reloads && should_interrupt(reload_receiver).await
+                                }
+                                None => false,
+                            };
+                            if interrupt {
+                                // Merge the events together so we don't lose progress.
+                                // Then, the next iteration of the loop will pick up the queued
+                                // event and respond immediately.
+                                events.extend(new_events);
+                                queue.push_front(event);
+
+                                // Cancel the in-progress reload. This releases the `ghci` lock to
+                                // prevent a deadlock.
+                                task.abort();
 
-                    // Send a SIGINT to interrupt the reload.
-                    // NB: This may take a couple seconds to register.
-                    ghci.lock().await.send_sigint().await?;
+                                // Send a SIGINT to interrupt the reload.
+                                // NB: This may take a couple seconds to register.
+                                ghci.lock().await.send_sigint().await?;
+                                break;
+                            }
+
+                            // Reload again once this reload finishes, in case it missed these
+                            // changes.
+                            match queue.back_mut() {
+                                Some(GhciEvent::Reload { events }) => events.extend(new_events),
+                                _ => queue.push_back(GhciEvent::Reload { events: new_events }),
+                            }
+                        }
+                        (_, new_event) => {
+                            // Other events are requested explicitly, so we never drop them or use
+                            // them to interrupt a reload. They'll run once this event is done.
+                            queue.push_back(new_event);
+                        }
+                    }
+                }
+                ret = &mut task => {
+                    ret.into_diagnostic()??;
+                    tracing::debug!("Finished dispatching ghci event");
+                    break;
                 }
-            }
-            ret = &mut task => {
-                ret.into_diagnostic()??;
-                tracing::debug!("Finished dispatching ghci event");
             }
         }
     }
 
+    if let Some(compile_timer) = compile_profile {
+        compile_timer.profile().log();
+    }
+
     Ok(())
 }
 
@@ -151,10 +273,89 @@ async fn dispatch(
         GhciEvent::Reload { events } => {
             ghci.lock().await.reload(events, reload_sender).await?;
         }
+        GhciEvent::ReloadAll { reply } => {
+            let mut ghci = ghci.lock().await;
+            let result = ghci.reload_all().await;
+            send_reply(reply, result.map(|()| ghci.last_log.clone().into())).await?;
+        }
+        GhciEvent::Restart { reply } => {
+            let mut ghci = ghci.lock().await;
+            tracing::info!("Restarting ghci");
+            // No paths triggered this restart.
+            ghci.hook_env.start(Vec::new(), Vec::new(), Vec::new());
+            ghci.opts.clear();
+            let result = ghci.restart(Vec::new()).await;
+            send_reply(reply, result.map(|()| ghci.last_log.clone().into())).await?;
+        }
+        GhciEvent::RunTests { reply } => {
+            let mut ghci = ghci.lock().await;
+            // Keep the diagnostics from the last compilation, so the error log still includes them,
+            // but not its test and hook results, which this run replaces.
+            let mut log = CompilationLog {
+                tests: None,
+                hooks: Vec::new(),
+                ..ghci.last_log.clone()
+            };
+            ghci.hook_env.start(Vec::new(), Vec::new(), Vec::new());
+            let result = ghci.test(&mut log).await;
+            send_reply(reply, result.map(|()| log.into())).await?;
+        }
+        GhciEvent::Eval { command, reply } => {
+            let result = ghci.lock().await.eval_command(&command).await;
+            send_reply(
+                reply,
+                result.map(|(output, log)| GhciResponse {
+                    output: Some(output),
+                    ..log.into()
+                }),
+            )
+            .await?;
+        }
+        GhciEvent::ToggleEval { reply } => {
+            let mut ghci = ghci.lock().await;
+            let result = ghci.toggle_eval().await;
+            send_reply(reply, result.map(|()| ghci.last_log.clone().into())).await?;
+        }
+        GhciEvent::ReloadConfig => {
+            ghci.lock().await.reload_config().await?;
+        }
+        GhciEvent::Status { reply } => {
+            let log = ghci.lock().await.last_log.clone();
+            send_reply(reply, Ok(log.into())).await?;
+        }
+        GhciEvent::ModuleGraph { reply } => {
+            let ghci = ghci.lock().await;
+            let response = GhciResponse {
+                module_graph: Some(ghci.module_graph.clone()),
+                ..ghci.last_log.clone().into()
+            };
+            send_reply(reply, Ok(response)).await?;
+        }
     }
     Ok(())
 }
 
+/// Send the result of an event to its `reply` channel, if any.
+///
+/// Errors are sent to the channel _and_ returned; if `ghci` fails, we can't keep using it.
+async fn send_reply(reply: GhciReply, result: miette::Result<GhciResponse>) -> miette::Result<()> {
+    match result {
+        Ok(response) => {
+            if let Some(reply) = reply {
+                // It's fine if the requester is gone.
+                let _ = reply.send(Ok(response)).await;
+            }
+            Ok(())
+        }
+        Err(err) => {
+            if let Some(reply) = reply {
+                let _ = reply.send(Err(miette!("{err}"))).await;
+            }
+            Err(err)
+        }
+    }
+}
+
 /// Should we interrupt a reload with a new event?
 #[instrument(level = "debug", skip_all)]
 async fn should_interrupt(reload_receiver: oneshot::Receiver<GhciReloadKind>) -> bool {
@@ -13,10 +13,16 @@ use std::fmt::Debug;
 use std::path::Path;
 use std::process::ExitStatus;
 use std::process::Stdio;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::time::Instant;
+use std::time::SystemTime;
 use tokio::io::DuplexStream;
+use tokio::sync::broadcast;
 use tokio::sync::oneshot;
+use tokio::sync::watch;
 use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 
 use aho_corasick::AhoCorasick;
 use camino::Utf8Path;
@@ -34,6 +40,7 @@ mod stdin;
 use stdin::GhciStdin;
 
 mod stdout;
+use stdout::GhciOutput;
 use stdout::GhciStdout;
 
 mod stderr;
@@ -48,7 +55,9 @@ mod error_log;
 use error_log::ErrorLog;
 
 pub mod parse;
+use parse::parse_compiling_line;
 use parse::parse_eval_commands;
+use parse::parse_module_imports;
 use parse::CompilationResult;
 use parse::EvalCommand;
 use parse::ShowPaths;
@@ -59,14 +68,35 @@ pub use ghci_command::GhciCommand;
 mod compilation_log;
 pub use compilation_log::CompilationLog;
 
+mod compile_profile;
+
+mod compile_times;
+use compile_times::default_compile_times_path;
+pub use compile_times::CompileTimer;
+
 mod writer;
+use crate::buffers::COMPILATION_LOG_CHANNEL_CAPACITY;
 use crate::buffers::GHCI_BUFFER_CAPACITY;
 pub use crate::ghci::writer::GhciWriter;
+pub use module_graph::ModuleGraph;
 
 mod module_set;
 pub use module_set::ModuleSet;
 
+mod reload_record;
+pub use reload_record::ReloadRecord;
+
+mod test_targets;
+pub use test_targets::TestTargets;
+
+mod status;
+pub use status::GhciPhase;
+pub use status::GhciStatus;
+pub use status::ReloadProgress;
+pub use status::StatusSender;
+
 mod loaded_module;
+mod module_graph;
 use loaded_module::LoadedModule;
 
 use crate::aho_corasick::AhoCorasickExt;
@@ -77,11 +107,14 @@ use crate::event_filter::FileEvent;
 use crate::format_bulleted_list;
 use crate::haskell_source_file::is_haskell_source_file;
 use crate::hooks;
+use crate::hooks::HookEnv;
 use crate::hooks::HookOpts;
 use crate::hooks::LifecycleEvent;
 use crate::ignore::GlobMatcher;
 use crate::incremental_reader::IncrementalReader;
 use crate::normal_path::NormalPath;
+use crate::notify::Notification;
+use crate::notify::Notifier;
 use crate::shutdown::ShutdownHandle;
 use crate::CommandExt;
 use crate::StringCase;
@@ -98,26 +131,61 @@ pub const PROMPT: &str = "###~GHCIWATCH-PROMPT~###";
 /// requires that the task is fully owned.
 #[derive(Debug, Clone)]
 pub struct GhciOpts {
+    /// The name of this session, if several sessions are running; see [`Opts::sessions`].
+    pub session: Option<String>,
     /// The command used to start the underlying `ghci` session.
     pub command: ClonableCommand,
     /// A path to write `ghci` errors to.
     pub error_path: Option<Utf8PathBuf>,
+    /// A path to write `ghci` errors to as JSON.
+    pub error_json_path: Option<Utf8PathBuf>,
+    /// A path to write a report of the slowest modules to compile to.
+    pub compile_profile_path: Option<Utf8PathBuf>,
     /// Enable running eval commands in files.
     pub enable_eval: bool,
     /// Lifecycle hooks, mostly `ghci` commands to run at certain points.
     pub hooks: HookOpts,
+    /// If set, reloads only run the tests affected by the changed modules.
+    pub test_targets: Option<TestTargets>,
+    /// Sends notifications when compilation starts failing or is fixed.
+    pub notifier: Notifier,
     /// Restart the `ghci` session when paths matching these globs are changed.
     pub restart_globs: GlobMatcher,
     /// Reload the `ghci` session when paths matching these globs are changed.
     pub reload_globs: GlobMatcher,
+    /// The restart and reload globs together, shared with the file watcher so changes to the
+    /// globs in the configuration file change which paths are sent to this session.
+    pub watched_globs: Arc<Mutex<GlobMatcher>>,
     /// Determines whether we should interrupt a reload in progress or not.
     pub no_interrupt_reloads: bool,
+    /// Exit after the session loads and its hooks run, rather than waiting for changes; see
+    /// [`Opts::once`].
+    pub once: bool,
+    /// With `once`, treat compilation warnings as failures.
+    pub fail_on_warnings: bool,
     /// Where to write what `ghci` emits to `stdout`. Inherits parent's `stdout` by default.
     pub stdout_writer: GhciWriter,
     /// Where to write what `ghci` emits to `stderr`. Inherits parent's `stderr` by default.
     pub stderr_writer: GhciWriter,
     /// Whether to clear the screen before reloads and restarts.
     pub clear: bool,
+    /// Sender for compilation logs, published after each startup, reload, or restart.
+    ///
+    /// Use [`broadcast::Sender::subscribe`] to listen for compilation results, e.g. to publish
+    /// diagnostics to an editor.
+    pub log_sender: broadcast::Sender<CompilationLog>,
+    /// Sender for a [`ReloadRecord`] after each startup, reload, or restart.
+    ///
+    /// This carries the same compilation log as `log_sender`, along with the changed files and
+    /// timing, e.g. for browsing reload history in the TUI.
+    pub history_sender: broadcast::Sender<ReloadRecord>,
+    /// Sender for the session's status, updated as it starts, compiles, and runs tests.
+    pub status_sender: StatusSender,
+    /// Times module compilation to estimate how long compilations will take.
+    pub compile_timer: CompileTimer,
+    /// If set, the session's module import search paths are sent here after it starts up or
+    /// restarts, e.g. so the file watcher can watch them.
+    pub search_paths_sender: Option<Arc<watch::Sender<Vec<Utf8PathBuf>>>>,
 }
 
 impl GhciOpts {
@@ -129,17 +197,10 @@ impl GhciOpts {
     /// If running in TUI mode, `ghci` output (from `stdout_writer` and `stderr_writer`) is sent to
     /// the stream given by the second return value.
     pub fn from_cli(opts: &Opts) -> miette::Result<(Self, Option<DuplexStream>)> {
-        // TODO: implement fancier default command
-        // See: https://github.com/ndmitchell/ghcid/blob/e2852979aa644c8fed92d46ab529d2c6c1c62b59/src/Ghcid.hs#L142-L171
-        let command = match (&opts.file, &opts.command) {
-            (Some(file), None) => ClonableCommand::new("ghci").arg(file.relative()),
-            (None, Some(command)) => command.clone(),
-            (None, None) => ClonableCommand::new("cabal").arg("repl"),
-            (Some(_), Some(_)) => unreachable!(),
-        };
+        let command = Self::command_from_cli(opts);
 
-        let stdout_writer;
-        let stderr_writer;
+        let mut stdout_writer;
+        let mut stderr_writer;
         let tui_reader;
 
         if opts.tui {
@@ -148,29 +209,140 @@ impl GhciOpts {
             stdout_writer = tui_writer.clone();
             stderr_writer = tui_writer.clone();
             tui_reader = Some(tui_reader_inner);
+        } else if opts.lsp {
+            // `stdout` is reserved for the language server protocol.
+            stdout_writer = GhciWriter::stderr();
+            stderr_writer = GhciWriter::stderr();
+            tui_reader = None;
         } else {
             stdout_writer = GhciWriter::stdout();
             stderr_writer = GhciWriter::stderr();
             tui_reader = None;
         }
 
+        let session = opts.session.clone();
+        if let Some(session) = session.as_ref().filter(|_| !opts.tui) {
+            // Label output, since several sessions are writing to the same place.
+            stdout_writer = stdout_writer.with_prefix(format!("[{session}] "));
+            stderr_writer = stderr_writer.with_prefix(format!("[{session}] "));
+        }
+
+        let (log_sender, _) = broadcast::channel(COMPILATION_LOG_CHANNEL_CAPACITY);
+        let (history_sender, _) = broadcast::channel(COMPILATION_LOG_CHANNEL_CAPACITY);
+
         Ok((
             Self {
+                session,
                 command,
                 error_path: opts.error_file.clone(),
+                error_json_path: opts.error_file_json.clone(),
+                compile_profile_path: opts.compile_profile.clone(),
                 enable_eval: opts.enable_eval,
                 hooks: opts.hooks.clone(),
+                test_targets: TestTargets::from_cli(&opts.tests)?,
+                notifier: Notifier::from_cli(&opts.notify),
                 restart_globs: opts.watch.restart_globs()?,
                 reload_globs: opts.watch.reload_globs()?,
+                watched_globs: Arc::new(Mutex::new(opts.watch.watched_globs()?)),
                 no_interrupt_reloads: opts.no_interrupt_reloads,
+                once: opts.once,
+                fail_on_warnings: opts.fail_on_warnings,
                 stdout_writer,
                 stderr_writer,
                 clear: opts.clear,
+                log_sender,
+                history_sender,
+                status_sender: Default::default(),
+                compile_timer: CompileTimer::load(
+                    opts.compile_times_file
+                        .clone()
+                        .or_else(default_compile_times_path),
+                    // The TUI shows progress in its status bar.
+                    !opts.tui,
+                )?,
+                search_paths_sender: opts
+                    .watch
+                    .watch_search_paths
+                    .then(|| Arc::new(watch::channel(Vec::new()).0)),
             },
             tui_reader,
         ))
     }
 
+    /// Update these options in place from reloaded command-line interface arguments, e.g. after
+    /// the configuration file changes.
+    ///
+    /// This updates the command, globs, hooks, and eval and test options. Options which are only
+    /// read at startup (like the output writers and the error log paths) are left unchanged.
+    ///
+    /// If an error is returned, the options are unchanged.
+    pub fn update_from_cli(&mut self, opts: &Opts) -> miette::Result<()> {
+        let restart_globs = opts.watch.restart_globs()?;
+        let reload_globs = opts.watch.reload_globs()?;
+        let watched_globs = opts.watch.watched_globs()?;
+        let test_targets = TestTargets::from_cli(&opts.tests)?;
+
+        self.command = Self::command_from_cli(opts);
+        self.enable_eval = opts.enable_eval;
+        self.hooks = opts.hooks.clone();
+        self.test_targets = test_targets;
+        self.notifier = Notifier::from_cli(&opts.notify);
+        self.restart_globs = restart_globs;
+        self.reload_globs = reload_globs;
+        *self
+            .watched_globs
+            .lock()
+            .expect("Watched globs lock was poisoned") = watched_globs;
+        self.clear = opts.clear;
+
+        Ok(())
+    }
+
+    /// Label the error log paths which are shared by several sessions with each session's name,
+    /// so the sessions don't overwrite each other's errors.
+    ///
+    /// For example, `ghcid.txt` becomes `ghcid.lib.txt` for the `lib` session.
+    pub fn label_shared_paths(sessions: &mut [Self]) {
+        Self::label_shared_path(sessions, |opts| &mut opts.error_path);
+        Self::label_shared_path(sessions, |opts| &mut opts.error_json_path);
+        Self::label_shared_path(sessions, |opts| &mut opts.compile_profile_path);
+    }
+
+    fn label_shared_path(
+        sessions: &mut [Self],
+        field: impl Fn(&mut Self) -> &mut Option<Utf8PathBuf>,
+    ) {
+        let mut counts = BTreeMap::<Utf8PathBuf, usize>::new();
+        for opts in sessions.iter_mut() {
+            if let Some(path) = field(opts).clone() {
+                *counts.entry(path).or_default() += 1;
+            }
+        }
+
+        for opts in sessions.iter_mut() {
+            let Some(session) = opts.session.clone() else {
+                continue;
+            };
+            if let Some(path) = field(opts) {
+                if counts.get(path).copied().unwrap_or_default() > 1 {
+                    *path = labelled_path(path, &session);
+                }
+            }
+        }
+    }
+
+    fn command_from_cli(opts: &Opts) -> ClonableCommand {
+        match (&opts.file, &opts.command) {
+            (Some(file), None) => ClonableCommand::new("ghci").arg(file.relative()),
+            (None, Some(command)) => command.clone(),
+            (None, None) => match &opts.project {
+                Some(project) => project.command(),
+                None => ClonableCommand::new("cabal").arg("repl"),
+            },
+            (Some(_), Some(_)) => unreachable!(),
+        }
+    }
+
     #[instrument(skip_all, level = "trace")]
     fn clear(&self) {
         if self.clear {
@@ -182,6 +354,14 @@ impl GhciOpts {
     }
 }
 
+/// Add a label to a path before its extension, like `ghcid.lib.txt`.
+fn labelled_path(path: &Utf8Path, label: &str) -> Utf8PathBuf {
+    match path.extension() {
+        Some(extension) => path.with_extension(format!("{label}.{extension}")),
+        None => path.with_extension(label),
+    }
+}
+
 /// A `ghci` session.
 pub struct Ghci {
     /// Options used to start this `ghci` session. We keep this around so we can reuse it when
@@ -218,6 +398,21 @@ pub struct Ghci {
     search_paths: ShowPaths,
     /// Tasks running `async:` shell commands in the background.
     command_handles: Vec<JoinHandle<miette::Result<ExitStatus>>>,
+    /// The compilation log from the most recent startup, reload, or restart.
+    last_log: CompilationLog,
+    /// The file changes which triggered the compilation in progress, for its [`ReloadRecord`].
+    pending_changes: Vec<FileEvent>,
+    /// The modules added or reloaded by the compilation in progress, used to select tests when
+    /// `opts.test_targets` is set.
+    ///
+    /// `None` if the full test suite should be run, e.g. after a restart.
+    changed_modules: Option<Vec<NormalPath>>,
+    /// The import graph of the loaded modules.
+    module_graph: ModuleGraph,
+    /// Context about the compilation in progress, for shell hooks.
+    hook_env: HookEnv,
+    /// Cancelled when the next reload or restart starts, to kill `cancel-on-reload` shell hooks.
+    hook_cancellation: CancellationToken,
 }
 
 impl Debug for Ghci {
@@ -233,16 +428,37 @@ impl Ghci {
     ///
     /// This starts a number of asynchronous tasks to manage the `ghci` session's input and output
     /// streams.
+    pub async fn new(shutdown: ShutdownHandle, opts: GhciOpts) -> miette::Result<Self> {
+        let hook_env = HookEnv {
+            session: opts.session.clone(),
+            error_file: opts.error_path.clone(),
+            error_file_json: opts.error_json_path.clone(),
+            ..Default::default()
+        };
+        Self::new_with_hook_env(shutdown, opts, hook_env).await
+    }
+
+    /// Start a new `ghci` session, running the startup hooks with the given context, e.g. the
+    /// paths which triggered a restart.
     #[instrument(skip_all, level = "debug", name = "ghci")]
-    pub async fn new(mut shutdown: ShutdownHandle, opts: GhciOpts) -> miette::Result<Self> {
+    async fn new_with_hook_env(
+        mut shutdown: ShutdownHandle,
+        opts: GhciOpts,
+        hook_env: HookEnv,
+    ) -> miette::Result<Self> {
+        opts.status_sender.set_phase(GhciPhase::Starting);
+
         let mut command_handles = Vec::new();
+        let hook_cancellation = CancellationToken::new();
         {
             let span = tracing::debug_span!("before_startup_shell");
             let _enter = span.enter();
             opts.hooks
                 .run_shell_hooks(
                     LifecycleEvent::Startup(hooks::When::Before),
+                    &hook_env,
                     &mut command_handles,
+                    &hook_cancellation,
                 )
                 .await?;
         }
@@ -288,7 +504,19 @@ impl Ghci {
         let (stderr_sender, stderr_receiver) = mpsc::channel(8);
 
         let stdout = GhciStdout {
-            reader: IncrementalReader::new(stdout).with_writer(opts.stdout_writer.clone()),
+            reader: IncrementalReader::new(stdout)
+                .with_writer(opts.stdout_writer.clone())
+                .with_line_callback({
+                    let status_sender = opts.status_sender.clone();
+                    let compile_timer = opts.compile_timer.clone();
+                    Box::new(move |line| {
+                        if let Some((progress, module)) = parse_compiling_line(line) {
+                            status_sender.set_phase(GhciPhase::Compiling(
+                                compile_timer.compiling(progress, &module),
+                            ));
+                        }
+                    })
+                }),
             stderr_sender: stderr_sender.clone(),
             buffer: vec![0; LINE_BUFFER_CAPACITY],
             prompt_patterns: AhoCorasick::from_anchored_patterns([PROMPT]),
@@ -325,7 +553,7 @@ impl Ghci {
             })
             .await;
 
-        let error_log = ErrorLog::new(opts.error_path.clone());
+        let error_log = ErrorLog::new(opts.error_path.clone(), opts.error_json_path.clone());
 
         Ok(Ghci {
             opts,
@@ -342,6 +570,12 @@ impl Ghci {
                 search_paths: Default::default(),
             },
             command_handles,
+            last_log: Default::default(),
+            pending_changes: Default::default(),
+            changed_modules: None,
+            module_graph: Default::default(),
+            hook_env,
+            hook_cancellation,
         })
     }
 
@@ -355,6 +589,7 @@ impl Ghci {
         events: [LifecycleEvent; N],
     ) -> miette::Result<()> {
         let start_instant = Instant::now();
+        self.opts.compile_timer.start(None);
 
         // Wait for the stdout job to start up.
         self.stdout.initialize(log).await?;
@@ -364,6 +599,7 @@ impl Ghci {
 
         // Get the initial list of targets.
         self.refresh_targets().await?;
+        self.refresh_module_graph().await;
         // Get the initial list of eval commands.
         self.refresh_eval_commands().await?;
 
@@ -463,8 +699,28 @@ impl Ghci {
         kind_sender: oneshot::Sender<GhciReloadKind>,
     ) -> miette::Result<()> {
         let start_instant = Instant::now();
+        self.cancel_hooks();
+        self.pending_changes = events.iter().cloned().collect();
         let actions = self.get_reload_actions(events).await?;
         let _ = kind_sender.send(actions.kind());
+        self.hook_env.start(
+            actions
+                .needs_reload
+                .iter()
+                .chain(&actions.needs_restart)
+                .map(|path| path.relative().to_owned())
+                .collect(),
+            actions
+                .needs_add
+                .iter()
+                .map(|path| path.relative().to_owned())
+                .collect(),
+            actions
+                .needs_remove
+                .iter()
+                .map(|path| path.relative().to_owned())
+                .collect(),
+        );
 
         if actions.needs_restart() {
             self.opts.clear();
@@ -472,7 +728,8 @@ impl Ghci {
                 "Restarting ghci:\n{}",
                 format_bulleted_list(&actions.needs_restart)
             );
-            self.restart().await?;
+            let changes = std::mem::take(&mut self.pending_changes);
+            self.restart(changes).await?;
             // Once we restart, everything is freshly loaded. We don't need to add or
             // reload any other modules.
             return Ok(());
@@ -482,6 +739,35 @@ impl Ghci {
 
         if actions.needs_modify() {
             self.opts.clear();
+            self.opts.status_sender.set_phase(GhciPhase::Reloading);
+
+            let changed = actions
+                .needs_reload
+                .iter()
+                .chain(&actions.needs_add)
+                .filter(|path| is_haskell_source_file(path))
+                .cloned()
+                .collect::<Vec<_>>();
+            for path in &actions.needs_remove {
+                self.module_graph.remove(path);
+            }
+            self.update_module_graph(&changed).await;
+            log.affected_modules = self.module_graph.dependents(&changed);
+            if !log.affected_modules.is_empty() {
+                tracing::info!(
+                    "Expecting {} to recompile: {}",
+                    if log.affected_modules.len() == 1 {
+                        "1 module".to_owned()
+                    } else {
+                        format!("{} modules", log.affected_modules.len())
+                    },
+                    log.affected_modules.join(", ")
+                );
+            }
+            self.opts
+                .compile_timer
+                .start(Some(self.module_graph.dependent_paths(&changed)));
+
             self.run_hooks(LifecycleEvent::Reload(hooks::When::Before), &mut log)
                 .await?;
         }
@@ -513,6 +799,14 @@ impl Ghci {
         }
 
         if actions.needs_modify() {
+            self.changed_modules = Some(
+                actions
+                    .needs_reload
+                    .iter()
+                    .chain(&actions.needs_add)
+                    .cloned()
+                    .collect(),
+            );
             self.finish_compilation(
                 start_instant,
                 &mut log,
@@ -526,16 +820,59 @@ impl Ghci {
         Ok(())
     }
 
+    /// `:reload` every module in this `ghci` session, whether or not it has changed.
+    #[instrument(skip_all, level = "debug")]
+    async fn reload_all(&mut self) -> miette::Result<()> {
+        let start_instant = Instant::now();
+        self.cancel_hooks();
+        let mut log = CompilationLog::default();
+        self.pending_changes.clear();
+        self.changed_modules = None;
+        self.hook_env.start(Vec::new(), Vec::new(), Vec::new());
+
+        self.opts.clear();
+        self.opts.status_sender.set_phase(GhciPhase::Reloading);
+        self.opts.compile_timer.start(None);
+        self.run_hooks(LifecycleEvent::Reload(hooks::When::Before), &mut log)
+            .await?;
+        tracing::info!("Reloading ghci");
+        self.stdin.reload(&mut self.stdout, &mut log).await?;
+        self.refresh_eval_commands().await?;
+        self.finish_compilation(
+            start_instant,
+            &mut log,
+            [LifecycleEvent::Reload(hooks::When::After)],
+        )
+        .await?;
+
+        self.prune_command_handles();
+
+        Ok(())
+    }
+
     /// Restart the `ghci` session.
+    ///
+    /// `changes` are the file changes which triggered the restart, if any.
     #[instrument(skip_all, level = "debug")]
-    async fn restart(&mut self) -> miette::Result<()> {
+    async fn restart(&mut self, changes: Vec<FileEvent>) -> miette::Result<()> {
+        self.cancel_hooks();
         let mut log = CompilationLog::default();
 
         self.run_hooks(LifecycleEvent::Restart(hooks::When::Before), &mut log)
             .await?;
         self.stop().await?;
-        let new = Self::new(self.shutdown.clone(), self.opts.clone()).await?;
+        // Keep the paths which triggered the restart, for hooks.
+        let new = Self::new_with_hook_env(
+            self.shutdown.clone(),
+            self.opts.clone(),
+            self.hook_env.clone(),
+        )
+        .await?;
+        let last_log = std::mem::take(&mut self.last_log);
         let _ = std::mem::replace(self, new);
+        // Keep the last result, so we can tell if the restart fixed or broke compilation.
+        self.last_log = last_log;
+        self.pending_changes = changes;
         self.initialize(
             &mut log,
             [
@@ -551,10 +888,128 @@ impl Ghci {
     /// Run the user provided test command.
     #[instrument(skip_all, level = "debug")]
     async fn test(&mut self, log: &mut CompilationLog) -> miette::Result<()> {
+        self.opts.status_sender.set_phase(GhciPhase::RunningTests);
         self.run_hooks(LifecycleEvent::Test, log).await?;
+        self.finish_tests(log).await?;
+        self.opts.status_sender.set_phase(GhciPhase::Idle);
         Ok(())
     }
 
+    /// Run the tests affected by the given changed modules and the modules which import them,
+    /// using `opts.test_targets`.
+    #[instrument(skip_all, level = "debug")]
+    async fn test_changed(
+        &mut self,
+        changed: &[NormalPath],
+        log: &mut CompilationLog,
+    ) -> miette::Result<()> {
+        let Some(test_targets) = self.opts.test_targets.clone() else {
+            return Ok(());
+        };
+
+        let loaded = self
+            .targets
+            .iter()
+            .filter_map(|module| self.search_paths.path_to_module(module.path()).ok())
+            .collect();
+        // Modules which import the changed modules are recompiled too, so their tests are
+        // affected as well.
+        let changed = changed
+            .iter()
+            .filter_map(|path| self.search_paths.path_to_module(path).ok())
+            .chain(self.module_graph.dependents(changed))
+            .collect::<BTreeSet<_>>();
+        let test_modules = test_targets.affected(changed.iter().map(String::as_str), &loaded);
+
+        if test_modules.is_empty() {
+            tracing::info!("No test modules affected by changes, skipping tests");
+            return Ok(());
+        }
+
+        self.opts.status_sender.set_phase(GhciPhase::RunningTests);
+        let start_time = Instant::now();
+        tracing::info!("Running tests in:\n{}", format_bulleted_list(&test_modules));
+        for test_module in &test_modules {
+            let command = test_targets.command(test_module);
+            tracing::info!(%command, "Running tests in {test_module}");
+            let command_start = Instant::now();
+            let output = self
+                .stdin
+                .run_command(&mut self.stdout, &command, log)
+                .await?;
+            log.record_test_output(&output.stdout);
+            let result = log.record_hook(
+                LifecycleEvent::Test,
+                &command,
+                command_start.elapsed(),
+                &output,
+            );
+            if let Some(failure) = &result.failure {
+                tracing::error!("Tests in {test_module} {failure}");
+            }
+        }
+        tracing::info!("Finished running tests in {:.2?}", start_time.elapsed());
+        self.finish_tests(log).await?;
+        self.opts.status_sender.set_phase(GhciPhase::Idle);
+
+        Ok(())
+    }
+
+    /// Report the test results parsed from the test commands' output, if any, and rewrite the
+    /// error log to include them.
+    #[instrument(skip_all, level = "trace")]
+    async fn finish_tests(&mut self, log: &CompilationLog) -> miette::Result<()> {
+        match &log.tests {
+            Some(tests) if tests.failed > 0 => {
+                tracing::error!(
+                    "Tests failed: {tests}\n{}",
+                    format_bulleted_list(tests.failures.iter().map(|failure| &failure.name))
+                );
+            }
+            Some(tests) => {
+                tracing::info!("Tests passed: {tests}");
+            }
+            None if log.hooks.is_empty() => {
+                return Ok(());
+            }
+            None => {}
+        }
+
+        self.write_error_log(log).await?;
+        self.last_log = log.clone();
+
+        Ok(())
+    }
+
+    /// Record the results of the `ghci` hook commands run after compilation, if any.
+    #[instrument(skip_all, level = "debug")]
+    async fn finish_hooks(&mut self, log: &CompilationLog) -> miette::Result<()> {
+        if log.hooks.is_empty() {
+            return Ok(());
+        }
+
+        // The error log was written before the hooks ran, so we write it again to include their
+        // results.
+        self.write_error_log(log).await?;
+        self.last_log = log.clone();
+
+        Ok(())
+    }
+
+    /// Evaluate a command in the `ghci` session, returning its output and any diagnostics.
+    #[instrument(skip(self), level = "debug")]
+    async fn eval_command(
+        &mut self,
+        command: &GhciCommand,
+    ) -> miette::Result<(String, CompilationLog)> {
+        let mut log = CompilationLog::default();
+        let output = self
+            .stdin
+            .run_command(&mut self.stdout, command, &mut log)
+            .await?;
+        Ok((output.stdout, log))
+    }
+
     /// Run the eval commands, if enabled.
     #[instrument(skip_all, level = "debug")]
     async fn eval(&mut self, log: &mut CompilationLog) -> miette::Result<()> {
@@ -588,6 +1043,52 @@ impl Ghci {
         Ok(())
     }
 
+    /// Toggle evaluating commands in comments.
+    #[instrument(skip_all, level = "debug")]
+    async fn toggle_eval(&mut self) -> miette::Result<()> {
+        self.opts.enable_eval = !self.opts.enable_eval;
+        if self.opts.enable_eval {
+            tracing::info!("Enabled eval commands");
+            self.refresh_eval_commands().await?;
+        } else {
+            tracing::info!("Disabled eval commands");
+            self.eval_commands.clear();
+        }
+        Ok(())
+    }
+
+    /// Apply changes to the configuration file.
+    ///
+    /// The `ghci` session is restarted if its command changed. Otherwise, the new options apply to
+    /// the next reload. If the configuration can't be loaded, the current options are kept.
+    #[instrument(skip_all, level = "debug")]
+    async fn reload_config(&mut self) -> miette::Result<()> {
+        let old_command = self.opts.command.clone();
+        let old_enable_eval = self.opts.enable_eval;
+        if let Err(err) = crate::config::reload_opts(self.opts.session.as_deref())
+            .and_then(|opts| self.opts.update_from_cli(&opts))
+        {
+            tracing::error!("Failed to reload configuration, keeping current options:\n{err:?}");
+            return Ok(());
+        }
+        tracing::info!("Reloaded configuration");
+
+        if self.opts.command != old_command {
+            tracing::info!("Command changed, restarting ghci");
+            self.hook_env.start(Vec::new(), Vec::new(), Vec::new());
+            self.opts.clear();
+            self.restart(Vec::new()).await?;
+        } else if self.opts.enable_eval != old_enable_eval {
+            if self.opts.enable_eval {
+                self.refresh_eval_commands().await?;
+            } else {
+                self.eval_commands.clear();
+            }
+        }
+
+        Ok(())
+    }
+
     /// Refresh the listing of targets by parsing the `:show paths` and `:show targets` output.
     #[instrument(skip_all, level = "debug")]
     async fn refresh_targets(&mut self) -> miette::Result<()> {
@@ -605,9 +1106,62 @@ impl Ghci {
     async fn refresh_paths(&mut self) -> miette::Result<()> {
         self.search_paths = self.stdin.show_paths(&mut self.stdout).await?;
         tracing::debug!(cwd = %self.search_paths.cwd, search_paths = ?self.search_paths.search_paths, "Parsed paths");
+        if let Some(sender) = &self.opts.search_paths_sender {
+            let mut paths = self
+                .search_paths
+                .source_search_paths()
+                .filter(|path| path.is_dir())
+                .collect::<Vec<_>>();
+            paths.sort();
+            sender.send_if_modified(|old_paths| {
+                let modified = *old_paths != paths;
+                *old_paths = paths;
+                modified
+            });
+        }
         Ok(())
     }
 
+    /// Rebuild the import graph by reading and parsing the files in `targets`.
+    #[instrument(skip_all, level = "debug")]
+    async fn refresh_module_graph(&mut self) {
+        self.module_graph.clear();
+        let paths = self
+            .targets
+            .iter()
+            .map(|target| target.path().clone())
+            .collect::<Vec<_>>();
+        self.update_module_graph(&paths).await;
+        tracing::debug!(modules = self.module_graph.len(), "Built module graph");
+    }
+
+    /// Update the import graph by reading and parsing the given files.
+    ///
+    /// Files which can't be read are left out of the graph.
+    #[instrument(skip_all, level = "debug")]
+    async fn update_module_graph(
+        &mut self,
+        paths: impl IntoIterator<Item = impl Borrow<NormalPath>>,
+    ) {
+        for path in paths {
+            let path = path.borrow();
+            match tokio::fs::read_to_string(path).await {
+                Ok(contents) => {
+                    // Modules without a header are named `Main`.
+                    self.module_graph.insert(
+                        path.clone(),
+                        "Main".to_owned(),
+                        parse_module_imports(&contents),
+                    );
+                }
+                Err(err) => {
+                    tracing::debug!(%path, "Failed to read module for import graph: {err}");
+                    self.module_graph.remove(path);
+                }
+            }
+        }
+    }
+
     /// Refresh `eval_commands` by reading and parsing the files in `targets`.
     #[instrument(skip_all, level = "debug")]
     async fn refresh_eval_commands(&mut self) -> miette::Result<()> {
@@ -859,6 +1413,13 @@ impl Ghci {
         Ok(())
     }
 
+    /// Kill any `cancel-on-reload` shell hooks which are still running from the previous
+    /// compilation.
+    #[instrument(skip_all, level = "trace")]
+    fn cancel_hooks(&mut self) {
+        std::mem::take(&mut self.hook_cancellation).cancel();
+    }
+
     // Get rid of any handles for background commands that have finished.
     #[instrument(skip_all, level = "trace")]
     fn prune_command_handles(&mut self) {
@@ -876,14 +1437,40 @@ impl Ghci {
         log: &mut CompilationLog,
         events: [LifecycleEvent; N],
     ) -> miette::Result<()> {
+        self.opts.compile_timer.finish().await;
+        if let Some(path) = &self.opts.compile_profile_path {
+            self.opts.compile_timer.profile().write(path).await?;
+        }
         // Allow hooks to consume the error log by updating it before running the hooks.
         self.write_error_log(log).await?;
+        let previous_result = self.last_log.result();
+        self.last_log = log.clone();
+        if let Some(notification) =
+            Notification::for_transition(previous_result, log, self.opts.session.as_deref())
+        {
+            self.command_handles
+                .extend(self.opts.notifier.notify(&notification));
+        }
+        // It's fine if nobody is listening.
+        let _ = self.opts.log_sender.send(log.clone());
+        self.opts
+            .status_sender
+            .finish_compilation(log, compilation_start.elapsed());
+
+        let finished = SystemTime::now();
+        let duration = compilation_start.elapsed();
+        self.hook_env.duration = Some(duration);
+
+        let event = events[N - 1];
+        let changed_modules = self.changed_modules.take();
 
         for event in events {
             self.run_hooks(event, log).await?;
         }
-
-        let event = events[N - 1];
+        for event in LifecycleEvent::for_result(previous_result, log.result()) {
+            self.run_hooks(event, log).await?;
+        }
+        self.finish_hooks(log).await?;
 
         if let Some(CompilationResult::Err) = log.result() {
             tracing::error!(
@@ -901,9 +1488,27 @@ impl Ghci {
             // Run the eval commands, if any.
             self.eval(log).await?;
             // Run the user-provided test command, if any.
-            self.test(log).await?;
+            match changed_modules {
+                Some(changed) if self.opts.test_targets.is_some() => {
+                    self.test_changed(&changed, log).await?;
+                }
+                _ => {
+                    self.test(log).await?;
+                }
+            }
         }
 
+        // Send the record after the hooks and tests have run, so it includes their results.
+        let _ = self.opts.history_sender.send(ReloadRecord {
+            timestamp: finished,
+            event,
+            changes: std::mem::take(&mut self.pending_changes),
+            duration,
+            log: log.clone(),
+        });
+
+        self.opts.status_sender.set_phase(GhciPhase::Idle);
+
         Ok(())
     }
 
@@ -913,20 +1518,33 @@ impl Ghci {
         event: LifecycleEvent,
         log: &mut CompilationLog,
     ) -> miette::Result<()> {
-        for hook in self.opts.hooks.select(event) {
+        for hook in self.opts.hooks.select(event, &self.hook_env) {
             tracing::info!(command = %hook.command, "Running {hook} command");
             match &hook.command {
                 hooks::Command::Ghci(command) => {
                     let start_time = Instant::now();
-                    self.stdin
+                    let output = self
+                        .stdin
                         .run_command(&mut self.stdout, command, log)
                         .await?;
                     if let LifecycleEvent::Test = &hook.event {
                         tracing::info!("Finished running tests in {:.2?}", start_time.elapsed());
+                        log.record_test_output(&output.stdout);
+                    }
+                    let result = log.record_hook(event, command, start_time.elapsed(), &output);
+                    if let Some(failure) = &result.failure {
+                        tracing::error!("{hook} command `{command}` {failure}");
                     }
                 }
                 hooks::Command::Shell(command) => {
-                    command.run_on(&mut self.command_handles).await?;
+                    let flow = command
+                        .with_env(self.hook_env.vars(hook.event, log))
+                        .run_on(&mut self.command_handles, &self.hook_cancellation)
+                        .await?;
+                    if flow.is_break() {
+                        tracing::warn!("Skipping the remaining {event} hooks");
+                        break;
+                    }
                 }
             }
         }
@@ -0,0 +1,236 @@
+//! The import graph of the modules loaded in a `ghci` session.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use serde::ser::SerializeSeq;
+use serde::Serialize;
+
+use crate::normal_path::NormalPath;
+
+use super::parse::ModuleImports;
+
+/// A module in a [`ModuleGraph`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct ModuleNode {
+    /// The module's dotted name, like `My.Cool.Module`.
+    name: String,
+    /// The names of the modules this module imports, including modules which aren't loaded.
+    imports: BTreeSet<String>,
+}
+
+/// The import graph of the modules loaded in a `ghci` session.
+///
+/// Edges are determined by module name, so imports of modules which aren't loaded (like modules
+/// from other packages) are ignored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleGraph {
+    modules: BTreeMap<NormalPath, ModuleNode>,
+}
+
+impl ModuleGraph {
+    /// Add a module to the graph, replacing any existing module with the same path.
+    ///
+    /// If the module has no name in its header, `default_name` is used.
+    pub fn insert(&mut self, path: NormalPath, default_name: String, imports: ModuleImports) {
+        self.modules.insert(
+            path,
+            ModuleNode {
+                name: imports.name.unwrap_or(default_name),
+                imports: imports.imports.into_iter().collect(),
+            },
+        );
+    }
+
+    /// Remove the module with the given path from the graph.
+    pub fn remove(&mut self, path: &Utf8Path) {
+        self.modules.remove(path);
+    }
+
+    /// Remove every module from the graph.
+    pub fn clear(&mut self) {
+        self.modules.clear();
+    }
+
+    /// Get the number of modules in the graph.
+    pub fn len(&self) -> usize {
+        self.modules.len()
+    }
+
+    /// Get the names of the given modules and every module which imports them, directly or
+    /// indirectly.
+    ///
+    /// When the given modules change, these are the modules `ghci` will need to recompile. Paths
+    /// which aren't in the graph are ignored.
+    pub fn dependents<'a>(&self, paths: impl IntoIterator<Item = &'a NormalPath>) -> Vec<String> {
+        self.dependent_names(paths)
+            .into_iter()
+            .map(ToOwned::to_owned)
+            .collect()
+    }
+
+    /// Like [`Self::dependents`], but get the modules' absolute source paths instead of their
+    /// names.
+    pub fn dependent_paths<'a>(
+        &self,
+        paths: impl IntoIterator<Item = &'a NormalPath>,
+    ) -> BTreeSet<Utf8PathBuf> {
+        let names = self.dependent_names(paths);
+        self.modules
+            .iter()
+            .filter(|(_, module)| names.contains(module.name.as_str()))
+            .map(|(path, _)| path.absolute().to_owned())
+            .collect()
+    }
+
+    fn dependent_names<'a>(
+        &self,
+        paths: impl IntoIterator<Item = &'a NormalPath>,
+    ) -> BTreeSet<&str> {
+        // Map from each module name to the modules which import it.
+        let mut importers = HashMap::<&str, Vec<&str>>::new();
+        for module in self.modules.values() {
+            for import in &module.imports {
+                importers
+                    .entry(import.as_str())
+                    .or_default()
+                    .push(module.name.as_str());
+            }
+        }
+
+        let mut seen = BTreeSet::new();
+        let mut queue = paths
+            .into_iter()
+            .filter_map(|path| self.modules.get(path.absolute()))
+            .map(|module| module.name.as_str())
+            .collect::<VecDeque<_>>();
+        while let Some(name) = queue.pop_front() {
+            if seen.insert(name) {
+                queue.extend(importers.get(name).into_iter().flatten());
+            }
+        }
+
+        seen
+    }
+}
+
+impl Serialize for ModuleGraph {
+    /// Serialize the graph as a list of modules, each with its path and the names of the loaded
+    /// modules it imports.
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        #[derive(Serialize)]
+        struct Module<'a> {
+            name: &'a str,
+            path: &'a Utf8Path,
+            imports: Vec<&'a str>,
+        }
+
+        let names = self
+            .modules
+            .values()
+            .map(|module| module.name.as_str())
+            .collect::<BTreeSet<_>>();
+
+        let mut seq = serializer.serialize_seq(Some(self.modules.len()))?;
+        for (path, module) in &self.modules {
+            seq.serialize_element(&Module {
+                name: &module.name,
+                path: path.relative(),
+                imports: module
+                    .imports
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|import| names.contains(import))
+                    .collect(),
+            })?;
+        }
+        seq.end()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn module(name: &str, imports: &[&str]) -> ModuleImports {
+        ModuleImports {
+            name: Some(name.to_owned()),
+            imports: imports.iter().map(|import| (*import).to_owned()).collect(),
+        }
+    }
+
+    fn path(path: &str) -> NormalPath {
+        NormalPath::new(path, "/puppy").unwrap()
+    }
+
+    fn graph() -> ModuleGraph {
+        let mut graph = ModuleGraph::default();
+        graph.insert(
+            path("src/A.hs"),
+            "A".to_owned(),
+            module("A", &["Data.Text"]),
+        );
+        graph.insert(path("src/B.hs"), "B".to_owned(), module("B", &["A"]));
+        graph.insert(path("src/C.hs"), "C".to_owned(), module("C", &["B", "A"]));
+        graph.insert(path("src/D.hs"), "D".to_owned(), module("D", &[]));
+        graph.insert(
+            path("app/Main.hs"),
+            "Main".to_owned(),
+            ModuleImports {
+                name: None,
+                imports: vec!["C".to_owned()],
+            },
+        );
+        graph
+    }
+
+    #[test]
+    fn test_dependents() {
+        let graph = graph();
+        assert_eq!(
+            graph.dependents(&[path("src/A.hs")]),
+            vec!["A", "B", "C", "Main"]
+        );
+        assert_eq!(graph.dependents(&[path("src/C.hs")]), vec!["C", "Main"]);
+        assert_eq!(
+            graph.dependents(&[path("src/D.hs"), path("src/B.hs")]),
+            vec!["B", "C", "D", "Main"]
+        );
+        assert_eq!(graph.dependents(&[path("src/E.hs")]), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_dependent_paths() {
+        assert_eq!(
+            graph().dependent_paths(&[path("src/B.hs")]),
+            ["/puppy/app/Main.hs", "/puppy/src/B.hs", "/puppy/src/C.hs"]
+                .into_iter()
+                .map(Utf8PathBuf::from)
+                .collect()
+        );
+    }
+
+    #[test]
+    fn test_serialize() {
+        let mut graph = graph();
+        graph.remove(path("src/D.hs").absolute());
+        assert_eq!(
+            serde_json::to_value(&graph).unwrap(),
+            serde_json::json!([
+                {"name": "Main", "path": "app/Main.hs", "imports": ["C"]},
+                {"name": "A", "path": "src/A.hs", "imports": []},
+                {"name": "B", "path": "src/B.hs", "imports": ["A"]},
+                {"name": "C", "path": "src/C.hs", "imports": ["A", "B"]},
+            ])
+        );
+    }
+}
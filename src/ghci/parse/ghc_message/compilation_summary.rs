@@ -1,3 +1,4 @@
+use serde::Serialize;
 use winnow::ascii::digit1;
 use winnow::combinator::alt;
 use winnow::combinator::opt;
@@ -18,7 +19,7 @@ use crate::ghci::parse::CompilationResult;
 /// ```text
 /// Failed, 58 modules loaded.
 /// ```
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub struct CompilationSummary {
     /// The compilation result; whether compilation succeeded or failed.
     pub result: CompilationResult,
@@ -7,15 +7,41 @@ use winnow::Parser;
 use crate::ghci::parse::lines::rest_of_line;
 use crate::ghci::parse::module_and_files;
 
-/// Parse a `[1 of 3] Compiling Foo ( Foo.hs, Foo.o, interpreted )` message.
-pub fn compiling(input: &mut &str) -> PResult<CompilingModule> {
+/// How far along a compilation is, from the `[1 of 3]` in a `Compiling` message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompilingProgress {
+    /// The number of the module being compiled, starting from 1.
+    pub current: usize,
+    /// The total number of modules to compile.
+    pub total: usize,
+}
+
+/// Parse the `[1 of 3]` prefix of a `Compiling` message.
+fn compiling_progress(input: &mut &str) -> PResult<CompilingProgress> {
     let _ = "[".parse_next(input)?;
     let _ = space0.parse_next(input)?;
-    let _ = digit1.parse_next(input)?;
+    let current = digit1.parse_to().parse_next(input)?;
     let _ = " of ".parse_next(input)?;
-    let _ = digit1.parse_next(input)?;
+    let total = digit1.parse_to().parse_next(input)?;
     let _ = "]".parse_next(input)?;
     let _ = " Compiling ".parse_next(input)?;
+
+    Ok(CompilingProgress { current, total })
+}
+
+/// Parse the progress and module from a line like `[1 of 3] Compiling Foo ( Foo.hs, Foo.o,
+/// interpreted )`.
+///
+/// Returns `None` if the line isn't a `Compiling` message.
+pub fn parse_compiling_line(line: &str) -> Option<(CompilingProgress, CompilingModule)> {
+    (compiling_progress, module_and_files)
+        .parse_next(&mut &*line)
+        .ok()
+}
+
+/// Parse a `[1 of 3] Compiling Foo ( Foo.hs, Foo.o, interpreted )` message.
+pub fn compiling(input: &mut &str) -> PResult<CompilingModule> {
+    let _ = compiling_progress.parse_next(input)?;
     let module = module_and_files.parse_next(input)?;
     let _ = rest_of_line.parse_next(input)?;
 
@@ -30,6 +56,54 @@ mod tests {
     use indoc::indoc;
     use pretty_assertions::assert_eq;
 
+    fn parse_compiling_progress(line: &str) -> Option<CompilingProgress> {
+        parse_compiling_line(line).map(|(progress, _module)| progress)
+    }
+
+    #[test]
+    fn test_parse_compiling_progress() {
+        assert_eq!(
+            parse_compiling_progress("[1 of 3] Compiling Foo ( Foo.hs, Foo.o, interpreted )"),
+            Some(CompilingProgress {
+                current: 1,
+                total: 3
+            })
+        );
+        assert_eq!(
+            parse_compiling_progress(
+                "[  12 of 6508] Compiling A.DoggyPrelude.Puppy ( src/A/DoggyPrelude/Puppy.hs )"
+            ),
+            Some(CompilingProgress {
+                current: 12,
+                total: 6508
+            })
+        );
+
+        // Negative cases.
+        assert_eq!(parse_compiling_progress("Ok, 3 modules loaded."), None);
+        assert_eq!(parse_compiling_progress("[1 of 3] Linking Foo"), None);
+        assert_eq!(parse_compiling_progress(" [1 of 3] Compiling Foo"), None);
+    }
+
+    #[test]
+    fn test_parse_compiling_line() {
+        assert_eq!(
+            parse_compiling_line("[ 2 of 3] Compiling Foo.Bar ( src/Foo/Bar.hs, interpreted )"),
+            Some((
+                CompilingProgress {
+                    current: 2,
+                    total: 3
+                },
+                CompilingModule {
+                    name: "Foo.Bar".into(),
+                    path: "src/Foo/Bar.hs".into()
+                }
+            ))
+        );
+        assert_eq!(parse_compiling_line("[1 of 3] Compiling Foo"), None);
+        assert_eq!(parse_compiling_line("Ok, 3 modules loaded."), None);
+    }
+
     #[test]
     fn test_parse_compiling_message() {
         assert_eq!(
@@ -4,6 +4,8 @@ use std::fmt::Display;
 
 use camino::Utf8PathBuf;
 use miette::miette;
+use serde::ser::SerializeStruct;
+use serde::Serialize;
 use winnow::combinator::alt;
 use winnow::combinator::repeat;
 use winnow::prelude::*;
@@ -21,6 +23,8 @@ use path_colon::path_colon;
 
 mod compiling;
 use compiling::compiling;
+pub use compiling::parse_compiling_line;
+pub use compiling::CompilingProgress;
 
 mod message_body;
 
@@ -98,7 +102,8 @@ impl GhcMessage {
 }
 
 /// The result of compiling modules in `ghci`.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
 pub enum CompilationResult {
     /// All the modules compiled successfully.
     Ok,
@@ -123,6 +128,76 @@ pub struct GhcDiagnostic {
     pub message: String,
 }
 
+impl GhcDiagnostic {
+    /// Get the GHC error code for this diagnostic, if any, like `GHC-83865`.
+    ///
+    /// GHC 9.6 and newer print these codes in brackets after the severity:
+    ///
+    /// ```text
+    /// src/MyModule.hs:4:11: error: [GHC-83865]
+    /// ```
+    pub fn error_code(&self) -> Option<&str> {
+        let code = self
+            .message
+            .trim_start()
+            .strip_prefix('[')?
+            .split_once(']')?
+            .0;
+        code.starts_with("GHC-").then_some(code)
+    }
+
+    /// Get the diagnostic's message without the leading error code, if any.
+    pub fn message_without_code(&self) -> &str {
+        match self.error_code() {
+            Some(code) => self
+                .message
+                .trim_start()
+                .strip_prefix(&format!("[{code}]"))
+                .unwrap_or(&self.message),
+            None => &self.message,
+        }
+    }
+
+    /// Get the first line of the diagnostic's message, for display in lists.
+    ///
+    /// Empty lines and lines containing only bracketed annotations like `[-Wunused-top-binds]`
+    /// are skipped.
+    pub fn headline(&self) -> &str {
+        self.message_without_code()
+            .lines()
+            .map(|line| line.trim().trim_start_matches('•').trim_start())
+            .find(|line| {
+                !line
+                    .split_whitespace()
+                    .all(|word| word.starts_with('[') && word.ends_with(']'))
+            })
+            .unwrap_or_default()
+    }
+
+    /// Construct an example diagnostic with the given severity, for tests.
+    #[cfg(test)]
+    pub fn example(severity: Severity) -> Self {
+        Self {
+            severity,
+            path: Some("src/MyLib.hs".into()),
+            span: PositionRange::new(1, 1, 1, 1),
+            message: "Oh no".into(),
+        }
+    }
+}
+
+impl Serialize for GhcDiagnostic {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("GhcDiagnostic", 5)?;
+        state.serialize_field("severity", &self.severity)?;
+        state.serialize_field("path", &self.path)?;
+        state.serialize_field("span", &self.span)?;
+        state.serialize_field("code", &self.error_code())?;
+        state.serialize_field("message", &self.message)?;
+        state.end()
+    }
+}
+
 impl Display for GhcDiagnostic {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match &self.path {
@@ -272,4 +347,50 @@ mod tests {
             ]
         );
     }
+
+    #[test]
+    fn test_error_code() {
+        let diagnostic = |message: &str| GhcDiagnostic {
+            message: message.into(),
+            ..GhcDiagnostic::example(Severity::Error)
+        };
+
+        assert_eq!(
+            diagnostic("[GHC-83865]\n    • Couldn't match type").error_code(),
+            Some("GHC-83865")
+        );
+        assert_eq!(
+            diagnostic("[GHC-40910] [-Wunused-top-binds]\n    Defined but not used").error_code(),
+            Some("GHC-40910")
+        );
+
+        // Negative cases.
+        assert_eq!(diagnostic("\n    • Couldn't match type").error_code(), None);
+        assert_eq!(
+            diagnostic("[-Wunused-top-binds]\n    Defined but not used").error_code(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_headline() {
+        let diagnostic = |message: &str| GhcDiagnostic {
+            message: message.into(),
+            ..GhcDiagnostic::example(Severity::Error)
+        };
+
+        assert_eq!(
+            diagnostic("[GHC-83865]\n    • Couldn't match type\n      Expected: ()").headline(),
+            "Couldn't match type"
+        );
+        assert_eq!(
+            diagnostic("[GHC-40910] [-Wunused-top-binds]\n    Defined but not used").headline(),
+            "Defined but not used"
+        );
+        assert_eq!(
+            diagnostic(" Defined but not used: `bar'").headline(),
+            "Defined but not used: `bar'"
+        );
+        assert_eq!(diagnostic("").headline(), "");
+    }
 }
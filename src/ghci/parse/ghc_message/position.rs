@@ -1,5 +1,6 @@
 use std::fmt::Display;
 
+use serde::Serialize;
 use winnow::ascii::digit1;
 use winnow::combinator::alt;
 use winnow::combinator::opt;
@@ -8,7 +9,7 @@ use winnow::PResult;
 use winnow::Parser;
 
 /// A position in a file.
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
 pub struct Position {
     /// 1-based line number.
     line: usize,
@@ -18,11 +19,20 @@ pub struct Position {
 
 impl Position {
     /// Construct a new [`Position`] from a line and column number.
-    #[cfg(test)]
     pub fn new(line: usize, column: usize) -> Self {
         Self { line, column }
     }
 
+    /// Get the 1-based line number.
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    /// Get the 1-based column number.
+    pub fn column(&self) -> usize {
+        self.column
+    }
+
     /// Is the line and column of this position zero? If so, there's no useful location information.
     pub fn is_zero(&self) -> bool {
         self.line == 0 && self.column == 0
@@ -36,7 +46,7 @@ impl Display for Position {
 }
 
 /// A range (span) of positions in a file.
-#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
 pub struct PositionRange {
     /// The start position.
     start: Position,
@@ -46,7 +56,6 @@ pub struct PositionRange {
 
 impl PositionRange {
     /// Construct a new span from the given lines and columns.
-    #[cfg(test)]
     pub fn new(start_line: usize, start_column: usize, end_line: usize, end_column: usize) -> Self {
         Self {
             start: Position::new(start_line, start_column),
@@ -54,6 +63,18 @@ impl PositionRange {
         }
     }
 
+    /// Get the start position.
+    pub fn start(&self) -> Position {
+        self.start
+    }
+
+    /// Get the end position.
+    ///
+    /// Note that GHC prints spans with an inclusive end column.
+    pub fn end(&self) -> Position {
+        self.end
+    }
+
     /// Is this a zero-length span at `0:0`?
     pub fn is_zero(&self) -> bool {
         self.start.is_zero() && self.end.is_zero()
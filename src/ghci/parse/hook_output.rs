@@ -0,0 +1,129 @@
+use std::fmt::Display;
+
+use serde::Serialize;
+use winnow::ascii::digit1;
+use winnow::ascii::space0;
+use winnow::ascii::space1;
+use winnow::combinator::alt;
+use winnow::combinator::delimited;
+use winnow::combinator::preceded;
+use winnow::PResult;
+use winnow::Parser;
+
+/// How a `ghci` command failed, parsed from its output.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum HookFailure {
+    /// The command exited with a non-zero exit code, like `exitWith (ExitFailure 1)`.
+    ExitFailure {
+        /// The exit code.
+        code: i32,
+    },
+    /// The command threw an exception.
+    Exception {
+        /// The first line of the exception's message.
+        message: String,
+    },
+}
+
+impl Display for HookFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HookFailure::ExitFailure { code } => write!(f, "exited with `ExitFailure {code}`"),
+            HookFailure::Exception { message } => write!(f, "threw an exception: {message}"),
+        }
+    }
+}
+
+/// Parse an `ExitFailure` exception message, like `ExitFailure 1` or `ExitFailure (-1)`.
+fn exit_failure(input: &mut &str) -> PResult<i32> {
+    preceded(
+        ("ExitFailure", space1),
+        alt((
+            delimited(("(", space0), ("-", digit1).recognize(), (space0, ")")),
+            digit1,
+        ))
+        .parse_to(),
+    )
+    .parse_next(input)
+}
+
+/// Parse the failure of a `ghci` command from its output.
+///
+/// `ghci` reports uncaught exceptions, including the `ExitCode` exceptions thrown by
+/// `System.Exit`, on a line starting with `*** Exception: `. Returns `None` if the output doesn't
+/// contain an exception, or if the only exception is `ExitSuccess`.
+pub fn parse_hook_failure(output: &str) -> Option<HookFailure> {
+    let output = strip_ansi_escapes::strip_str(output);
+
+    output.lines().find_map(|line| {
+        let message = line.strip_prefix("*** Exception: ")?.trim_end();
+        if message == "ExitSuccess" {
+            return None;
+        }
+        Some(match exit_failure.parse(message) {
+            Ok(code) => HookFailure::ExitFailure { code },
+            Err(_) => HookFailure::Exception {
+                message: message.to_owned(),
+            },
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_hook_failure() {
+        assert_eq!(
+            parse_hook_failure(indoc!(
+                "
+                Finished in 0.0012 seconds
+                3 examples, 1 failure
+                *** Exception: ExitFailure 1
+                "
+            )),
+            Some(HookFailure::ExitFailure { code: 1 })
+        );
+
+        assert_eq!(
+            parse_hook_failure("*** Exception: ExitFailure (-3)\n"),
+            Some(HookFailure::ExitFailure { code: -3 })
+        );
+
+        assert_eq!(
+            parse_hook_failure(indoc!(
+                "
+                *** Exception: Prelude.head: empty list
+                CallStack (from HasCallStack):
+                  error, called at libraries/base/GHC/List.hs:1646:3 in base:GHC.List
+                "
+            )),
+            Some(HookFailure::Exception {
+                message: "Prelude.head: empty list".to_owned()
+            })
+        );
+
+        assert_eq!(
+            parse_hook_failure("*** Exception: ExitFailure 1 happened\n"),
+            Some(HookFailure::Exception {
+                message: "ExitFailure 1 happened".to_owned()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_hook_failure_none() {
+        assert_eq!(parse_hook_failure(""), None);
+        assert_eq!(parse_hook_failure("3 examples, 0 failures\n"), None);
+        assert_eq!(parse_hook_failure("*** Exception: ExitSuccess\n"), None);
+        assert_eq!(
+            parse_hook_failure("putStrLn \"*** Exception: lol\"\n"),
+            None
+        );
+    }
+}
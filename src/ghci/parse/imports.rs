@@ -0,0 +1,170 @@
+//! Parse the module header and import declarations of a Haskell source file.
+
+use winnow::ascii::space1;
+use winnow::combinator::opt;
+use winnow::token::take_till;
+use winnow::PResult;
+use winnow::Parser;
+
+use super::module_name;
+
+/// The module name and imports declared in a Haskell source file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ModuleImports {
+    /// The name in the `module` header, if any.
+    pub name: Option<String>,
+    /// The names of the imported modules, in order.
+    pub imports: Vec<String>,
+}
+
+/// Parse the module header and import declarations of a Haskell source file.
+///
+/// This is a line-based approximation: declarations must start at the beginning of a line, and
+/// the imported module's name must be on the same line as the `import` keyword. Comments
+/// (including pragmas like `{-# SOURCE #-}`) are skipped.
+pub fn parse_module_imports(input: &str) -> ModuleImports {
+    let mut ret = ModuleImports::default();
+    let mut comment_depth = 0_usize;
+
+    for line in input.lines() {
+        let line = strip_comments(line, &mut comment_depth);
+        let mut line = line.as_str();
+        if ret.name.is_none() {
+            if let Ok(name) = module_header.parse_next(&mut line) {
+                ret.name = Some(name.to_owned());
+                continue;
+            }
+        }
+        if let Ok(import) = import_declaration.parse_next(&mut line) {
+            ret.imports.push(import.to_owned());
+        }
+    }
+
+    ret
+}
+
+/// Parse the start of a `module` header, like `module My.Module (`.
+fn module_header<'i>(input: &mut &'i str) -> PResult<&'i str> {
+    ("module", space1, module_name)
+        .map(|(_, _, name)| name)
+        .parse_next(input)
+}
+
+/// Parse the start of an import declaration, like `import qualified "base" Data.List as List`.
+fn import_declaration<'i>(input: &mut &'i str) -> PResult<&'i str> {
+    (
+        "import",
+        space1,
+        opt(("safe", space1)),
+        opt(("qualified", space1)),
+        opt(('"', take_till(0.., '"'), '"', space1)),
+        module_name,
+    )
+        .map(|(_, _, _, _, _, name)| name)
+        .parse_next(input)
+}
+
+/// Remove comments from a line, tracking the depth of nested `{- ... -}` block comments across
+/// lines.
+fn strip_comments(line: &str, depth: &mut usize) -> String {
+    let mut ret = String::with_capacity(line.len());
+    let mut rest = line;
+    while !rest.is_empty() {
+        if rest.starts_with("{-") {
+            *depth += 1;
+            rest = &rest[2..];
+        } else if *depth > 0 && rest.starts_with("-}") {
+            *depth -= 1;
+            // Keep words on either side of a comment apart.
+            ret.push(' ');
+            rest = &rest[2..];
+        } else if *depth == 0 && is_line_comment(rest) {
+            break;
+        } else {
+            let c = rest.chars().next().expect("`rest` is not empty");
+            if *depth == 0 {
+                ret.push(c);
+            }
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    ret
+}
+
+/// Does the input start with a line comment? A line comment is two or more dashes which aren't
+/// part of an operator like `-->`.
+fn is_line_comment(input: &str) -> bool {
+    let dashes = input.len() - input.trim_start_matches('-').len();
+    dashes >= 2
+        && !input[dashes..]
+            .chars()
+            .next()
+            .is_some_and(|c| "!#$%&*+./<=>?@\\^|~:".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_parse_module_imports() {
+        assert_eq!(
+            parse_module_imports(indoc!(
+                r#"
+                {-# LANGUAGE OverloadedStrings #-}
+                -- | A module.
+                module My.Module
+                  ( puppy
+                  ) where
+
+                import Data.Text (Text)
+                import qualified Data.Map as Map
+                import safe "base" Data.List qualified as List
+                import {-# SOURCE #-} My.Cycle
+                {- import Commented.Out
+                   import {- nested -} Also.Commented.Out
+                -}
+                -- import Line.Commented.Out
+                import           My.Other.Module -- The other module.
+
+                importantThing :: Text
+                importantThing = "import Not.An.Import"
+                "#
+            )),
+            ModuleImports {
+                name: Some("My.Module".to_owned()),
+                imports: vec![
+                    "Data.Text".to_owned(),
+                    "Data.Map".to_owned(),
+                    "Data.List".to_owned(),
+                    "My.Cycle".to_owned(),
+                    "My.Other.Module".to_owned(),
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_module_imports_main() {
+        assert_eq!(
+            parse_module_imports("import System.IO\nmain = putStrLn \"--> hi\"\n"),
+            ModuleImports {
+                name: None,
+                imports: vec!["System.IO".to_owned()],
+            }
+        );
+    }
+
+    #[test]
+    fn test_strip_comments() {
+        let mut depth = 0;
+        assert_eq!(strip_comments("x --> y -- comment", &mut depth), "x --> y ");
+        assert_eq!(strip_comments("a {- b", &mut depth), "a ");
+        assert_eq!(depth, 1);
+        assert_eq!(strip_comments("c -} d", &mut depth), "  d");
+        assert_eq!(depth, 0);
+    }
+}
@@ -3,10 +3,13 @@
 mod eval;
 mod ghc_message;
 mod haskell_grammar;
+mod hook_output;
+mod imports;
 mod lines;
 mod module_and_files;
 mod show_paths;
 mod show_targets;
+mod test_output;
 
 use haskell_grammar::module_name;
 use lines::rest_of_line;
@@ -14,13 +17,22 @@ use module_and_files::module_and_files;
 
 pub use eval::parse_eval_commands;
 pub use eval::EvalCommand;
+pub use ghc_message::parse_compiling_line;
 pub use ghc_message::parse_ghc_messages;
 pub use ghc_message::CompilationResult;
 pub use ghc_message::CompilationSummary;
+pub use ghc_message::CompilingProgress;
 pub use ghc_message::GhcDiagnostic;
 pub use ghc_message::GhcMessage;
+pub use ghc_message::PositionRange;
 pub use ghc_message::Severity;
+pub use hook_output::parse_hook_failure;
+pub use hook_output::HookFailure;
+pub use imports::parse_module_imports;
+pub use imports::ModuleImports;
 pub use module_and_files::CompilingModule;
 pub use show_paths::parse_show_paths;
 pub use show_paths::ShowPaths;
 pub use show_targets::parse_show_targets;
+pub use test_output::parse_test_output;
+pub use test_output::TestResults;
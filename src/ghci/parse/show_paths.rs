@@ -21,6 +21,9 @@ use crate::normal_path::NormalPath;
 
 use super::lines::until_newline;
 
+/// Directories containing build products rather than source files.
+const BUILD_DIRECTORIES: [&str; 3] = ["dist-newstyle", "dist", ".stack-work"];
+
 /// Parsed `:show paths` output.
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ShowPaths {
@@ -67,6 +70,16 @@ impl ShowPaths {
         Err(miette!("Couldn't find source path for {target}"))
     }
 
+    /// Get the search paths which contain source files, skipping build directories like
+    /// `dist-newstyle` and `.stack-work`.
+    pub fn source_search_paths(&self) -> impl Iterator<Item = Utf8PathBuf> + '_ {
+        self.absolute_search_paths().filter(|path| {
+            !path
+                .components()
+                .any(|component| BUILD_DIRECTORIES.contains(&component.as_str()))
+        })
+    }
+
     fn absolute_search_paths(&self) -> impl Iterator<Item = Utf8PathBuf> + '_ {
         self.search_paths.iter().map(|path| {
             if path.is_absolute() {
@@ -231,6 +244,27 @@ mod tests {
             .is_err());
     }
 
+    #[test]
+    fn test_source_search_paths() {
+        let paths = ShowPaths {
+            cwd: Utf8PathBuf::from("/Users/wiggles/ghciwatch/"),
+            search_paths: vec![
+                Utf8PathBuf::from("src"),
+                Utf8PathBuf::from("/Users/wiggles/ghciwatch/test"),
+                Utf8PathBuf::from("/Users/wiggles/ghciwatch/dist-newstyle/build/x86_64-linux/ghc-9.4.8/ghciwatch-0.1.0.0/build/autogen"),
+                Utf8PathBuf::from("/Users/wiggles/ghciwatch/.stack-work/dist/x86_64-linux/ghc-9.4.8/build/global-autogen"),
+            ],
+        };
+
+        assert_eq!(
+            paths.source_search_paths().collect::<Vec<_>>(),
+            vec![
+                Utf8PathBuf::from("/Users/wiggles/ghciwatch/src"),
+                Utf8PathBuf::from("/Users/wiggles/ghciwatch/test"),
+            ]
+        );
+    }
+
     #[test]
     fn test_path_to_module() {
         let paths = ShowPaths {
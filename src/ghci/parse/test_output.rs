@@ -0,0 +1,373 @@
+use std::fmt::Display;
+
+use camino::Utf8PathBuf;
+use serde::Serialize;
+use winnow::ascii::digit1;
+use winnow::ascii::space0;
+use winnow::combinator::alt;
+use winnow::combinator::opt;
+use winnow::combinator::preceded;
+use winnow::PResult;
+use winnow::Parser;
+
+use super::GhcDiagnostic;
+use super::PositionRange;
+use super::Severity;
+
+/// Results parsed from the output of a test suite, like `hspec` or `tasty`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct TestResults {
+    /// The number of tests which passed.
+    pub passed: usize,
+    /// The number of tests which failed.
+    pub failed: usize,
+    /// The number of pending tests.
+    pub pending: usize,
+    /// The tests which failed, if the test output lists them.
+    pub failures: Vec<TestFailure>,
+}
+
+impl TestResults {
+    /// Add the results from another test run to these results.
+    pub fn merge(&mut self, other: TestResults) {
+        self.passed += other.passed;
+        self.failed += other.failed;
+        self.pending += other.pending;
+        self.failures.extend(other.failures);
+    }
+
+    /// Format the failing tests as error diagnostics, for the error log.
+    ///
+    /// If tests failed but the output didn't list them, a single diagnostic summarizes the
+    /// results.
+    pub fn to_diagnostics(&self) -> Vec<GhcDiagnostic> {
+        if self.failed > 0 && self.failures.is_empty() {
+            vec![GhcDiagnostic {
+                severity: Severity::Error,
+                path: None,
+                span: PositionRange::default(),
+                message: format!("\n    Tests failed: {self}\n"),
+            }]
+        } else {
+            self.failures
+                .iter()
+                .map(TestFailure::to_diagnostic)
+                .collect()
+        }
+    }
+}
+
+impl Display for TestResults {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} passed, {} failed", self.passed, self.failed)?;
+        if self.pending > 0 {
+            write!(f, ", {} pending", self.pending)?;
+        }
+        Ok(())
+    }
+}
+
+/// A failing test.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct TestFailure {
+    /// The test's name, including the names of the groups containing it.
+    pub name: String,
+    /// The source file the failure was reported in, if known.
+    pub path: Option<Utf8PathBuf>,
+    /// The location of the failure in `path`.
+    pub span: PositionRange,
+}
+
+impl TestFailure {
+    /// Format this failure as an error diagnostic.
+    pub fn to_diagnostic(&self) -> GhcDiagnostic {
+        GhcDiagnostic {
+            severity: Severity::Error,
+            path: self.path.clone(),
+            span: self.span,
+            message: format!("\n    Test failed: {}\n", self.name),
+        }
+    }
+}
+
+/// Parse a summary line like `5 examples, 1 failure, 2 pending`, written by `hspec`.
+fn hspec_summary(input: &mut &str) -> PResult<TestResults> {
+    let examples: usize = digit1.parse_to().parse_next(input)?;
+    let _ = (" example", opt("s"), ", ").parse_next(input)?;
+    let failed = digit1.parse_to().parse_next(input)?;
+    let _ = (" failure", opt("s")).parse_next(input)?;
+    let pending = opt(preceded(", ", digit1.parse_to()))
+        .parse_next(input)?
+        .unwrap_or(0);
+    if pending > 0 {
+        let _ = " pending".parse_next(input)?;
+    }
+
+    Ok(TestResults {
+        passed: examples.saturating_sub(failed + pending),
+        failed,
+        pending,
+        failures: Vec::new(),
+    })
+}
+
+/// Parse a summary line like `All 5 tests passed (0.01s)` or `1 out of 5 tests failed (0.01s)`,
+/// written by `tasty`.
+fn tasty_summary(input: &mut &str) -> PResult<TestResults> {
+    alt((
+        ("All ", digit1.parse_to(), " test", opt("s"), " passed").map(|(_, passed, _, _, _)| {
+            TestResults {
+                passed,
+                ..Default::default()
+            }
+        }),
+        (
+            digit1.parse_to::<usize>(),
+            " out of ",
+            digit1.parse_to::<usize>(),
+            " test",
+            opt("s"),
+            " failed",
+        )
+            .map(|(failed, _, total, _, _, _)| TestResults {
+                passed: total.saturating_sub(failed),
+                failed,
+                ..Default::default()
+            }),
+    ))
+    .parse_next(input)
+}
+
+/// Parse a location line like `  test/MyLibSpec.hs:12:5: `, written by `hspec` before each
+/// failure.
+fn location(line: &str) -> Option<(Utf8PathBuf, PositionRange)> {
+    let mut parts = line.trim().strip_suffix(':')?.rsplitn(3, ':');
+    let column = parts.next()?.parse().ok()?;
+    let line = parts.next()?.parse().ok()?;
+    let path = parts.next()?;
+    Some((path.into(), PositionRange::new(line, column, line, column)))
+}
+
+/// Parse a failure line like `  1) MyLib.someFunc fails`, written by `hspec`.
+fn hspec_failure(input: &mut &str) -> PResult<String> {
+    let _ = (space0, digit1, ") ").parse_next(input)?;
+    Ok(input.trim_end().to_owned())
+}
+
+/// Parse a test line like `    fails:  FAIL (0.01s)`, written by `tasty`.
+///
+/// Returns the test name and whether it failed.
+fn tasty_test<'i>(input: &mut &'i str) -> PResult<(&'i str, bool)> {
+    let (name, _, _) = (winnow::token::take_till(1.., ':'), ':', space0).parse_next(input)?;
+    let failed = alt(("OK".value(false), "FAIL".value(true))).parse_next(input)?;
+    Ok((name, failed))
+}
+
+/// Parse the results of a test suite from its output.
+///
+/// Recognizes the summaries written by `hspec` and `tasty`. Returns `None` if the output doesn't
+/// contain a summary.
+pub fn parse_test_output(output: &str) -> Option<TestResults> {
+    let output = strip_ansi_escapes::strip_str(output);
+
+    let mut results = None;
+    let mut failures = Vec::new();
+
+    // `hspec` lists failures after a `Failures:` line, each preceded by its location.
+    let mut in_hspec_failures = false;
+    let mut hspec_location = None;
+
+    // `tasty` lists tests in a tree, so we track the enclosing groups and their indentation.
+    let mut tasty_groups: Vec<(usize, &str)> = Vec::new();
+
+    for line in output.lines() {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+
+        if let Ok(summary) = alt((hspec_summary, tasty_summary)).parse_next(&mut &*trimmed) {
+            results
+                .get_or_insert_with(TestResults::default)
+                .merge(summary);
+            in_hspec_failures = false;
+            continue;
+        }
+
+        if line == "Failures:" {
+            in_hspec_failures = true;
+            continue;
+        }
+
+        if in_hspec_failures {
+            if let Some(location) = location(line) {
+                hspec_location = Some(location);
+            } else if let Ok(name) = hspec_failure.parse_next(&mut &*line) {
+                let (path, span) = match hspec_location.take() {
+                    Some((path, span)) => (Some(path), span),
+                    None => (None, PositionRange::default()),
+                };
+                failures.push(TestFailure { name, path, span });
+            }
+            continue;
+        }
+
+        tasty_groups.retain(|(group_indent, _)| *group_indent < indent);
+        match tasty_test.parse_next(&mut &*trimmed) {
+            Ok((name, true)) => {
+                let name = tasty_groups
+                    .iter()
+                    .map(|(_, group)| *group)
+                    .chain([name])
+                    .collect::<Vec<_>>()
+                    .join(".");
+                failures.push(TestFailure {
+                    name,
+                    path: None,
+                    span: PositionRange::default(),
+                });
+            }
+            Ok((_, false)) => {}
+            Err(_) => {
+                if !trimmed.is_empty() {
+                    tasty_groups.push((indent, trimmed.trim_end()));
+                }
+            }
+        }
+    }
+
+    let mut results = results?;
+    results.failures = failures;
+    Some(results)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_hspec_output() {
+        assert_eq!(
+            parse_test_output(indoc!(
+                "
+                MyLib
+                  someFunc
+                    works [✔]
+                    fails [✘]
+                    is pending [‐]
+                      # PENDING: No reason given
+
+                Failures:
+
+                  test/MyLibSpec.hs:12:5:
+                  1) MyLib.someFunc fails
+                       expected: 1
+                        but got: 2
+
+                  To rerun use: --match \"/MyLib/someFunc/fails/\"
+
+                Randomized with seed 1234
+
+                Finished in 0.0012 seconds
+                3 examples, 1 failure, 1 pending
+                "
+            )),
+            Some(TestResults {
+                passed: 1,
+                failed: 1,
+                pending: 1,
+                failures: vec![TestFailure {
+                    name: "MyLib.someFunc fails".into(),
+                    path: Some("test/MyLibSpec.hs".into()),
+                    span: PositionRange::new(12, 5, 12, 5),
+                }],
+            })
+        );
+
+        assert_eq!(
+            parse_test_output("Finished in 0.0001 seconds\n1 example, 0 failures\n"),
+            Some(TestResults {
+                passed: 1,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_tasty_output() {
+        assert_eq!(
+            parse_test_output(indoc!(
+                "
+                Tests
+                  MyLib
+                    works:      OK
+                    fails:      FAIL
+                      test/Main.hs:10:
+                      expected: 1
+                       but got: 2
+                      Use -p '/fails/' to rerun this test only.
+                  Other
+                    works:      OK (0.01s)
+
+                1 out of 3 tests failed (0.01s)
+                "
+            )),
+            Some(TestResults {
+                passed: 2,
+                failed: 1,
+                pending: 0,
+                failures: vec![TestFailure {
+                    name: "Tests.MyLib.fails".into(),
+                    path: None,
+                    span: PositionRange::default(),
+                }],
+            })
+        );
+
+        assert_eq!(
+            parse_test_output("Tests\n  works: OK\n\nAll 1 tests passed (0.00s)\n"),
+            Some(TestResults {
+                passed: 1,
+                ..Default::default()
+            })
+        );
+    }
+
+    #[test]
+    fn test_parse_test_output_no_summary() {
+        assert_eq!(parse_test_output(""), None);
+        assert_eq!(parse_test_output("()\n"), None);
+        assert_eq!(parse_test_output("3 examples\n"), None);
+    }
+
+    #[test]
+    fn test_to_diagnostics() {
+        let results = TestResults {
+            passed: 2,
+            failed: 1,
+            ..Default::default()
+        };
+        assert_eq!(
+            results
+                .to_diagnostics()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>(),
+            ["<no location info>: error:\n    Tests failed: 2 passed, 1 failed\n"]
+        );
+    }
+
+    #[test]
+    fn test_test_failure_to_diagnostic() {
+        let failure = TestFailure {
+            name: "MyLib.someFunc fails".into(),
+            path: Some("test/MyLibSpec.hs".into()),
+            span: PositionRange::new(12, 5, 12, 5),
+        };
+        assert_eq!(
+            failure.to_diagnostic().to_string(),
+            "test/MyLibSpec.hs:12:5: error:\n    Test failed: MyLib.someFunc fails\n"
+        );
+    }
+}
@@ -0,0 +1,34 @@
+use std::time::Duration;
+use std::time::SystemTime;
+
+use crate::event_filter::FileEvent;
+use crate::hooks::LifecycleEvent;
+
+use super::parse::CompilationResult;
+use super::CompilationLog;
+
+/// A record of a completed startup, reload, or restart, for browsing the session's history.
+#[derive(Debug, Clone)]
+pub struct ReloadRecord {
+    /// When the compilation finished.
+    pub timestamp: SystemTime,
+    /// What kind of compilation this was; one of [`LifecycleEvent::Startup`],
+    /// [`LifecycleEvent::Reload`], or [`LifecycleEvent::Restart`].
+    pub event: LifecycleEvent,
+    /// The file changes which triggered this compilation, if any.
+    ///
+    /// This is empty for the initial startup and for reloads or restarts requested by the user.
+    pub changes: Vec<FileEvent>,
+    /// How long the compilation took.
+    pub duration: Duration,
+    /// The compilation result and diagnostics, and the results of the hooks and tests which ran
+    /// after it.
+    pub log: CompilationLog,
+}
+
+impl ReloadRecord {
+    /// Get the result of compilation.
+    pub fn result(&self) -> Option<CompilationResult> {
+        self.log.result()
+    }
+}
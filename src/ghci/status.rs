@@ -0,0 +1,191 @@
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::watch;
+
+use super::parse::CompilationSummary;
+use super::parse::CompilingProgress;
+use super::parse::Severity;
+use super::CompilationLog;
+
+/// What the `ghci` session is doing right now.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum GhciPhase {
+    /// The session is starting up or restarting.
+    #[default]
+    Starting,
+    /// Modules are being reloaded, but none have started compiling yet.
+    Reloading,
+    /// Modules are being compiled.
+    Compiling(ReloadProgress),
+    /// Test or eval commands are running.
+    RunningTests,
+    /// Waiting for changes.
+    Idle,
+}
+
+/// How far along a compilation is, and how long it's expected to take.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReloadProgress {
+    /// The position of the module being compiled.
+    pub progress: CompilingProgress,
+    /// The name of the module being compiled.
+    pub module: String,
+    /// The estimated time until compilation finishes, if there's enough history to estimate it.
+    pub remaining: Option<Duration>,
+}
+
+impl Display for ReloadProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[{}/{}] Compiling {}",
+            self.progress.current, self.progress.total, self.module
+        )?;
+        if let Some(remaining) = self.remaining {
+            // Sub-second precision is just noise here.
+            let remaining = Duration::from_secs(remaining.as_secs_f64().ceil() as u64);
+            write!(f, ", ~{} left", humantime::format_duration(remaining))?;
+        }
+        Ok(())
+    }
+}
+
+/// The state of the `ghci` session, for display in a status bar.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GhciStatus {
+    /// What the session is doing right now.
+    pub phase: GhciPhase,
+    /// How long the most recent startup, reload, or restart took.
+    pub last_duration: Option<Duration>,
+    /// The summary from the most recent compilation, including the number of modules loaded.
+    pub summary: Option<CompilationSummary>,
+    /// The number of errors from the most recent compilation.
+    pub errors: usize,
+    /// The number of warnings from the most recent compilation.
+    pub warnings: usize,
+}
+
+impl GhciStatus {
+    /// Record the results of a finished compilation.
+    fn finish_compilation(&mut self, log: &CompilationLog, duration: Duration) {
+        self.last_duration = Some(duration);
+        self.summary = log.summary;
+        self.errors = log
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .count();
+        self.warnings = log.diagnostics.len() - self.errors;
+    }
+}
+
+/// A cheaply-clonable handle for publishing [`GhciStatus`] updates.
+///
+/// Use [`StatusSender::subscribe`] to watch the status, e.g. to draw it in the TUI.
+#[derive(Debug, Clone)]
+pub struct StatusSender(Arc<watch::Sender<GhciStatus>>);
+
+impl Default for StatusSender {
+    fn default() -> Self {
+        Self(Arc::new(watch::channel(Default::default()).0))
+    }
+}
+
+impl StatusSender {
+    /// Get a receiver for status updates.
+    pub fn subscribe(&self) -> watch::Receiver<GhciStatus> {
+        self.0.subscribe()
+    }
+
+    /// Set the session's current phase.
+    pub fn set_phase(&self, phase: GhciPhase) {
+        self.0.send_if_modified(|status| {
+            let modified = status.phase != phase;
+            status.phase = phase;
+            modified
+        });
+    }
+
+    /// Record the results of a finished compilation.
+    pub fn finish_compilation(&self, log: &CompilationLog, duration: Duration) {
+        self.0
+            .send_modify(|status| status.finish_compilation(log, duration));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::ghci::parse::CompilationResult;
+    use crate::ghci::parse::GhcDiagnostic;
+
+    #[test]
+    fn test_display_reload_progress() {
+        let mut progress = ReloadProgress {
+            progress: CompilingProgress {
+                current: 37,
+                total: 412,
+            },
+            module: "Foo.Bar".to_owned(),
+            remaining: None,
+        };
+        assert_eq!(progress.to_string(), "[37/412] Compiling Foo.Bar");
+        progress.remaining = Some(Duration::from_millis(11_200));
+        assert_eq!(
+            progress.to_string(),
+            "[37/412] Compiling Foo.Bar, ~12s left"
+        );
+        progress.remaining = Some(Duration::from_secs(75));
+        assert_eq!(
+            progress.to_string(),
+            "[37/412] Compiling Foo.Bar, ~1m 15s left"
+        );
+    }
+
+    #[test]
+    fn test_status_sender() {
+        let sender = StatusSender::default();
+        let mut receiver = sender.subscribe();
+        assert_eq!(receiver.borrow_and_update().phase, GhciPhase::Starting);
+
+        sender.set_phase(GhciPhase::Starting);
+        assert!(!receiver.has_changed().unwrap());
+
+        sender.finish_compilation(
+            &CompilationLog {
+                summary: Some(CompilationSummary {
+                    result: CompilationResult::Err,
+                    modules_loaded: 3,
+                }),
+                diagnostics: vec![
+                    GhcDiagnostic::example(Severity::Error),
+                    GhcDiagnostic::example(Severity::Warning),
+                    GhcDiagnostic::example(Severity::Warning),
+                ],
+                ..Default::default()
+            },
+            Duration::from_secs(2),
+        );
+        sender.set_phase(GhciPhase::Idle);
+
+        assert!(receiver.has_changed().unwrap());
+        assert_eq!(
+            *receiver.borrow_and_update(),
+            GhciStatus {
+                phase: GhciPhase::Idle,
+                last_duration: Some(Duration::from_secs(2)),
+                summary: Some(CompilationSummary {
+                    result: CompilationResult::Err,
+                    modules_loaded: 3,
+                }),
+                errors: 1,
+                warnings: 2,
+            }
+        );
+    }
+}
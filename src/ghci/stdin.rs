@@ -15,6 +15,7 @@ use super::CompilationLog;
 use super::GhciCommand;
 use super::ModuleSet;
 use super::PROMPT;
+use crate::ghci::GhciOutput;
 use crate::ghci::GhciStdout;
 
 pub struct GhciStdin {
@@ -27,7 +28,8 @@ pub struct GhciStdin {
 impl GhciStdin {
     /// Write a line on `stdin` and wait for a prompt on stdout.
     ///
-    /// The `line` should contain the trailing newline.
+    /// The `line` should contain the trailing newline. Returns the output printed before the
+    /// prompt.
     ///
     /// The `find` parameter determines where the prompt can be found in the output line.
     #[instrument(skip(self, stdout), level = "debug")]
@@ -37,7 +39,7 @@ impl GhciStdin {
         line: &str,
         find: FindAt,
         log: &mut CompilationLog,
-    ) -> miette::Result<()> {
+    ) -> miette::Result<GhciOutput> {
         self.stdin
             .write_all(line.as_bytes())
             .await
@@ -47,32 +49,34 @@ impl GhciStdin {
 
     /// Write a line on `stdin` and wait for a prompt on stdout.
     ///
-    /// The `line` should contain the trailing newline.
+    /// The `line` should contain the trailing newline. Returns the output printed before the
+    /// prompt.
     async fn write_line(
         &mut self,
         stdout: &mut GhciStdout,
         line: &str,
         log: &mut CompilationLog,
-    ) -> miette::Result<()> {
+    ) -> miette::Result<GhciOutput> {
         self.write_line_with_prompt_at(stdout, line, FindAt::LineStart, log)
             .await
     }
 
     /// Run a [`GhciCommand`].
     ///
-    /// The command may be multiple lines.
+    /// The command may be multiple lines. Returns the output printed by the command.
     #[instrument(skip(self, stdout), level = "debug")]
     pub async fn run_command(
         &mut self,
         stdout: &mut GhciStdout,
         command: &GhciCommand,
         log: &mut CompilationLog,
-    ) -> miette::Result<()> {
+    ) -> miette::Result<GhciOutput> {
+        let mut output = GhciOutput::default();
         for line in command.lines() {
-            self.write_line(stdout, &format!("{line}\n"), log).await?;
+            output.push(self.write_line(stdout, &format!("{line}\n"), log).await?);
         }
 
-        Ok(())
+        Ok(output)
     }
 
     #[instrument(skip(self, stdout), name = "stdin_initialize", level = "debug")]
@@ -101,7 +105,8 @@ impl GhciStdin {
         stdout: &mut GhciStdout,
         log: &mut CompilationLog,
     ) -> miette::Result<()> {
-        self.write_line(stdout, ":reload\n", log).await
+        self.write_line(stdout, ":reload\n", log).await?;
+        Ok(())
     }
 
     #[instrument(skip_all, level = "debug")]
@@ -120,7 +125,8 @@ impl GhciStdin {
         //
         // https://downloads.haskell.org/ghc/latest/docs/users_guide/ghci.html#ghci-cmd-:load
         self.write_line(stdout, &format!(":add {modules}\n"), log)
-            .await
+            .await?;
+        Ok(())
     }
 
     #[instrument(skip_all, level = "debug")]
@@ -132,7 +138,8 @@ impl GhciStdin {
     ) -> miette::Result<()> {
         let modules = modules.into_iter().format(" ");
         self.write_line(stdout, &format!(":unadd {modules}\n"), log)
-            .await
+            .await?;
+        Ok(())
     }
 
     #[instrument(skip(self, stdout), level = "debug")]
@@ -145,7 +152,8 @@ impl GhciStdin {
         // `:add *` forces the module to be interpreted, even if it was already loaded from
         // bytecode. This is necessary to access the module's top-level binds for the eval feature.
         self.write_line(stdout, &format!(":add *{module}\n"), log)
-            .await
+            .await?;
+        Ok(())
     }
 
     #[instrument(skip(self, stdout), level = "debug")]
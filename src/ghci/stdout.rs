@@ -21,6 +21,23 @@ use super::writer::GhciWriter;
 use super::CompilationLog;
 use super::ModuleSet;
 
+/// Output printed by `ghci` in response to a command.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GhciOutput {
+    /// Output printed on `stdout`.
+    pub stdout: String,
+    /// Output printed on `stderr`, like uncaught exceptions.
+    pub stderr: String,
+}
+
+impl GhciOutput {
+    /// Append another command's output to this output.
+    pub fn push(&mut self, other: GhciOutput) {
+        self.stdout.push_str(&other.stdout);
+        self.stderr.push_str(&other.stderr);
+    }
+}
+
 pub struct GhciStdout {
     /// Reader for parsing and forwarding the underlying stdout stream.
     pub reader: IncrementalReader<ChildStdout, GhciWriter>,
@@ -34,8 +51,11 @@ pub struct GhciStdout {
 }
 
 impl GhciStdout {
+    /// Parse compiler messages from `data` and `ghci`'s `stderr` into the `log`.
+    ///
+    /// Returns the output `ghci` printed on `stderr`.
     #[instrument(skip_all, level = "debug")]
-    async fn parse_into_log(&self, data: &str, log: &mut CompilationLog) -> miette::Result<()> {
+    async fn parse_into_log(&self, data: &str, log: &mut CompilationLog) -> miette::Result<String> {
         // Parse GHCi output into compiler messages.
         //
         // These include diagnostics, which modules were compiled, and a compilation summary.
@@ -49,7 +69,7 @@ impl GhciStdout {
         };
         log.extend(parse_ghc_messages(data).wrap_err("Failed to parse compiler output")?);
         log.extend(parse_ghc_messages(&stderr_data).wrap_err("Failed to parse compiler output")?);
-        Ok(())
+        Ok(stderr_data)
     }
 
     #[instrument(skip_all, name = "stdout_initialize", level = "debug")]
@@ -76,8 +96,15 @@ impl GhciStdout {
         Ok(())
     }
 
+    /// Wait for a prompt, parsing compiler output into the `log`.
+    ///
+    /// Returns the output `ghci` printed before the prompt.
     #[instrument(skip_all, level = "debug")]
-    pub async fn prompt(&mut self, find: FindAt, log: &mut CompilationLog) -> miette::Result<()> {
+    pub async fn prompt(
+        &mut self,
+        find: FindAt,
+        log: &mut CompilationLog,
+    ) -> miette::Result<GhciOutput> {
         self.stderr_sender
             .send(StderrEvent::ClearBuffer)
             .await
@@ -94,8 +121,11 @@ impl GhciStdout {
             .await?;
         tracing::debug!(bytes = data.len(), "Got data from ghci");
 
-        self.parse_into_log(&data, log).await?;
-        Ok(())
+        let stderr = self.parse_into_log(&data, log).await?;
+        Ok(GhciOutput {
+            stdout: data,
+            stderr,
+        })
     }
 
     #[instrument(skip_all, level = "debug")]
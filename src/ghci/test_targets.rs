@@ -0,0 +1,155 @@
+use std::collections::BTreeSet;
+
+use miette::miette;
+
+use crate::cli::TestOpts;
+
+use super::GhciCommand;
+
+/// The placeholder for the test module name in `--targeted-test-ghci`.
+const MODULE_PLACEHOLDER: &str = "{module}";
+
+/// Maps changed modules to the test modules which should be run after a reload.
+#[derive(Debug, Clone)]
+pub struct TestTargets {
+    /// The command template, containing [`MODULE_PLACEHOLDER`].
+    command: GhciCommand,
+    /// Suffixes which name a source module's test modules, like `Spec`.
+    suffixes: Vec<String>,
+    /// Explicit mappings from source modules to test modules.
+    mappings: Vec<(String, String)>,
+}
+
+impl TestTargets {
+    /// Construct targeted test options from the command-line interface.
+    ///
+    /// Returns `None` if `--targeted-test-ghci` isn't given.
+    pub fn from_cli(opts: &TestOpts) -> miette::Result<Option<Self>> {
+        let Some(command) = &opts.targeted_test_ghci else {
+            return Ok(None);
+        };
+
+        if !command.contains(MODULE_PLACEHOLDER) {
+            return Err(miette!(
+                "`--targeted-test-ghci` must contain `{MODULE_PLACEHOLDER}`, but got `{command}`"
+            ));
+        }
+
+        Ok(Some(Self {
+            command: command.clone(),
+            suffixes: opts.test_module_suffixes.clone(),
+            mappings: opts.test_modules.clone(),
+        }))
+    }
+
+    /// Get the test modules affected by the given changed modules.
+    ///
+    /// Only test modules in `loaded` are returned.
+    pub fn affected<'a>(
+        &self,
+        changed: impl IntoIterator<Item = &'a str>,
+        loaded: &BTreeSet<String>,
+    ) -> BTreeSet<String> {
+        let mut affected = BTreeSet::new();
+        for module in changed {
+            if self.is_test_module(module) {
+                affected.insert(module.to_owned());
+            }
+            affected.extend(
+                self.suffixes
+                    .iter()
+                    .map(|suffix| format!("{module}{suffix}")),
+            );
+            affected.extend(
+                self.mappings
+                    .iter()
+                    .filter(|(source, _)| source == module)
+                    .map(|(_, test_module)| test_module.clone()),
+            );
+        }
+        affected.retain(|module| loaded.contains(module));
+        affected
+    }
+
+    /// Get the command to run the tests in the given test module.
+    pub fn command(&self, test_module: &str) -> GhciCommand {
+        GhciCommand(self.command.replace(MODULE_PLACEHOLDER, test_module))
+    }
+
+    fn is_test_module(&self, module: &str) -> bool {
+        self.suffixes.iter().any(|suffix| module.ends_with(suffix))
+            || self
+                .mappings
+                .iter()
+                .any(|(_, test_module)| test_module == module)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    fn test_targets() -> TestTargets {
+        TestTargets::from_cli(&TestOpts {
+            targeted_test_ghci: Some(GhciCommand("Test.Hspec.hspec {module}.spec".into())),
+            test_module_suffixes: vec!["Spec".into(), "Test".into()],
+            test_modules: vec![("My.Db".into(), "My.IntegrationSpec".into())],
+        })
+        .unwrap()
+        .unwrap()
+    }
+
+    #[test]
+    fn test_from_cli() {
+        let mut opts = TestOpts {
+            targeted_test_ghci: None,
+            test_module_suffixes: vec![],
+            test_modules: vec![],
+        };
+        assert!(TestTargets::from_cli(&opts).unwrap().is_none());
+
+        opts.targeted_test_ghci = Some(GhciCommand("Test.Hspec.hspec spec".into()));
+        assert!(TestTargets::from_cli(&opts).is_err());
+    }
+
+    #[test]
+    fn test_affected() {
+        let targets = test_targets();
+        let loaded: BTreeSet<String> = [
+            "My.Db",
+            "My.DbSpec",
+            "My.IntegrationSpec",
+            "My.Lib",
+            "My.LibTest",
+            "My.Other",
+            "My.OtherSpec",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect();
+
+        assert_eq!(
+            targets
+                .affected(["My.Lib", "My.Db"], &loaded)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            ["My.DbSpec", "My.IntegrationSpec", "My.LibTest"]
+        );
+
+        // Test modules run themselves, and unloaded test modules are skipped.
+        assert_eq!(
+            targets
+                .affected(["My.OtherSpec", "My.Unknown"], &loaded)
+                .into_iter()
+                .collect::<Vec<_>>(),
+            ["My.OtherSpec"]
+        );
+
+        assert_eq!(
+            targets.command("My.DbSpec"),
+            GhciCommand("Test.Hspec.hspec My.DbSpec.spec".into())
+        );
+    }
+}
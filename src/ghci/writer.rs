@@ -25,6 +25,82 @@ enum Kind {
     Stderr(Stderr),
     DuplexStream(Compat<Arc<Mutex<Compat<DuplexStream>>>>),
     Sink(Sink),
+    Prefixed(Box<Prefixed>),
+}
+
+/// A writer which adds a prefix to the start of each line.
+#[derive(Debug)]
+struct Prefixed {
+    inner: GhciWriter,
+    prefix: String,
+    /// Are we at the start of a line?
+    line_start: bool,
+    /// Output which has been accepted but not yet written to `inner`.
+    buffer: Vec<u8>,
+}
+
+impl Prefixed {
+    fn new(inner: GhciWriter, prefix: String) -> Self {
+        Self {
+            inner,
+            prefix,
+            line_start: true,
+            buffer: Vec::new(),
+        }
+    }
+
+    fn poll_write_buffer(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        while !self.buffer.is_empty() {
+            match Pin::new(&mut self.inner).poll_write(cx, &self.buffer) {
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                }
+                Poll::Ready(Ok(written)) => {
+                    self.buffer.drain(..written);
+                }
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_write(&mut self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize, io::Error>> {
+        // Don't accept more output until the last write is finished.
+        match self.poll_write_buffer(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+            Poll::Pending => return Poll::Pending,
+        }
+
+        for &byte in buf {
+            if self.line_start {
+                self.buffer.extend_from_slice(self.prefix.as_bytes());
+            }
+            self.buffer.push(byte);
+            self.line_start = byte == b'\n';
+        }
+
+        // The output is buffered, so it's fine if this doesn't finish yet.
+        match self.poll_write_buffer(cx) {
+            Poll::Ready(Err(err)) => Poll::Ready(Err(err)),
+            Poll::Ready(Ok(())) | Poll::Pending => Poll::Ready(Ok(buf.len())),
+        }
+    }
+
+    fn poll_flush(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        match self.poll_write_buffer(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut self.inner).poll_flush(cx),
+            ret => ret,
+        }
+    }
+
+    fn poll_shutdown(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), io::Error>> {
+        match self.poll_write_buffer(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut self.inner).poll_shutdown(cx),
+            ret => ret,
+        }
+    }
 }
 
 impl GhciWriter {
@@ -49,6 +125,11 @@ impl GhciWriter {
     pub fn sink() -> Self {
         Self(Kind::Sink(tokio::io::sink()))
     }
+
+    /// Write to this writer, with the given prefix at the start of each line.
+    pub fn with_prefix(self, prefix: impl Into<String>) -> Self {
+        Self(Kind::Prefixed(Box::new(Prefixed::new(self, prefix.into()))))
+    }
 }
 
 impl AsyncWrite for GhciWriter {
@@ -62,6 +143,7 @@ impl AsyncWrite for GhciWriter {
             Kind::Stderr(ref mut x) => Pin::new(x).poll_write(cx, buf),
             Kind::DuplexStream(ref mut x) => Pin::new(x).poll_write(cx, buf),
             Kind::Sink(ref mut x) => Pin::new(x).poll_write(cx, buf),
+            Kind::Prefixed(ref mut x) => x.poll_write(cx, buf),
         }
     }
 
@@ -71,6 +153,7 @@ impl AsyncWrite for GhciWriter {
             Kind::Stderr(ref mut x) => Pin::new(x).poll_flush(cx),
             Kind::DuplexStream(ref mut x) => Pin::new(x).poll_flush(cx),
             Kind::Sink(ref mut x) => Pin::new(x).poll_flush(cx),
+            Kind::Prefixed(ref mut x) => x.poll_flush(cx),
         }
     }
 
@@ -80,6 +163,7 @@ impl AsyncWrite for GhciWriter {
             Kind::Stderr(ref mut x) => Pin::new(x).poll_shutdown(cx),
             Kind::DuplexStream(ref mut x) => Pin::new(x).poll_shutdown(cx),
             Kind::Sink(ref mut x) => Pin::new(x).poll_shutdown(cx),
+            Kind::Prefixed(ref mut x) => x.poll_shutdown(cx),
         }
     }
 }
@@ -91,6 +175,35 @@ impl Clone for GhciWriter {
             Kind::Stderr(_) => Self::stderr(),
             Kind::DuplexStream(x) => Self(Kind::DuplexStream(x.clone())),
             Kind::Sink(_) => Self::sink(),
+            Kind::Prefixed(x) => x.inner.clone().with_prefix(x.prefix.clone()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn test_with_prefix() {
+        let (writer, mut reader) = tokio::io::duplex(1024);
+        let mut writer = GhciWriter::duplex_stream(writer).with_prefix("[lib] ");
+        writer
+            .write_all(b"Ok, one module loaded.\nghci")
+            .await
+            .unwrap();
+        writer.write_all(b"> \n\nBye\n").await.unwrap();
+        writer.shutdown().await.unwrap();
+        drop(writer);
+
+        let mut output = String::new();
+        reader.read_to_string(&mut output).await.unwrap();
+        assert_eq!(
+            output,
+            "[lib] Ok, one module loaded.\n[lib] ghci> \n[lib] \n[lib] Bye\n"
+        );
+    }
+}
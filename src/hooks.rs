@@ -4,17 +4,31 @@ use std::fmt::Display;
 use std::fmt::Write;
 use std::process::ExitStatus;
 use std::str::FromStr;
+use std::time::Duration;
 
-use clap::builder::ValueParserFactory;
 use clap::Arg;
 use clap::ArgAction;
 use clap::Args;
 use clap::FromArgMatches;
 use enum_iterator::Sequence;
 use indoc::indoc;
+use itertools::Itertools;
 use tokio::task::JoinHandle;
-
+use tokio_util::sync::CancellationToken;
+use winnow::combinator::delimited;
+use winnow::combinator::opt;
+use winnow::combinator::rest;
+use winnow::token::take_till;
+use winnow::PResult;
+use winnow::Parser;
+
+use camino::Utf8PathBuf;
+
+use crate::ghci::parse::CompilationResult;
+use crate::ghci::parse::Severity;
+use crate::ghci::CompilationLog;
 use crate::ghci::GhciCommand;
+use crate::ignore::GlobMatcher;
 use crate::maybe_async_command::MaybeAsyncCommand;
 
 /// A lifecycle event that triggers hooks.
@@ -28,6 +42,14 @@ pub enum LifecycleEvent {
     Reload(When),
     /// When a `ghci` session is restarted (when a module is removed or renamed).
     Restart(When),
+    /// When compilation succeeds (after startup, reloads, and restarts).
+    Success,
+    /// When compilation fails (after startup, reloads, and restarts).
+    Failure,
+    /// When compilation succeeds after the previous compilation failed.
+    Fixed,
+    /// When compilation fails after the previous compilation succeeded.
+    Broken,
 }
 
 impl Display for LifecycleEvent {
@@ -40,6 +62,26 @@ impl Display for LifecycleEvent {
 }
 
 impl LifecycleEvent {
+    /// Get the events for a compilation's result: [`LifecycleEvent::Success`] or
+    /// [`LifecycleEvent::Failure`], followed by [`LifecycleEvent::Fixed`] or
+    /// [`LifecycleEvent::Broken`] if the result differs from the previous compilation's.
+    pub fn for_result(
+        previous: Option<CompilationResult>,
+        result: Option<CompilationResult>,
+    ) -> Vec<Self> {
+        match (previous, result) {
+            (_, None) => vec![],
+            (Some(CompilationResult::Err), Some(CompilationResult::Ok)) => {
+                vec![LifecycleEvent::Success, LifecycleEvent::Fixed]
+            }
+            (Some(CompilationResult::Ok), Some(CompilationResult::Err)) => {
+                vec![LifecycleEvent::Failure, LifecycleEvent::Broken]
+            }
+            (_, Some(CompilationResult::Ok)) => vec![LifecycleEvent::Success],
+            (_, Some(CompilationResult::Err)) => vec![LifecycleEvent::Failure],
+        }
+    }
+
     /// Get the event name, like `test` or `reload`.
     pub fn event_name(&self) -> &'static str {
         match self {
@@ -47,6 +89,10 @@ impl LifecycleEvent {
             LifecycleEvent::Startup(_) => "startup",
             LifecycleEvent::Reload(_) => "reload",
             LifecycleEvent::Restart(_) => "restart",
+            LifecycleEvent::Success => "on-success",
+            LifecycleEvent::Failure => "on-failure",
+            LifecycleEvent::Fixed => "on-fixed",
+            LifecycleEvent::Broken => "on-broken",
         }
     }
 
@@ -57,6 +103,10 @@ impl LifecycleEvent {
             LifecycleEvent::Startup(_) => "starting up",
             LifecycleEvent::Reload(_) => "reloading",
             LifecycleEvent::Restart(_) => "restarting",
+            LifecycleEvent::Success => "succeeding",
+            LifecycleEvent::Failure => "failing",
+            LifecycleEvent::Fixed => "fixing",
+            LifecycleEvent::Broken => "breaking",
         }
     }
 
@@ -82,19 +132,47 @@ impl LifecycleEvent {
                 The GHCi session must be restarted when `.cabal` or `.ghci` files are modified.
                 "
             ),
+            LifecycleEvent::Success => indoc!(
+                "
+                Success hooks run when GHCi starts up, reloads, or restarts without compilation errors, after the startup, reload, or restart hooks and before tests.
+                "
+            ),
+            LifecycleEvent::Failure => indoc!(
+                "
+                Failure hooks run when GHCi starts up, reloads, or restarts with compilation errors, after the startup, reload, or restart hooks.
+                "
+            ),
+            LifecycleEvent::Fixed => indoc!(
+                "
+                Fixed hooks run when compilation succeeds after the previous compilation failed, after the success hooks.
+                "
+            ),
+            LifecycleEvent::Broken => indoc!(
+                "
+                Broken hooks run when compilation fails after the previous compilation succeeded, after the failure hooks.
+                "
+            ),
         }.trim_end_matches('\n')
     }
 
     fn get_help_name(&self) -> Option<&'static str> {
         match self {
             LifecycleEvent::Test => Some("tests"),
+            LifecycleEvent::Success => Some("when compilation succeeds"),
+            LifecycleEvent::Failure => Some("when compilation fails"),
+            LifecycleEvent::Fixed => Some("when compilation is fixed"),
+            LifecycleEvent::Broken => Some("when compilation breaks"),
             _ => None,
         }
     }
 
     fn when(&self) -> Option<When> {
         match &self {
-            LifecycleEvent::Test => None,
+            LifecycleEvent::Test
+            | LifecycleEvent::Success
+            | LifecycleEvent::Failure
+            | LifecycleEvent::Fixed
+            | LifecycleEvent::Broken => None,
             LifecycleEvent::Startup(when) => Some(*when),
             LifecycleEvent::Reload(when) => Some(*when),
             LifecycleEvent::Restart(when) => Some(*when),
@@ -107,7 +185,11 @@ impl LifecycleEvent {
             LifecycleEvent::Startup(When::After)
             | LifecycleEvent::Test
             | LifecycleEvent::Reload(_)
-            | LifecycleEvent::Restart(_) => {
+            | LifecycleEvent::Restart(_)
+            | LifecycleEvent::Success
+            | LifecycleEvent::Failure
+            | LifecycleEvent::Fixed
+            | LifecycleEvent::Broken => {
                 vec![CommandKind::Ghci, CommandKind::Shell]
             }
         }
@@ -117,6 +199,7 @@ impl LifecycleEvent {
         enum_iterator::all::<Self>().flat_map(|event| {
             event.supported_kind().into_iter().map(move |kind| Hook {
                 event,
+                when_changed: None,
                 command: kind,
             })
         })
@@ -197,11 +280,49 @@ impl Display for Command {
     }
 }
 
+/// A hook command, as given on the command line.
+///
+/// Commands can be prefixed with `when-changed=GLOBS:` to only run them when a path matching
+/// one of the space-separated `GLOBS` triggered the compilation, like
+/// `when-changed=package.yaml:hpack`.
+#[derive(Debug, Clone)]
+pub struct FilteredCommand<C> {
+    /// Only run the command when a path matching these globs changed.
+    pub when_changed: Option<GlobMatcher>,
+    /// The command to run.
+    pub command: C,
+}
+
+impl<C> FilteredCommand<C> {
+    /// Parse a command with an optional `when-changed=GLOBS:` prefix, parsing the rest of the
+    /// input with the given function.
+    pub fn parse(
+        input: &str,
+        parse_command: impl FnOnce(&str) -> miette::Result<C>,
+    ) -> miette::Result<Self> {
+        let (globs, command) = (opt(parse_when_changed), rest)
+            .parse(input)
+            .map_err(|err| miette::miette!("{err}"))?;
+        Ok(Self {
+            when_changed: globs
+                .map(|globs| GlobMatcher::from_globs(globs.split_whitespace()))
+                .transpose()?,
+            command: parse_command(command)?,
+        })
+    }
+}
+
+fn parse_when_changed<'i>(input: &mut &'i str) -> PResult<&'i str> {
+    delimited("when-changed=", take_till(1.., ':'), ':').parse_next(input)
+}
+
 /// A lifecycle hook, specifying a command to run and an event to run it at.
 #[derive(Debug, Clone)]
 pub struct Hook<C> {
     /// The event to run this hook on.
     pub event: LifecycleEvent,
+    /// If given, only run this hook when a path matching these globs triggered the compilation.
+    pub when_changed: Option<GlobMatcher>,
     /// The command to run.
     pub command: C,
 }
@@ -213,11 +334,45 @@ impl<C> Display for Hook<C> {
 }
 
 impl<C> Hook<C> {
-    fn with_command<C2>(&self, command: C2) -> Hook<C2> {
+    fn with_command<C2>(&self, command: &FilteredCommand<impl Clone + Into<C2>>) -> Hook<C2> {
         Hook {
             event: self.event,
-            command,
+            when_changed: command.when_changed.clone(),
+            command: command.command.clone().into(),
+        }
+    }
+
+    /// Should this hook run for a compilation triggered by the paths in `env`?
+    ///
+    /// Hooks without a `when-changed` filter always run, as do all hooks for compilations which
+    /// weren't triggered by any paths, like the initial startup.
+    pub fn should_run(&self, env: &HookEnv) -> bool {
+        let globs = match &self.when_changed {
+            Some(globs) => globs,
+            None => return true,
+        };
+        let mut paths = env
+            .changed
+            .iter()
+            .chain(&env.added)
+            .chain(&env.removed)
+            .peekable();
+        if paths.peek().is_none() {
+            return true;
         }
+        paths.any(|path| globs.matched(path).is_whitelist())
+    }
+}
+
+impl From<GhciCommand> for Command {
+    fn from(command: GhciCommand) -> Self {
+        Self::Ghci(command)
+    }
+}
+
+impl From<MaybeAsyncCommand> for Command {
+    fn from(command: MaybeAsyncCommand) -> Self {
+        Self::Shell(command)
     }
 }
 
@@ -239,6 +394,11 @@ impl Hook<CommandKind> {
                 Example: `TestMain.testMain`.
                 ",
             )),
+            (LifecycleEvent::Success, CommandKind::Shell) => Some(indoc!(
+                "
+                Example: `async:fourmolu --mode inplace src`.
+                ",
+            )),
             _ => None,
         }
         .map(|help| help.trim_end_matches('\n'))
@@ -249,7 +409,7 @@ impl Hook<CommandKind> {
     }
 
     fn help(&self) -> Help {
-        let Hook { event, command } = self;
+        let Hook { event, command, .. } = self;
         let kind = match command {
             CommandKind::Ghci => "`ghci`",
             CommandKind::Shell => "Shell",
@@ -275,7 +435,16 @@ impl Hook<CommandKind> {
         long.push_str(event.get_message());
 
         if let CommandKind::Shell = command {
-            long.push_str("\n\nCommands starting with `async:` will be run in the background.");
+            long.push_str(indoc!(
+                "
+
+                Commands starting with `async:` will be run in the background. Other options can be \
+                given before the colon, separated by commas: `timeout=DURATION` kills the command \
+                if it runs too long, `on-failure=ignore|warn|abort|shutdown` determines what \
+                happens when it fails (`abort` and `shutdown` can't be used with `async`), and \
+                `cancel-on-reload` runs the command in the background and kills it when the next \
+                reload starts."
+            ));
         }
 
         if let Some(extra_help) = self.extra_help() {
@@ -283,6 +452,13 @@ impl Hook<CommandKind> {
             long.push_str(extra_help);
         }
 
+        long.push_str(
+            "\n\nCommands starting with `when-changed=GLOBS:` will only run when a path matching one \
+            of the space-separated `GLOBS` triggered the reload or restart. Paths other than \
+            Haskell modules only trigger a reload or restart if they match a `--reload-glob` or \
+            `--restart-glob`.",
+        );
+
         long.push_str("\n\nCan be given multiple times.");
 
         Help { short, long }
@@ -304,19 +480,42 @@ pub struct HookOpts {
 }
 
 impl HookOpts {
-    pub fn select(&self, event: LifecycleEvent) -> impl Iterator<Item = &Hook<Command>> {
-        self.hooks.iter().filter(move |hook| hook.event == event)
+    /// Get the hooks to run for an event, for a compilation triggered by the paths in `env`.
+    pub fn select<'a>(
+        &'a self,
+        event: LifecycleEvent,
+        env: &'a HookEnv,
+    ) -> impl Iterator<Item = &'a Hook<Command>> {
+        self.hooks.iter().filter(move |hook| {
+            if hook.event != event {
+                false
+            } else if hook.should_run(env) {
+                true
+            } else {
+                tracing::debug!(command = %hook.command, "No matching paths changed, skipping {hook} command");
+                false
+            }
+        })
     }
 
     pub async fn run_shell_hooks(
         &self,
         event: LifecycleEvent,
+        env: &HookEnv,
         handles: &mut Vec<JoinHandle<miette::Result<ExitStatus>>>,
+        cancel: &CancellationToken,
     ) -> miette::Result<()> {
-        for hook in self.select(event) {
+        for hook in self.select(event, env) {
             if let Command::Shell(command) = &hook.command {
                 tracing::info!(%command, "Running {hook} command");
-                command.run_on(handles).await?;
+                let flow = command
+                    .with_env(env.vars(event, &Default::default()))
+                    .run_on(handles, cancel)
+                    .await?;
+                if flow.is_break() {
+                    tracing::warn!("Skipping the remaining {event} hooks");
+                    break;
+                }
             }
         }
         Ok(())
@@ -338,8 +537,12 @@ impl Args for HookOpts {
                 .help_heading("Lifecycle hooks");
 
             let arg = match hook.command {
-                CommandKind::Ghci => arg.value_parser(GhciCommand::value_parser()),
-                CommandKind::Shell => arg.value_parser(MaybeAsyncCommand::from_str),
+                CommandKind::Ghci => arg.value_parser(|input: &str| {
+                    FilteredCommand::parse(input, |command| Ok(GhciCommand(command.to_owned())))
+                }),
+                CommandKind::Shell => arg.value_parser(|input: &str| {
+                    FilteredCommand::parse(input, MaybeAsyncCommand::from_str)
+                }),
             };
 
             cmd = cmd.arg(arg);
@@ -366,19 +569,19 @@ impl FromArgMatches for HookOpts {
                 CommandKind::Ghci => {
                     self.hooks.extend(
                         matches
-                            .get_many::<GhciCommand>(&name)
+                            .get_many::<FilteredCommand<GhciCommand>>(&name)
                             .into_iter()
                             .flatten()
-                            .map(|command| hook.with_command(Command::Ghci(command.clone()))),
+                            .map(|command| hook.with_command::<Command>(command)),
                     );
                 }
                 CommandKind::Shell => {
                     self.hooks.extend(
                         matches
-                            .get_many::<MaybeAsyncCommand>(&name)
+                            .get_many::<FilteredCommand<MaybeAsyncCommand>>(&name)
                             .into_iter()
                             .flatten()
-                            .map(|command| hook.with_command(Command::Shell(command.clone()))),
+                            .map(|command| hook.with_command::<Command>(command)),
                     );
                 }
             }
@@ -394,3 +597,256 @@ impl FromArgMatches for HookOpts {
         Ok(())
     }
 }
+
+/// Context about the compilation which triggered a hook, passed to shell hooks as environment
+/// variables:
+///
+/// - `GHCIWATCH_EVENT`: The hook's event, like `after-reload` or `on-fixed`.
+/// - `GHCIWATCH_SESSION`: The session's name, if several sessions are running.
+/// - `GHCIWATCH_CHANGED_PATHS`, `GHCIWATCH_ADDED_PATHS`, `GHCIWATCH_REMOVED_PATHS`: The paths
+///   which triggered the reload or restart, one per line.
+/// - `GHCIWATCH_RESULT`: `ok` or `error`, once compilation has finished.
+/// - `GHCIWATCH_ERRORS`, `GHCIWATCH_WARNINGS`: The number of errors and warnings, once
+///   compilation has finished.
+/// - `GHCIWATCH_DURATION_MS`: How long compilation took, once it has finished.
+/// - `GHCIWATCH_ERROR_FILE`, `GHCIWATCH_ERROR_FILE_JSON`: The error file paths, if set.
+#[derive(Debug, Clone, Default)]
+pub struct HookEnv {
+    /// The session's name, if several sessions are running.
+    pub session: Option<String>,
+    /// The path the error log is written to, if any.
+    pub error_file: Option<Utf8PathBuf>,
+    /// The path the JSON error log is written to, if any.
+    pub error_file_json: Option<Utf8PathBuf>,
+    /// Modified paths which triggered the compilation.
+    pub changed: Vec<Utf8PathBuf>,
+    /// New paths which triggered the compilation.
+    pub added: Vec<Utf8PathBuf>,
+    /// Removed paths which triggered the compilation.
+    pub removed: Vec<Utf8PathBuf>,
+    /// How long compilation took, once it has finished.
+    pub duration: Option<Duration>,
+}
+
+impl HookEnv {
+    /// Start a new compilation, triggered by the given paths.
+    pub fn start(
+        &mut self,
+        changed: Vec<Utf8PathBuf>,
+        added: Vec<Utf8PathBuf>,
+        removed: Vec<Utf8PathBuf>,
+    ) {
+        self.changed = changed;
+        self.added = added;
+        self.removed = removed;
+        self.duration = None;
+    }
+
+    /// Get the environment variables for a hook.
+    ///
+    /// `log` is the compilation log so far; if it has a result, the result and diagnostic counts
+    /// are included.
+    pub fn vars(&self, event: LifecycleEvent, log: &CompilationLog) -> Vec<(&'static str, String)> {
+        let mut vars = vec![
+            ("GHCIWATCH_EVENT", event.to_string()),
+            ("GHCIWATCH_CHANGED_PATHS", self.changed.iter().join("\n")),
+            ("GHCIWATCH_ADDED_PATHS", self.added.iter().join("\n")),
+            ("GHCIWATCH_REMOVED_PATHS", self.removed.iter().join("\n")),
+        ];
+
+        if let Some(session) = &self.session {
+            vars.push(("GHCIWATCH_SESSION", session.clone()));
+        }
+
+        if let Some(result) = log.result() {
+            let errors = log
+                .diagnostics
+                .iter()
+                .filter(|diagnostic| diagnostic.severity == Severity::Error)
+                .count();
+            vars.push((
+                "GHCIWATCH_RESULT",
+                match result {
+                    CompilationResult::Ok => "ok",
+                    CompilationResult::Err => "error",
+                }
+                .to_owned(),
+            ));
+            vars.push(("GHCIWATCH_ERRORS", errors.to_string()));
+            vars.push((
+                "GHCIWATCH_WARNINGS",
+                (log.diagnostics.len() - errors).to_string(),
+            ));
+        }
+
+        if let Some(duration) = self.duration {
+            vars.push(("GHCIWATCH_DURATION_MS", duration.as_millis().to_string()));
+        }
+        if let Some(path) = &self.error_file {
+            vars.push(("GHCIWATCH_ERROR_FILE", path.to_string()));
+        }
+        if let Some(path) = &self.error_file_json {
+            vars.push(("GHCIWATCH_ERROR_FILE_JSON", path.to_string()));
+        }
+
+        vars
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    #[test]
+    fn test_for_result() {
+        use CompilationResult::Err;
+        use CompilationResult::Ok;
+
+        assert_eq!(
+            LifecycleEvent::for_result(None, Some(Ok)),
+            [LifecycleEvent::Success]
+        );
+        assert_eq!(
+            LifecycleEvent::for_result(None, Some(Err)),
+            [LifecycleEvent::Failure]
+        );
+        assert_eq!(
+            LifecycleEvent::for_result(Some(Ok), Some(Ok)),
+            [LifecycleEvent::Success]
+        );
+        assert_eq!(
+            LifecycleEvent::for_result(Some(Err), Some(Ok)),
+            [LifecycleEvent::Success, LifecycleEvent::Fixed]
+        );
+        assert_eq!(
+            LifecycleEvent::for_result(Some(Ok), Some(Err)),
+            [LifecycleEvent::Failure, LifecycleEvent::Broken]
+        );
+        assert_eq!(LifecycleEvent::for_result(Some(Ok), None), []);
+    }
+
+    #[test]
+    fn test_hook_env_vars() {
+        let mut env = HookEnv {
+            error_file: Some("ghcid.txt".into()),
+            ..Default::default()
+        };
+        env.start(
+            vec!["src/A.hs".into(), "src/B.hs".into()],
+            vec!["src/C.hs".into()],
+            vec![],
+        );
+        assert_eq!(
+            env.vars(LifecycleEvent::Reload(When::Before), &Default::default()),
+            [
+                ("GHCIWATCH_EVENT", "before-reload".to_owned()),
+                ("GHCIWATCH_CHANGED_PATHS", "src/A.hs\nsrc/B.hs".to_owned()),
+                ("GHCIWATCH_ADDED_PATHS", "src/C.hs".to_owned()),
+                ("GHCIWATCH_REMOVED_PATHS", "".to_owned()),
+                ("GHCIWATCH_ERROR_FILE", "ghcid.txt".to_owned()),
+            ]
+        );
+
+        env.duration = Some(Duration::from_millis(1500));
+        let log = CompilationLog {
+            summary: Some(crate::ghci::parse::CompilationSummary {
+                result: CompilationResult::Err,
+                modules_loaded: 2,
+            }),
+            ..Default::default()
+        };
+        assert_eq!(
+            env.vars(LifecycleEvent::Broken, &log)[4..],
+            [
+                ("GHCIWATCH_RESULT", "error".to_owned()),
+                ("GHCIWATCH_ERRORS", "0".to_owned()),
+                ("GHCIWATCH_WARNINGS", "0".to_owned()),
+                ("GHCIWATCH_DURATION_MS", "1500".to_owned()),
+                ("GHCIWATCH_ERROR_FILE", "ghcid.txt".to_owned()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_when_changed() {
+        let command = FilteredCommand::parse(
+            "when-changed=package.yaml **/*.graphql:async:hpack",
+            MaybeAsyncCommand::from_str,
+        )
+        .unwrap();
+        assert!(command.command.is_async);
+        assert_eq!(command.command.to_string(), "hpack");
+
+        let hook = Hook {
+            event: LifecycleEvent::Reload(When::Before),
+            when_changed: command.when_changed,
+            command: Command::Shell(command.command),
+        };
+        let mut env = HookEnv::default();
+        // Compilations which weren't triggered by any paths run every hook.
+        assert!(hook.should_run(&env));
+        env.start(vec!["src/MyLib.hs".into()], vec![], vec![]);
+        assert!(!hook.should_run(&env));
+        env.start(vec![], vec!["schema/Query.graphql".into()], vec![]);
+        assert!(hook.should_run(&env));
+        env.start(
+            vec!["src/MyLib.hs".into(), "package.yaml".into()],
+            vec![],
+            vec![],
+        );
+        assert!(hook.should_run(&env));
+
+        let command = FilteredCommand::parse(":set args --fast", |command| {
+            Ok(GhciCommand(command.to_owned()))
+        })
+        .unwrap();
+        assert!(command.when_changed.is_none());
+        assert_eq!(command.command.0, ":set args --fast");
+    }
+
+    #[test]
+    fn test_when_changed_after_manual_restart() {
+        let command = FilteredCommand::parse(
+            "when-changed=package.yaml:hpack",
+            MaybeAsyncCommand::from_str,
+        )
+        .unwrap();
+        let event = LifecycleEvent::Restart(When::Before);
+        let hooks = HookOpts {
+            hooks: vec![Hook {
+                event,
+                when_changed: command.when_changed,
+                command: Command::Shell(command.command),
+            }],
+        };
+
+        // A reload triggered by a path the hook doesn't care about skips it...
+        let mut env = HookEnv::default();
+        env.start(vec!["src/MyLib.hs".into()], vec![], vec![]);
+        assert_eq!(hooks.select(event, &env).count(), 0);
+
+        // ...but a manual restart afterwards isn't filtered by that reload's paths.
+        env.start(vec![], vec![], vec![]);
+        assert_eq!(hooks.select(event, &env).count(), 1);
+    }
+
+    #[test]
+    fn test_hook_arg_names() {
+        let names = LifecycleEvent::hooks()
+            .map(|hook| hook.arg_name())
+            .collect::<Vec<_>>();
+        for name in [
+            "on-success-ghci",
+            "on-failure-shell",
+            "on-fixed-shell",
+            "on-broken-ghci",
+        ] {
+            assert!(
+                names.iter().any(|arg| arg == name),
+                "{name} not in {names:?}"
+            );
+        }
+    }
+}
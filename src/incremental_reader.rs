@@ -41,8 +41,15 @@ pub struct IncrementalReader<R, W> {
     /// We're not guaranteed that the data we read at one time is aligned on a UTF-8 boundary. If
     /// that's the case, we store the data here until we get more data.
     non_utf8: Vec<u8>,
+    /// A function called with each complete line as it's read, if any.
+    ///
+    /// This lets callers observe output like compilation progress before the end marker is seen.
+    on_line: Option<LineCallback>,
 }
 
+/// A function called with each line read by an [`IncrementalReader`].
+pub type LineCallback = Box<dyn FnMut(&str) + Send + Sync>;
+
 impl<R, W> IncrementalReader<R, W>
 where
     R: AsyncRead,
@@ -56,6 +63,7 @@ where
             lines: String::with_capacity(VEC_BUFFER_CAPACITY * LINE_BUFFER_CAPACITY),
             line: String::with_capacity(LINE_BUFFER_CAPACITY),
             non_utf8: Vec::with_capacity(SPLIT_UTF8_CODEPOINT_CAPACITY),
+            on_line: None,
         }
     }
 
@@ -68,6 +76,14 @@ where
         }
     }
 
+    /// Call the given function with each complete line this reader reads.
+    pub fn with_line_callback(self, on_line: LineCallback) -> Self {
+        Self {
+            on_line: Some(on_line),
+            ..self
+        }
+    }
+
     /// Read from the contained reader until a line beginning with one of the `end_marker` patterns
     /// is seen, returning the lines until the marker is found.
     ///
@@ -282,6 +298,9 @@ where
 
         let line = std::mem::replace(&mut self.line, String::with_capacity(LINE_BUFFER_CAPACITY));
         tracing::debug!(line, "Read line");
+        if let Some(on_line) = &mut self.on_line {
+            on_line(&line);
+        }
         self.lines.push_str(&line);
         self.lines.push('\n');
 
@@ -448,6 +467,44 @@ mod tests {
         );
     }
 
+    /// The line callback sees each line before the end marker, across chunk boundaries.
+    #[tokio::test]
+    async fn test_read_until_line_callback() {
+        let fake_reader = FakeReader::with_byte_chunks([
+            b"[1 of 2] Compiling Foo\n[2 of",
+            b" 2] Compiling Bar\nOk, 2 modules loaded.\nghci> ",
+        ]);
+
+        let lines = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut reader = IncrementalReader::new(fake_reader)
+            .with_writer(tokio::io::sink())
+            .with_line_callback({
+                let lines = lines.clone();
+                Box::new(move |line| lines.lock().unwrap().push(line.to_owned()))
+            });
+        let end_marker = AhoCorasick::from_anchored_patterns(["ghci> "]);
+        let mut buffer = vec![0; LINE_BUFFER_CAPACITY];
+
+        reader
+            .read_until(&mut ReadOpts {
+                end_marker: &end_marker,
+                find: FindAt::LineStart,
+                writing: WriteBehavior::Hide,
+                buffer: &mut buffer,
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *lines.lock().unwrap(),
+            [
+                "[1 of 2] Compiling Foo",
+                "[2 of 2] Compiling Bar",
+                "Ok, 2 modules loaded.",
+            ]
+        );
+    }
+
     /// Same as `test_read_until` but with `FindAt::Anywhere`.
     #[tokio::test]
     async fn test_read_until_find_anywhere() {
@@ -19,6 +19,8 @@ pub mod clap_markdown;
 pub mod cli;
 mod clonable_command;
 mod command_ext;
+mod config;
+mod control_socket;
 mod cwd;
 mod event_filter;
 mod format_bulleted_list;
@@ -27,8 +29,11 @@ mod haskell_source_file;
 mod hooks;
 mod ignore;
 mod incremental_reader;
+mod lsp;
 mod maybe_async_command;
 mod normal_path;
+mod notify;
+mod project;
 mod shutdown;
 mod string_case;
 mod tracing;
@@ -40,16 +45,22 @@ pub(crate) use cwd::current_dir_utf8;
 pub(crate) use format_bulleted_list::format_bulleted_list;
 pub(crate) use string_case::StringCase;
 
+pub use config::parse_opts;
+pub use config::parse_session_opts;
+pub use control_socket::run_control_socket;
 pub use ghci::manager::run_ghci;
 pub use ghci::Ghci;
 pub use ghci::GhciOpts;
 pub use ghci::GhciWriter;
+pub use lsp::run_lsp;
 pub use shutdown::ShutdownError;
 pub use shutdown::ShutdownHandle;
 pub use shutdown::ShutdownManager;
 pub use tracing::TracingOpts;
 pub use tui::run_tui;
+pub use tui::TuiSession;
 pub use watcher::run_watcher;
+pub use watcher::WatchedSession;
 pub use watcher::WatcherOpts;
 
 #[cfg(test)]
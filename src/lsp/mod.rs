@@ -0,0 +1,537 @@
+//! A minimal Language Server Protocol server which publishes `ghci` diagnostics.
+//!
+//! This doesn't provide any language features of its own; it translates each [`CompilationLog`]
+//! into `textDocument/publishDiagnostics` notifications.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use miette::miette;
+use miette::IntoDiagnostic;
+use serde::Serialize;
+use serde_json::json;
+use serde_json::Value;
+use tokio::io::AsyncWrite;
+use tokio::sync::broadcast;
+use tokio::sync::mpsc;
+use tracing::instrument;
+
+mod transport;
+use transport::read_message;
+use transport::write_message;
+
+use crate::ghci::parse::GhcDiagnostic;
+use crate::ghci::parse::PositionRange;
+use crate::ghci::parse::Severity;
+use crate::ghci::CompilationLog;
+use crate::shutdown::ShutdownHandle;
+
+/// JSON-RPC error code for unknown methods.
+const METHOD_NOT_FOUND: i64 = -32601;
+
+/// A position in a text document, as defined by the Language Server Protocol.
+///
+/// Both the line and the character are 0-based. Strictly speaking, the LSP counts characters in
+/// UTF-16 code units, but GHC counts codepoints; the difference only matters for lines with
+/// characters outside the Basic Multilingual Plane.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+struct LspPosition {
+    line: usize,
+    character: usize,
+}
+
+/// A range in a text document, as defined by the Language Server Protocol.
+///
+/// The end position is exclusive.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+struct LspRange {
+    start: LspPosition,
+    end: LspPosition,
+}
+
+impl From<PositionRange> for LspRange {
+    fn from(span: PositionRange) -> Self {
+        if span.is_zero() {
+            return Self::default();
+        }
+
+        let (start, end) = (span.start(), span.end());
+        Self {
+            start: LspPosition {
+                line: start.line().saturating_sub(1),
+                character: start.column().saturating_sub(1),
+            },
+            // GHC's end columns are 1-based and inclusive, which is the same number as a 0-based
+            // exclusive column.
+            end: LspPosition {
+                line: end.line().saturating_sub(1),
+                character: end.column(),
+            },
+        }
+    }
+}
+
+/// A diagnostic, as defined by the Language Server Protocol.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+struct LspDiagnostic {
+    range: LspRange,
+    severity: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    code: Option<String>,
+    source: &'static str,
+    message: String,
+}
+
+impl From<&GhcDiagnostic> for LspDiagnostic {
+    fn from(diagnostic: &GhcDiagnostic) -> Self {
+        let message = textwrap::dedent(diagnostic.message_without_code().trim_start_matches(' '))
+            .trim()
+            .to_owned();
+
+        Self {
+            range: diagnostic.span.into(),
+            severity: match diagnostic.severity {
+                Severity::Error => 1,
+                Severity::Warning => 2,
+            },
+            code: diagnostic.error_code().map(ToOwned::to_owned),
+            source: "ghc",
+            message,
+        }
+    }
+}
+
+/// Convert a path to a `file://` URI.
+fn path_to_uri(path: &Utf8Path) -> String {
+    let mut uri = String::from("file://");
+    for byte in path.as_str().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char);
+            }
+            _ => {
+                uri.push_str(&format!("%{byte:02X}"));
+            }
+        }
+    }
+    uri
+}
+
+/// Group the diagnostics in a compilation log by the URI of the file they refer to.
+///
+/// Relative paths are resolved against `cwd`. Diagnostics without a path are skipped.
+fn diagnostics_by_uri(
+    log: &CompilationLog,
+    cwd: &Utf8Path,
+) -> BTreeMap<String, Vec<LspDiagnostic>> {
+    let mut ret: BTreeMap<_, Vec<_>> = BTreeMap::new();
+    for diagnostic in &log.diagnostics {
+        match &diagnostic.path {
+            Some(path) => {
+                let uri = path_to_uri(&cwd.join(path));
+                ret.entry(uri).or_default().push(diagnostic.into());
+            }
+            None => {
+                tracing::debug!(%diagnostic, "Not publishing diagnostic without a path");
+            }
+        }
+    }
+    ret
+}
+
+/// State for the language server.
+struct LspServer<W> {
+    /// Where to write messages to the client.
+    writer: W,
+    /// The directory relative paths in diagnostics are resolved against.
+    cwd: Utf8PathBuf,
+    /// Has the client sent the `initialized` notification?
+    initialized: bool,
+    /// Has the client sent the `shutdown` request?
+    shutdown_requested: bool,
+    /// The most recent compilation log received before the client finished initializing.
+    pending: Option<CompilationLog>,
+    /// URIs we've published diagnostics for.
+    ///
+    /// When these files compile cleanly, we need to clear their diagnostics.
+    published: BTreeSet<String>,
+}
+
+impl<W> LspServer<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    fn new(writer: W, cwd: Utf8PathBuf) -> Self {
+        Self {
+            writer,
+            cwd,
+            initialized: false,
+            shutdown_requested: false,
+            pending: None,
+            published: Default::default(),
+        }
+    }
+
+    /// Handle a message from the client.
+    ///
+    /// Returns `true` if the client has asked us to exit. The specification requires a nonzero
+    /// exit code if the client exits without requesting a shutdown first, so that's an error.
+    #[instrument(skip(self), level = "debug")]
+    async fn handle_message(&mut self, message: Value) -> miette::Result<bool> {
+        let method = message.get("method").and_then(Value::as_str);
+        let id = message.get("id").cloned();
+
+        match (method, id) {
+            (Some("initialize"), Some(id)) => {
+                self.respond(
+                    id,
+                    json!({
+                        "capabilities": {},
+                        "serverInfo": {
+                            "name": "ghciwatch",
+                            "version": env!("CARGO_PKG_VERSION"),
+                        },
+                    }),
+                )
+                .await?;
+            }
+            (Some("initialized"), None) => {
+                self.initialized = true;
+                if let Some(log) = self.pending.take() {
+                    self.publish(log).await?;
+                }
+            }
+            (Some("shutdown"), Some(id)) => {
+                self.shutdown_requested = true;
+                self.respond(id, Value::Null).await?;
+            }
+            (Some("exit"), None) => {
+                if !self.shutdown_requested {
+                    return Err(miette!(
+                        "Language server client exited without requesting a shutdown"
+                    ));
+                }
+                return Ok(true);
+            }
+            (method, Some(id)) => {
+                tracing::debug!(?method, "Unsupported request");
+                self.write(json!({
+                    "jsonrpc": "2.0",
+                    "id": id,
+                    "error": {
+                        "code": METHOD_NOT_FOUND,
+                        "message": format!("Unsupported method: {}", method.unwrap_or("<none>")),
+                    },
+                }))
+                .await?;
+            }
+            (method, None) => {
+                tracing::trace!(?method, "Ignoring notification");
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Publish diagnostics from a compilation log, clearing diagnostics for files which no longer
+    /// have any.
+    #[instrument(skip_all, level = "debug")]
+    async fn publish(&mut self, log: CompilationLog) -> miette::Result<()> {
+        if !self.initialized {
+            self.pending = Some(log);
+            return Ok(());
+        }
+
+        let diagnostics = diagnostics_by_uri(&log, &self.cwd);
+
+        let cleared = self
+            .published
+            .iter()
+            .filter(|uri| !diagnostics.contains_key(*uri))
+            .cloned()
+            .collect::<Vec<_>>();
+        for uri in cleared {
+            self.publish_for_uri(&uri, &[]).await?;
+        }
+
+        for (uri, diagnostics) in &diagnostics {
+            self.publish_for_uri(uri, diagnostics).await?;
+        }
+
+        self.published = diagnostics.into_keys().collect();
+        Ok(())
+    }
+
+    async fn publish_for_uri(
+        &mut self,
+        uri: &str,
+        diagnostics: &[LspDiagnostic],
+    ) -> miette::Result<()> {
+        tracing::debug!(
+            uri,
+            diagnostics = diagnostics.len(),
+            "Publishing diagnostics"
+        );
+        self.write(json!({
+            "jsonrpc": "2.0",
+            "method": "textDocument/publishDiagnostics",
+            "params": {
+                "uri": uri,
+                "diagnostics": diagnostics,
+            },
+        }))
+        .await
+    }
+
+    async fn respond(&mut self, id: Value, result: Value) -> miette::Result<()> {
+        self.write(json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "result": result,
+        }))
+        .await
+    }
+
+    async fn write(&mut self, message: Value) -> miette::Result<()> {
+        write_message(&mut self.writer, &message).await
+    }
+}
+
+/// Read messages from `stdin` on a dedicated thread, sending them to the returned receiver.
+///
+/// The receiver is closed when `stdin` is closed.
+fn spawn_stdin_reader() -> miette::Result<mpsc::Receiver<miette::Result<Value>>> {
+    let (sender, receiver) = mpsc::channel(1);
+    std::thread::Builder::new()
+        .name("lsp-stdin".to_owned())
+        .spawn(move || {
+            let mut stdin = std::io::stdin().lock();
+            while let Some(message) = read_message(&mut stdin).transpose() {
+                let is_err = message.is_err();
+                if sender.blocking_send(message).is_err() || is_err {
+                    break;
+                }
+            }
+        })
+        .into_diagnostic()?;
+    Ok(receiver)
+}
+
+/// Run the language server on `stdin` and `stdout`, publishing diagnostics from the given
+/// compilation logs.
+#[instrument(level = "debug", skip_all)]
+pub async fn run_lsp(
+    mut shutdown: ShutdownHandle,
+    mut log_receiver: broadcast::Receiver<CompilationLog>,
+) -> miette::Result<()> {
+    let mut message_receiver = spawn_stdin_reader()?;
+    let mut server = LspServer::new(tokio::io::stdout(), crate::current_dir_utf8()?);
+
+    loop {
+        tokio::select! {
+            _ = shutdown.on_shutdown_requested() => {
+                break;
+            }
+            message = message_receiver.recv() => {
+                match message {
+                    Some(message) => {
+                        if server.handle_message(message?).await? {
+                            break;
+                        }
+                    }
+                    None => {
+                        tracing::debug!("Language server client closed stdin");
+                        break;
+                    }
+                }
+            }
+            log = log_receiver.recv() => {
+                match log {
+                    Ok(log) => {
+                        server.publish(log).await?;
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::debug!(skipped, "Language server fell behind, skipping compilation logs");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        return Err(miette!("Compilation log channel closed"));
+                    }
+                }
+            }
+        }
+    }
+
+    let _ = shutdown.request_shutdown();
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_range_from_span() {
+        assert_eq!(
+            LspRange::from(PositionRange::default()),
+            LspRange::default()
+        );
+        assert_eq!(
+            LspRange::from(PositionRange::new(4, 11, 4, 11)),
+            LspRange {
+                start: LspPosition {
+                    line: 3,
+                    character: 10
+                },
+                end: LspPosition {
+                    line: 3,
+                    character: 11
+                },
+            }
+        );
+        assert_eq!(
+            LspRange::from(PositionRange::new(2, 3, 4, 5)),
+            LspRange {
+                start: LspPosition {
+                    line: 1,
+                    character: 2
+                },
+                end: LspPosition {
+                    line: 3,
+                    character: 5
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn test_path_to_uri() {
+        assert_eq!(
+            path_to_uri(Utf8Path::new("/home/puppy/src/My/Module.hs")),
+            "file:///home/puppy/src/My/Module.hs"
+        );
+        assert_eq!(
+            path_to_uri(Utf8Path::new("/home/puppy/my project/Main.hs")),
+            "file:///home/puppy/my%20project/Main.hs"
+        );
+    }
+
+    #[test]
+    fn test_diagnostic_message() {
+        let diagnostic = GhcDiagnostic {
+            severity: Severity::Error,
+            path: Some("src/My/Module.hs".into()),
+            span: PositionRange::new(3, 11, 3, 18),
+            message: indoc!(
+                r#"[GHC-83865]
+                    • Couldn't match type ‘[Char]’ with ‘()’
+                      Expected: ()
+                        Actual: String
+                "#
+            )
+            .into(),
+        };
+
+        assert_eq!(
+            LspDiagnostic::from(&diagnostic),
+            LspDiagnostic {
+                range: LspRange {
+                    start: LspPosition {
+                        line: 2,
+                        character: 10
+                    },
+                    end: LspPosition {
+                        line: 2,
+                        character: 18
+                    },
+                },
+                severity: 1,
+                code: Some("GHC-83865".into()),
+                source: "ghc",
+                message: indoc!(
+                    "
+                    • Couldn't match type ‘[Char]’ with ‘()’
+                      Expected: ()
+                        Actual: String"
+                )
+                .trim_start()
+                .into(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_publish_clears_fixed_files() {
+        let diagnostic = |path: &str| GhcDiagnostic {
+            path: Some(path.into()),
+            ..GhcDiagnostic::example(Severity::Warning)
+        };
+
+        let mut server = LspServer::new(Vec::new(), "/puppy".into());
+        server
+            .handle_message(json!({"jsonrpc": "2.0", "method": "initialized"}))
+            .await
+            .unwrap();
+
+        server
+            .publish(CompilationLog {
+                diagnostics: vec![diagnostic("A.hs"), diagnostic("B.hs")],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(
+            server.published,
+            [
+                "file:///puppy/A.hs".to_owned(),
+                "file:///puppy/B.hs".to_owned()
+            ]
+            .into()
+        );
+
+        server.writer.clear();
+        server
+            .publish(CompilationLog {
+                diagnostics: vec![diagnostic("B.hs")],
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(server.published, ["file:///puppy/B.hs".to_owned()].into());
+
+        let mut output = server.writer.as_slice();
+        let mut published = Vec::new();
+        while let Some(message) = read_message(&mut output).unwrap() {
+            assert_eq!(message["method"], "textDocument/publishDiagnostics");
+            published.push((
+                message["params"]["uri"].clone(),
+                message["params"]["diagnostics"].clone(),
+            ));
+        }
+        assert_eq!(published.len(), 2);
+        // Diagnostics for the fixed file are cleared.
+        assert_eq!(published[0], (json!("file:///puppy/A.hs"), json!([])));
+        assert_eq!(published[1].0, json!("file:///puppy/B.hs"));
+        assert_eq!(published[1].1.as_array().map(Vec::len), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_exit() {
+        let exit = json!({"jsonrpc": "2.0", "method": "exit"});
+
+        let mut server = LspServer::new(Vec::new(), "/puppy".into());
+        assert!(server.handle_message(exit.clone()).await.is_err());
+
+        let mut server = LspServer::new(Vec::new(), "/puppy".into());
+        assert!(!server
+            .handle_message(json!({"jsonrpc": "2.0", "id": 1, "method": "shutdown"}))
+            .await
+            .unwrap());
+        assert!(server.handle_message(exit).await.unwrap());
+    }
+}
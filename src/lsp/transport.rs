@@ -0,0 +1,142 @@
+//! Reading and writing Language Server Protocol messages.
+//!
+//! Messages are JSON-RPC bodies preceded by HTTP-style headers, like this:
+//!
+//! ```text
+//! Content-Length: 44\r\n
+//! \r\n
+//! {"jsonrpc":"2.0","id":1,"method":"shutdown"}
+//! ```
+//!
+//! See: <https://microsoft.github.io/language-server-protocol/specifications/lsp/3.17/specification/#baseProtocol>
+
+use std::io::BufRead;
+
+use miette::miette;
+use miette::Context;
+use miette::IntoDiagnostic;
+use serde_json::Value;
+use tokio::io::AsyncWrite;
+use tokio::io::AsyncWriteExt;
+
+/// Read a message from the given reader.
+///
+/// Returns `None` if the reader reaches EOF before a message starts.
+///
+/// This is blocking because `tokio`'s `stdin` can't be cancelled, which would hang the runtime on
+/// shutdown.
+pub fn read_message(reader: &mut impl BufRead) -> miette::Result<Option<Value>> {
+    let mut content_length = None;
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        let bytes = reader
+            .read_line(&mut line)
+            .into_diagnostic()
+            .wrap_err("Failed to read message header")?;
+        if bytes == 0 {
+            return Ok(None);
+        }
+
+        let header = line.trim_end_matches(['\r', '\n']);
+        if header.is_empty() {
+            // The headers are over.
+            break;
+        }
+
+        match header.split_once(':') {
+            Some((name, value)) if name.eq_ignore_ascii_case("Content-Length") => {
+                content_length = Some(
+                    value
+                        .trim()
+                        .parse::<usize>()
+                        .into_diagnostic()
+                        .wrap_err_with(|| format!("Invalid `Content-Length` header: {header:?}"))?,
+                );
+            }
+            Some(_) => {
+                // Other headers, like `Content-Type`, are ignored.
+            }
+            None => {
+                return Err(miette!("Invalid message header: {header:?}"));
+            }
+        }
+    }
+
+    let content_length =
+        content_length.ok_or_else(|| miette!("Message has no `Content-Length` header"))?;
+
+    let mut body = vec![0; content_length];
+    reader
+        .read_exact(&mut body)
+        .into_diagnostic()
+        .wrap_err("Failed to read message body")?;
+
+    serde_json::from_slice(&body)
+        .into_diagnostic()
+        .wrap_err("Failed to parse message body as JSON")
+        .map(Some)
+}
+
+/// Write a message to the given writer.
+pub async fn write_message<W>(writer: &mut W, message: &Value) -> miette::Result<()>
+where
+    W: AsyncWrite + Unpin,
+{
+    let body = serde_json::to_vec(message).into_diagnostic()?;
+    writer
+        .write_all(format!("Content-Length: {}\r\n\r\n", body.len()).as_bytes())
+        .await
+        .into_diagnostic()?;
+    writer.write_all(&body).await.into_diagnostic()?;
+    writer.flush().await.into_diagnostic()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+    use serde_json::json;
+
+    #[test]
+    fn test_read_message() {
+        let mut input: &[u8] = b"Content-Length: 44\r\n\
+            Content-Type: application/vscode-jsonrpc; charset=utf-8\r\n\
+            \r\n\
+            {\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"shutdown\"}\
+            Content-Length: 17\r\n\
+            \r\n\
+            {\"method\":\"exit\"}";
+
+        assert_eq!(
+            read_message(&mut input).unwrap(),
+            Some(json!({"jsonrpc": "2.0", "id": 1, "method": "shutdown"}))
+        );
+        assert_eq!(
+            read_message(&mut input).unwrap(),
+            Some(json!({"method": "exit"}))
+        );
+        assert_eq!(read_message(&mut input).unwrap(), None);
+
+        // Negative cases.
+        let mut input: &[u8] = b"\r\n{}";
+        assert!(read_message(&mut input).is_err());
+        let mut input: &[u8] = b"Content-Length: puppy\r\n\r\n{}";
+        assert!(read_message(&mut input).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_message() {
+        let mut output = Vec::new();
+        write_message(&mut output, &json!({"method": "exit"}))
+            .await
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "Content-Length: 17\r\n\r\n{\"method\":\"exit\"}"
+        );
+    }
+}
@@ -7,21 +7,25 @@
 use std::time::Duration;
 
 use clap::CommandFactory;
-use clap::Parser;
 use ghciwatch::cli;
+use ghciwatch::run_control_socket;
 use ghciwatch::run_ghci;
+use ghciwatch::run_lsp;
 use ghciwatch::run_tui;
 use ghciwatch::run_watcher;
 use ghciwatch::GhciOpts;
 use ghciwatch::ShutdownManager;
 use ghciwatch::TracingOpts;
+use ghciwatch::TuiSession;
+use ghciwatch::WatchedSession;
 use ghciwatch::WatcherOpts;
 use tokio::sync::mpsc;
+use tracing::Instrument;
 
 #[tokio::main]
 async fn main() -> miette::Result<()> {
     miette::set_panic_hook();
-    let mut opts = cli::Opts::parse();
+    let mut opts = ghciwatch::parse_opts()?;
     opts.init()?;
     let (maybe_tracing_reader, _tracing_guard) = TracingOpts::from_cli(&opts).install()?;
 
@@ -49,37 +53,112 @@ async fn main() -> miette::Result<()> {
         return Ok(());
     }
 
+    if let Some(project) = &opts.project {
+        tracing::info!("{project}");
+        if project.needs_hpack {
+            tracing::warn!(
+                "Found a `package.yaml` file but no `.cabal` file; \
+                 use `--before-startup-shell hpack` to generate it"
+            );
+        }
+    }
+
     std::env::set_var("IN_GHCIWATCH", "1");
 
-    let (ghci_sender, ghci_receiver) = mpsc::channel(32);
+    let session_opts = ghciwatch::parse_session_opts(&opts)?;
+    let mut ghci_opts = Vec::with_capacity(session_opts.len());
+    let mut ghci_readers = Vec::with_capacity(session_opts.len());
+    for session in &session_opts {
+        let (session_ghci_opts, maybe_ghci_reader) = GhciOpts::from_cli(session)?;
+        ghci_opts.push(session_ghci_opts);
+        ghci_readers.push(maybe_ghci_reader);
+    }
+    GhciOpts::label_shared_paths(&mut ghci_opts);
+
+    let mut watcher_opts = WatcherOpts::from_cli(&opts);
+    let mut tui_sessions = Vec::new();
+    let mut ghci_tasks = Vec::with_capacity(ghci_opts.len());
+    for ((session, ghci_opts), maybe_ghci_reader) in
+        session_opts.iter().zip(ghci_opts).zip(ghci_readers)
+    {
+        let (ghci_sender, ghci_receiver) = mpsc::channel(32);
+        watcher_opts.sessions.push(WatchedSession::from_cli(
+            session,
+            &ghci_opts,
+            ghci_sender.clone(),
+        )?);
 
-    let (ghci_opts, maybe_ghci_reader) = GhciOpts::from_cli(&opts)?;
-    let watcher_opts = WatcherOpts::from_cli(&opts);
+        if opts.tui {
+            tui_sessions.push(TuiSession {
+                name: ghci_opts.session.clone(),
+                ghci_reader: maybe_ghci_reader
+                    .expect("`tui_reader` must be present if `tui` is given"),
+                ghci_sender: ghci_sender.clone(),
+                history_receiver: ghci_opts.history_sender.subscribe(),
+                status_receiver: ghci_opts.status_sender.subscribe(),
+            });
+        }
+
+        ghci_tasks.push((ghci_sender, ghci_opts, ghci_receiver));
+    }
 
     let mut manager = ShutdownManager::with_timeout(Duration::from_secs(1));
 
     if opts.tui {
         let tracing_reader =
             maybe_tracing_reader.expect("`tracing_reader` must be present if `tui` is given");
-        let ghci_reader =
-            maybe_ghci_reader.expect("`tui_reader` must be present if `tui` is given");
         manager
             .spawn("run_tui", |handle| {
-                run_tui(handle, ghci_reader, tracing_reader)
+                run_tui(handle, tracing_reader, tui_sessions)
             })
             .await;
     }
 
-    manager
-        .spawn("run_ghci", |handle| {
-            run_ghci(handle, ghci_opts, ghci_receiver)
-        })
-        .await;
-    manager
-        .spawn("run_watcher", move |handle| {
-            run_watcher(handle, ghci_sender, watcher_opts)
-        })
-        .await;
+    // The language server and control socket only talk to the first session.
+    let (first_ghci_sender, first_ghci_opts, _) = &ghci_tasks[0];
+    if ghci_tasks.len() > 1 && (opts.lsp || opts.control_socket.is_some()) {
+        tracing::info!(
+            "The language server and control socket only use the first session, {}",
+            first_ghci_opts.session.as_deref().unwrap_or_default()
+        );
+    }
+
+    if opts.lsp {
+        let log_receiver = first_ghci_opts.log_sender.subscribe();
+        manager
+            .spawn("run_lsp", |handle| run_lsp(handle, log_receiver))
+            .await;
+    }
+
+    if let Some(path) = opts.control_socket.clone() {
+        let ghci_sender = first_ghci_sender.clone();
+        manager
+            .spawn("run_control_socket", |handle| {
+                run_control_socket(handle, path, ghci_sender)
+            })
+            .await;
+    }
+
+    for (_, ghci_opts, ghci_receiver) in ghci_tasks {
+        let name = match &ghci_opts.session {
+            Some(session) => format!("run_ghci[{session}]"),
+            None => "run_ghci".to_owned(),
+        };
+        let span = tracing::info_span!("session", name = ghci_opts.session.as_deref());
+        manager
+            .spawn(name, |handle| {
+                run_ghci(handle, ghci_opts, ghci_receiver).instrument(span)
+            })
+            .await;
+    }
+    // With `--once`, the sessions exit after loading, so there's nothing to watch.
+    if !opts.once {
+        manager
+            .spawn("run_watcher", move |handle| {
+                run_watcher(handle, watcher_opts)
+            })
+            .await;
+    }
     let ret = manager.wait_for_shutdown().await;
     tracing::debug!("main() finished");
     ret
@@ -1,28 +1,69 @@
 use std::fmt::Display;
 use std::fmt::Write;
+use std::ops::ControlFlow;
 use std::process::ExitStatus;
 use std::process::Stdio;
 use std::str::FromStr;
+use std::time::Duration;
 
+use command_group::AsyncCommandGroup;
 use miette::miette;
 use miette::Context;
 use miette::IntoDiagnostic;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncReadExt;
 use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
 use tracing::instrument;
 use tracing::Instrument;
+use winnow::combinator::alt;
+use winnow::combinator::cut_err;
 use winnow::combinator::opt;
+use winnow::combinator::preceded;
 use winnow::combinator::rest;
+use winnow::combinator::separated;
+use winnow::combinator::terminated;
+use winnow::token::take_till;
 use winnow::PResult;
 use winnow::Parser;
 
 use crate::clonable_command::ClonableCommand;
 use crate::command_ext::CommandExt;
 
+/// What to do when a shell command fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FailurePolicy {
+    /// Don't report the failure, except in debug logs.
+    Ignore,
+    /// Log the failure and continue.
+    #[default]
+    Warn,
+    /// Log the failure and skip the remaining hooks for the event.
+    Abort,
+    /// Log the failure and shut down `ghciwatch`.
+    Shutdown,
+}
+
 /// A shell command which may optionally be run asynchronously.
-#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// Commands can be prefixed with comma-separated options and a colon, like
+/// `async,timeout=30s:hpack`:
+///
+/// - `async` runs the command in the background.
+/// - `cancel-on-reload` runs the command in the background and kills it if it's still running
+///   when the next reload starts.
+/// - `timeout=DURATION` kills the command if it runs for longer than `DURATION`.
+/// - `on-failure=POLICY` determines what to do when the command fails; see [`FailurePolicy`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct MaybeAsyncCommand {
     /// Should this command be run asynchronously?
     pub is_async: bool,
+    /// Should this command be killed when the next reload starts?
+    pub cancel_on_reload: bool,
+    /// How long to let the command run before killing it.
+    pub timeout: Option<Duration>,
+    /// What to do when the command fails.
+    pub on_failure: FailurePolicy,
     /// The contained command.
     pub command: ClonableCommand,
 }
@@ -37,65 +78,232 @@ impl FromStr for MaybeAsyncCommand {
     type Err = miette::Report;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        parse_maybe_async_command
+        let command = parse_maybe_async_command
             .parse(s)
-            .map_err(|err| miette!("{err}"))
+            .map_err(|err| miette!("{err}"))?;
+        if command.is_async
+            && matches!(
+                command.on_failure,
+                FailurePolicy::Abort | FailurePolicy::Shutdown
+            )
+        {
+            // Asynchronous commands finish after the other hooks have run, so there's nothing
+            // left to abort and nobody to report a shutdown to.
+            return Err(miette!(
+                "`on-failure=abort` and `on-failure=shutdown` can't be used with asynchronous \
+                 commands: {s}"
+            ));
+        }
+        Ok(command)
     }
 }
 
+/// An option in a [`MaybeAsyncCommand`]'s prefix.
+#[derive(Debug, Clone, Copy)]
+enum CommandOption {
+    Async,
+    CancelOnReload,
+    Timeout(Duration),
+    OnFailure(FailurePolicy),
+}
+
+fn command_option(input: &mut &str) -> PResult<CommandOption> {
+    alt((
+        "async".value(CommandOption::Async),
+        "cancel-on-reload".value(CommandOption::CancelOnReload),
+        preceded(
+            "timeout=",
+            cut_err(
+                take_till(1.., [',', ':'])
+                    .try_map(humantime::parse_duration)
+                    .map(CommandOption::Timeout),
+            ),
+        ),
+        preceded(
+            "on-failure=",
+            cut_err(alt((
+                "ignore".value(FailurePolicy::Ignore),
+                "warn".value(FailurePolicy::Warn),
+                "abort".value(FailurePolicy::Abort),
+                "shutdown".value(FailurePolicy::Shutdown),
+            )))
+            .map(CommandOption::OnFailure),
+        ),
+    ))
+    .parse_next(input)
+}
+
 fn parse_maybe_async_command(input: &mut &str) -> PResult<MaybeAsyncCommand> {
-    let is_async = opt("async:").parse_next(input)?.is_some();
+    let options: Option<Vec<_>> =
+        opt(terminated(separated(1.., command_option, ','), ':')).parse_next(input)?;
 
-    let command = rest.parse_to().parse_next(input)?;
+    let mut ret = MaybeAsyncCommand {
+        command: rest.parse_to().parse_next(input)?,
+        ..Default::default()
+    };
+    for option in options.into_iter().flatten() {
+        match option {
+            CommandOption::Async => ret.is_async = true,
+            CommandOption::CancelOnReload => {
+                ret.is_async = true;
+                ret.cancel_on_reload = true;
+            }
+            CommandOption::Timeout(timeout) => ret.timeout = Some(timeout),
+            CommandOption::OnFailure(policy) => ret.on_failure = policy,
+        }
+    }
+
+    Ok(ret)
+}
 
-    Ok(MaybeAsyncCommand { is_async, command })
+/// Why a command was killed before it finished.
+#[derive(Debug, Clone, Copy)]
+enum Killed {
+    TimedOut(Duration),
+    Cancelled,
 }
 
 impl MaybeAsyncCommand {
-    #[instrument(skip(self), fields(%self), level = "debug")]
-    pub async fn status(&self) -> MaybeAsyncCommandStatus {
+    /// Get a copy of this command with the given environment variables set.
+    pub fn with_env<K, V>(&self, vars: impl IntoIterator<Item = (K, V)>) -> Self
+    where
+        K: Into<std::ffi::OsString>,
+        V: Into<std::ffi::OsString>,
+    {
+        let mut command = self.command.clone();
+        for (key, value) in vars {
+            command = command.env(key, value);
+        }
+        Self {
+            command,
+            ..self.clone()
+        }
+    }
+
+    /// Run this command, reporting its status.
+    ///
+    /// If the command is `cancel-on-reload`, it's killed when `cancel` is cancelled. Commands are
+    /// run in their own process group, so that killing a command also kills its children.
+    #[instrument(skip(self, cancel), fields(%self), level = "debug")]
+    pub async fn status(&self, cancel: &CancellationToken) -> MaybeAsyncCommandStatus {
         let program = self.command.program.to_string_lossy().into_owned();
         let mut command = self.command.as_tokio();
         let command_formatted = self.display();
+        let timeout = self.timeout;
+        let on_failure = self.on_failure;
+        let cancel = if self.cancel_on_reload {
+            cancel.clone()
+        } else {
+            CancellationToken::new()
+        };
         let join_handle = tokio::task::spawn(
             async move {
                 tracing::info!("$ {command_formatted}");
-                let output = command
+                let mut child = command
                     .stdout(Stdio::piped())
                     .stderr(Stdio::piped())
-                    .output()
-                    .await
+                    .group_spawn()
                     .into_diagnostic()
                     .wrap_err_with(|| format!("Failed to execute `{command_formatted}`"))?;
 
-                let status = output.status;
+                let stop_reading = CancellationToken::new();
+                let stdout = tokio::task::spawn(read_all(
+                    child.inner().stdout.take(),
+                    stop_reading.clone(),
+                ));
+                let stderr = tokio::task::spawn(read_all(
+                    child.inner().stderr.take(),
+                    stop_reading.clone(),
+                ));
+
+                let timed_out = async {
+                    match timeout {
+                        Some(timeout) => tokio::time::sleep(timeout).await,
+                        None => std::future::pending().await,
+                    }
+                };
+                // We wait for the group leader rather than the whole group; once the leader exits,
+                // any children it left running are orphaned and can't be waited for anyway.
+                let killed = tokio::select! {
+                    status = child.inner().wait() => Err(status),
+                    () = timed_out => Ok(Killed::TimedOut(timeout.unwrap_or_default())),
+                    () = cancel.cancelled() => Ok(Killed::Cancelled),
+                };
+                let (status, killed) = match killed {
+                    Err(status) => (status, None),
+                    Ok(killed) => {
+                        // Kill the whole process group, in case the command started children.
+                        if let Err(err) = child.kill() {
+                            tracing::debug!("Failed to kill `{command_formatted}`: {err}");
+                        }
+                        (child.inner().wait().await, Some(killed))
+                    }
+                };
+                let status = status
+                    .into_diagnostic()
+                    .wrap_err_with(|| format!("Failed to execute `{command_formatted}`"))?;
 
                 let mut message = shell_words::quote(&program).into_owned();
                 message.push(' ');
-                if status.success() {
-                    message.push_str("finished successfully");
-                } else {
-                    write!(message, "failed: {status}").expect("Writing to a `String` never fails");
+                match killed {
+                    Some(Killed::Cancelled) => {
+                        message.push_str("was cancelled by a reload");
+                    }
+                    Some(Killed::TimedOut(timeout)) => {
+                        write!(
+                            message,
+                            "timed out after {}",
+                            humantime::format_duration(timeout)
+                        )
+                        .expect("Writing to a `String` never fails");
+                    }
+                    None if status.success() => {
+                        message.push_str("finished successfully");
+                    }
+                    None => {
+                        write!(message, "failed: {status}")
+                            .expect("Writing to a `String` never fails");
+                    }
                 }
 
-                let stdout = String::from_utf8_lossy(&output.stdout);
+                // Background processes started by the command may hold its `stdout` and `stderr`
+                // open long after it exits, so we only wait a little while for the rest of the
+                // output.
+                let output = async { (stdout.await, stderr.await) };
+                tokio::pin!(output);
+                let (stdout, stderr) = tokio::select! {
+                    output = &mut output => output,
+                    () = tokio::time::sleep(OUTPUT_GRACE_PERIOD) => {
+                        tracing::debug!("`{command_formatted}` exited but its output is still open");
+                        stop_reading.cancel();
+                        output.await
+                    }
+                };
+
+                let stdout = String::from_utf8_lossy(&stdout.unwrap_or_default()).into_owned();
                 let stdout = stdout.trim();
                 if !stdout.is_empty() {
                     write!(message, "\n\nStdout: {stdout}")
                         .expect("Writing to a `String` never fails");
                 }
 
-                let stderr = String::from_utf8_lossy(&output.stderr);
+                let stderr = String::from_utf8_lossy(&stderr.unwrap_or_default()).into_owned();
                 let stderr = stderr.trim();
                 if !stderr.is_empty() {
                     write!(message, "\n\nStderr: {stderr}")
                         .expect("Writing to a `String` never fails");
                 }
 
-                if status.success() {
-                    tracing::debug!("{message}");
-                } else {
-                    tracing::error!("{message}");
+                match killed {
+                    Some(Killed::Cancelled) => {
+                        tracing::debug!("{message}");
+                    }
+                    _ if status.success() || on_failure == FailurePolicy::Ignore => {
+                        tracing::debug!("{message}");
+                    }
+                    _ => {
+                        tracing::error!("{message}");
+                    }
                 }
 
                 Ok(status)
@@ -120,15 +328,28 @@ impl MaybeAsyncCommand {
     ///
     /// If it's a synchronous command, report its status. Otherwise, add the [`JoinHandle`] for its
     /// task to the given list of handles.
+    ///
+    /// Returns [`ControlFlow::Break`] if a synchronous command failed and its failure policy is
+    /// [`FailurePolicy::Abort`], and an error if its policy is [`FailurePolicy::Shutdown`].
     pub async fn run_on(
         &self,
         handles: &mut Vec<JoinHandle<miette::Result<ExitStatus>>>,
-    ) -> miette::Result<()> {
-        match self.status().await {
+        cancel: &CancellationToken,
+    ) -> miette::Result<ControlFlow<()>> {
+        match self.status(cancel).await {
             MaybeAsyncCommandStatus::Sync(result) => {
                 // If we failed to execute the program, that's an actual error, but if the
-                // program failed on its own, we'll log and move on.
-                result?;
+                // program failed on its own, we follow its failure policy.
+                let status = result?;
+                if !status.success() {
+                    match self.on_failure {
+                        FailurePolicy::Ignore | FailurePolicy::Warn => {}
+                        FailurePolicy::Abort => return Ok(ControlFlow::Break(())),
+                        FailurePolicy::Shutdown => {
+                            return Err(miette!("`{}` failed: {status}", self.display()));
+                        }
+                    }
+                }
             }
             MaybeAsyncCommandStatus::Async(join_handle) => {
                 // If the program is running asynchronously, we'll store the `JoinHandle`
@@ -136,10 +357,33 @@ impl MaybeAsyncCommand {
                 handles.push(join_handle);
             }
         }
-        Ok(())
+        Ok(ControlFlow::Continue(()))
     }
 }
 
+/// How long to keep reading a command's output after it exits.
+const OUTPUT_GRACE_PERIOD: Duration = Duration::from_secs(1);
+
+/// Read a child process's output stream to the end, if it's present, or until `stop` is
+/// cancelled.
+async fn read_all(stream: Option<impl AsyncRead + Unpin>, stop: CancellationToken) -> Vec<u8> {
+    let mut buffer = Vec::new();
+    if let Some(mut stream) = stream {
+        // If reading fails partway through or we're told to stop, we just report what we have.
+        let mut chunk = [0; 4096];
+        loop {
+            tokio::select! {
+                read = stream.read(&mut chunk) => match read {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => buffer.extend_from_slice(&chunk[..n]),
+                },
+                () = stop.cancelled() => break,
+            }
+        }
+    }
+    buffer
+}
+
 pub enum MaybeAsyncCommandStatus {
     Sync(miette::Result<ExitStatus>),
     Async(JoinHandle<miette::Result<ExitStatus>>),
@@ -164,7 +408,8 @@ mod tests {
             MaybeAsyncCommand {
                 is_async: false,
                 command: ClonableCommand::new("puppy")
-                    .args(["--flavor", "sammy", "--eyes", "brown"])
+                    .args(["--flavor", "sammy", "--eyes", "brown"]),
+                ..Default::default()
             }
         );
 
@@ -175,8 +420,115 @@ mod tests {
             MaybeAsyncCommand {
                 is_async: true,
                 command: ClonableCommand::new("puppy")
-                    .args(["--flavor", "sammy", "--eyes", "brown"])
+                    .args(["--flavor", "sammy", "--eyes", "brown"]),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_options() {
+        assert_eq!(
+            "timeout=1m 30s,on-failure=abort:hpack"
+                .parse::<MaybeAsyncCommand>()
+                .unwrap(),
+            MaybeAsyncCommand {
+                timeout: Some(Duration::from_secs(90)),
+                on_failure: FailurePolicy::Abort,
+                command: ClonableCommand::new("hpack"),
+                ..Default::default()
+            }
+        );
+
+        assert_eq!(
+            "cancel-on-reload,on-failure=ignore:tags"
+                .parse::<MaybeAsyncCommand>()
+                .unwrap(),
+            MaybeAsyncCommand {
+                is_async: true,
+                cancel_on_reload: true,
+                on_failure: FailurePolicy::Ignore,
+                command: ClonableCommand::new("tags"),
+                ..Default::default()
+            }
+        );
+
+        // Colons elsewhere aren't options.
+        assert_eq!(
+            "echo asynchronous:thing"
+                .parse::<MaybeAsyncCommand>()
+                .unwrap(),
+            MaybeAsyncCommand {
+                command: ClonableCommand::new("echo").arg("asynchronous:thing"),
+                ..Default::default()
             }
         );
+
+        assert!("timeout=soon:hpack".parse::<MaybeAsyncCommand>().is_err());
+        assert!("on-failure=panic:hpack"
+            .parse::<MaybeAsyncCommand>()
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_timeout() {
+        let command: MaybeAsyncCommand = "timeout=100ms,on-failure=abort:sleep 10".parse().unwrap();
+        let flow = command
+            .run_on(&mut Vec::new(), &CancellationToken::new())
+            .await
+            .unwrap();
+        assert_eq!(flow, ControlFlow::Break(()));
+
+        let command: MaybeAsyncCommand = "on-failure=shutdown:false".parse().unwrap();
+        assert!(command
+            .run_on(&mut Vec::new(), &CancellationToken::new())
+            .await
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_async_failure_policy() {
+        assert!("async,on-failure=abort:hpack"
+            .parse::<MaybeAsyncCommand>()
+            .is_err());
+        assert!("cancel-on-reload,on-failure=shutdown:tags"
+            .parse::<MaybeAsyncCommand>()
+            .is_err());
+        assert!("async,on-failure=ignore:hpack"
+            .parse::<MaybeAsyncCommand>()
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_background_output() {
+        // The backgrounded `sleep` keeps the command's `stdout` open after it exits.
+        let command: MaybeAsyncCommand =
+            "timeout=5s:sh -c 'echo hello; sleep 30 &'".parse().unwrap();
+        let status = tokio::time::timeout(
+            Duration::from_secs(5),
+            command.run_on(&mut Vec::new(), &CancellationToken::new()),
+        )
+        .await
+        .expect("Command output should not block after the command exits")
+        .unwrap();
+        assert_eq!(status, ControlFlow::Continue(()));
+    }
+
+    #[tokio::test]
+    async fn test_cancel_on_reload() {
+        let command: MaybeAsyncCommand = "cancel-on-reload:sleep 10".parse().unwrap();
+        let cancel = CancellationToken::new();
+        let mut handles = Vec::new();
+        assert_eq!(
+            command.run_on(&mut handles, &cancel).await.unwrap(),
+            ControlFlow::Continue(())
+        );
+        cancel.cancel();
+        let status = tokio::time::timeout(Duration::from_secs(5), handles.pop().unwrap())
+            .await
+            .unwrap()
+            .unwrap()
+            .unwrap();
+        assert!(!status.success());
     }
 }
@@ -0,0 +1,297 @@
+//! Notifications when compilation starts failing or is fixed.
+
+use std::io::Write;
+use std::process::ExitStatus;
+use std::process::Stdio;
+
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+use tokio::io::AsyncWriteExt;
+use tokio::task::JoinHandle;
+use tracing::instrument;
+
+use crate::cli::NotifyOpts;
+use crate::clonable_command::ClonableCommand;
+use crate::command_ext::CommandExt;
+use crate::ghci::parse::CompilationResult;
+use crate::ghci::parse::Severity;
+use crate::ghci::CompilationLog;
+
+/// A way to deliver a notification.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum NotifyMethod {
+    /// Ring the terminal bell.
+    Bell,
+    /// Send a desktop notification with the OSC 9 escape sequence, supported by iTerm2, WezTerm,
+    /// kitty, and Windows Terminal.
+    Osc9,
+    /// Send a desktop notification with the OSC 777 escape sequence, supported by urxvt, foot,
+    /// Ghostty, and WezTerm.
+    Osc777,
+}
+
+impl NotifyMethod {
+    /// Get the escape sequence to write to the terminal for a notification.
+    fn escape_sequence(&self, notification: &Notification) -> String {
+        match self {
+            NotifyMethod::Bell => "\x07".to_owned(),
+            NotifyMethod::Osc9 => format!(
+                "\x1b]9;{}: {}\x07",
+                sanitize(&notification.title),
+                sanitize(&notification.body)
+            ),
+            // Fields are separated by semicolons, so the title can't contain any.
+            NotifyMethod::Osc777 => format!(
+                "\x1b]777;notify;{};{}\x07",
+                sanitize(&notification.title).replace(';', ","),
+                sanitize(&notification.body)
+            ),
+        }
+    }
+}
+
+/// Remove control characters, which would end an escape sequence early.
+fn sanitize(text: &str) -> String {
+    text.chars().filter(|c| !c.is_control()).collect()
+}
+
+/// A notification that compilation started failing or was fixed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Notification {
+    /// A short title, like `ghciwatch`.
+    pub title: String,
+    /// What happened, like `Compilation failed: 1 error, 2 warnings`.
+    pub body: String,
+}
+
+impl Notification {
+    /// Construct a notification for a compilation, if its result differs from the previous
+    /// compilation's result.
+    ///
+    /// There's no notification for the first compilation, when there's no previous result.
+    pub fn for_transition(
+        previous: Option<CompilationResult>,
+        log: &CompilationLog,
+        session: Option<&str>,
+    ) -> Option<Self> {
+        let result = log.result()?;
+        if previous? == result {
+            return None;
+        }
+
+        let body = match result {
+            CompilationResult::Err => {
+                let errors = log
+                    .diagnostics
+                    .iter()
+                    .filter(|diagnostic| diagnostic.severity == Severity::Error)
+                    .count();
+                let warnings = log.diagnostics.len() - errors;
+                format!(
+                    "Compilation failed: {}, {}",
+                    pluralize(errors, "error", "errors"),
+                    pluralize(warnings, "warning", "warnings")
+                )
+            }
+            CompilationResult::Ok => {
+                let modules = log.summary.map(|summary| summary.modules_loaded);
+                format!(
+                    "Compilation fixed: {} loaded",
+                    pluralize(modules.unwrap_or_default(), "module", "modules")
+                )
+            }
+        };
+
+        Some(Self {
+            title: match session {
+                Some(session) => format!("ghciwatch [{session}]"),
+                None => "ghciwatch".to_owned(),
+            },
+            body,
+        })
+    }
+}
+
+fn pluralize(count: usize, singular: &str, plural: &str) -> String {
+    if count == 1 {
+        format!("{count} {singular}")
+    } else {
+        format!("{count} {plural}")
+    }
+}
+
+/// Sends [`Notification`]s.
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+    methods: Vec<NotifyMethod>,
+    command: Option<ClonableCommand>,
+}
+
+impl Notifier {
+    /// Construct a notifier from parsed command-line arguments.
+    pub fn from_cli(opts: &NotifyOpts) -> Self {
+        Self {
+            methods: opts.methods.clone(),
+            command: opts.notify_command.clone(),
+        }
+    }
+
+    /// Send a notification.
+    ///
+    /// Escape sequences are written to `stderr`, so they reach the terminal even when `stdout` is
+    /// used for the language server. If a notifier command is configured, it's started in the
+    /// background with the notification written to its `stdin`, and its handle is returned.
+    #[instrument(skip(self), level = "debug")]
+    pub fn notify(
+        &self,
+        notification: &Notification,
+    ) -> Option<JoinHandle<miette::Result<ExitStatus>>> {
+        if !self.methods.is_empty() {
+            let escapes = self
+                .methods
+                .iter()
+                .map(|method| method.escape_sequence(notification))
+                .collect::<String>();
+            let mut stderr = std::io::stderr().lock();
+            if let Err(err) = stderr
+                .write_all(escapes.as_bytes())
+                .and_then(|()| stderr.flush())
+            {
+                tracing::debug!("Failed to write notification to the terminal: {err}");
+            }
+        }
+
+        let command = self.command.clone()?;
+        let input = format!("{}: {}\n", notification.title, notification.body);
+        Some(tokio::task::spawn(run_notify_command(command, input)))
+    }
+}
+
+async fn run_notify_command(command: ClonableCommand, input: String) -> miette::Result<ExitStatus> {
+    let command_formatted = command.display();
+    let mut child = command
+        .as_tokio()
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to execute `{command_formatted}`"))?;
+
+    if let Some(mut stdin) = child.stdin.take() {
+        // The command may exit without reading its input; that's its business.
+        let _ = stdin.write_all(input.as_bytes()).await;
+    }
+
+    let output = child
+        .wait_with_output()
+        .await
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to execute `{command_formatted}`"))?;
+    if !output.status.success() {
+        tracing::warn!(
+            "Notifier command `{command_formatted}` failed: {}\n{}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+
+    Ok(output.status)
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+    use crate::ghci::parse::CompilationSummary;
+    use crate::ghci::parse::GhcDiagnostic;
+
+    fn log(result: CompilationResult, severities: &[Severity]) -> CompilationLog {
+        CompilationLog {
+            summary: Some(CompilationSummary {
+                result,
+                modules_loaded: 3,
+            }),
+            diagnostics: severities
+                .iter()
+                .map(|severity| GhcDiagnostic::example(*severity))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_notification_for_transition() {
+        let failed = log(
+            CompilationResult::Err,
+            &[Severity::Error, Severity::Warning, Severity::Warning],
+        );
+        let fixed = log(CompilationResult::Ok, &[]);
+
+        assert_eq!(
+            Notification::for_transition(Some(CompilationResult::Ok), &failed, None),
+            Some(Notification {
+                title: "ghciwatch".to_owned(),
+                body: "Compilation failed: 1 error, 2 warnings".to_owned(),
+            })
+        );
+        assert_eq!(
+            Notification::for_transition(Some(CompilationResult::Err), &fixed, Some("lib")),
+            Some(Notification {
+                title: "ghciwatch [lib]".to_owned(),
+                body: "Compilation fixed: 3 modules loaded".to_owned(),
+            })
+        );
+
+        // No transition, no notification.
+        assert_eq!(
+            Notification::for_transition(Some(CompilationResult::Err), &failed, None),
+            None
+        );
+        assert_eq!(Notification::for_transition(None, &failed, None), None);
+        assert_eq!(
+            Notification::for_transition(
+                Some(CompilationResult::Ok),
+                &CompilationLog::default(),
+                None
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_escape_sequence() {
+        let notification = Notification {
+            title: "ghciwatch; [lib]".to_owned(),
+            body: "Compilation failed:\n1 error".to_owned(),
+        };
+        assert_eq!(NotifyMethod::Bell.escape_sequence(&notification), "\x07");
+        assert_eq!(
+            NotifyMethod::Osc9.escape_sequence(&notification),
+            "\x1b]9;ghciwatch; [lib]: Compilation failed:1 error\x07"
+        );
+        assert_eq!(
+            NotifyMethod::Osc777.escape_sequence(&notification),
+            "\x1b]777;notify;ghciwatch, [lib];Compilation failed:1 error\x07"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_notify_command() {
+        let notifier = Notifier {
+            methods: Vec::new(),
+            command: Some("grep -q 'ghciwatch: Compilation fixed'".parse().unwrap()),
+        };
+        let status = notifier
+            .notify(&Notification {
+                title: "ghciwatch".to_owned(),
+                body: "Compilation fixed: 3 modules loaded".to_owned(),
+            })
+            .unwrap()
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(status.success());
+    }
+}
@@ -0,0 +1,561 @@
+//! Detecting Cabal, Stack, and `hpack` projects to pick a default `ghci` command and default
+//! paths to watch.
+
+use std::fmt::Display;
+
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use miette::IntoDiagnostic;
+use miette::WrapErr;
+
+use crate::clonable_command::ClonableCommand;
+
+/// The build tool used to start a `ghci` session for a [`Project`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectKind {
+    /// A Cabal project, started with `cabal repl`.
+    Cabal,
+    /// A Stack project, started with `stack repl`.
+    Stack,
+}
+
+impl Display for ProjectKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProjectKind::Cabal => write!(f, "Cabal"),
+            ProjectKind::Stack => write!(f, "Stack"),
+        }
+    }
+}
+
+/// A Haskell project detected from the files in a directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Project {
+    /// The build tool to start `ghci` with.
+    pub kind: ProjectKind,
+    /// The component to load, from `hie.yaml`, or the first package in a `cabal.project` with no
+    /// package at the project root.
+    pub component: Option<String>,
+    /// The project's source directories, from `hs-source-dirs` in `.cabal` files or `source-dirs`
+    /// in `package.yaml` files, relative to the project directory.
+    pub source_dirs: Vec<Utf8PathBuf>,
+    /// The project files which were found, relative to the project directory.
+    ///
+    /// Changes to these files restart the `ghci` session.
+    pub files: Vec<Utf8PathBuf>,
+    /// True if a `package.yaml` file was found without a generated `.cabal` file, so `hpack`
+    /// needs to run before `cabal repl`.
+    pub needs_hpack: bool,
+}
+
+impl Project {
+    /// Detect a project in the given directory, if any.
+    pub fn detect(dir: &Utf8Path) -> miette::Result<Option<Self>> {
+        let mut files = Vec::new();
+        for name in [
+            "stack.yaml",
+            "cabal.project",
+            "cabal.project.local",
+            "hie.yaml",
+        ] {
+            if dir.join(name).is_file() {
+                files.push(Utf8PathBuf::from(name));
+            }
+        }
+
+        // Each package directory, relative to `dir`.
+        let mut packages = vec![Utf8PathBuf::new()];
+        if files.iter().any(|file| file == "cabal.project") {
+            let cabal_project = read(&dir.join("cabal.project"))?;
+            packages = project_packages(&cabal_project);
+        }
+
+        let mut source_dirs = Vec::new();
+        let mut needs_hpack = false;
+        // The name of the first package, used as the `cabal repl` target if no package is at the
+        // project root.
+        let mut first_package = None;
+        for package in &packages {
+            let cabal_files = cabal_files(&dir.join(package))?;
+            for cabal_file in &cabal_files {
+                let contents = read(&dir.join(package).join(cabal_file))?;
+                if first_package.is_none() {
+                    first_package = cabal_field(&contents, "name")
+                        .first()
+                        .map(|name| name.trim().to_owned());
+                }
+                source_dirs.extend(
+                    cabal_source_dirs(&contents)
+                        .into_iter()
+                        .map(|source_dir| package.join(source_dir)),
+                );
+                files.push(package.join(cabal_file));
+            }
+
+            let package_yaml = package.join("package.yaml");
+            if dir.join(&package_yaml).is_file() {
+                if cabal_files.is_empty() {
+                    let contents = read(&dir.join(&package_yaml))?;
+                    source_dirs.extend(
+                        package_yaml_source_dirs(&contents)
+                            .into_iter()
+                            .map(|source_dir| package.join(source_dir)),
+                    );
+                    if first_package.is_none() {
+                        first_package = package_yaml_name(&contents);
+                    }
+                    needs_hpack = true;
+                }
+                files.push(package_yaml);
+            }
+        }
+
+        let kind = if files.iter().any(|file| file == "stack.yaml") {
+            // Stack runs `hpack` itself.
+            needs_hpack = false;
+            ProjectKind::Stack
+        } else if files.iter().any(|file| {
+            file == "cabal.project"
+                || file.extension() == Some("cabal")
+                || file.ends_with("package.yaml")
+        }) {
+            ProjectKind::Cabal
+        } else {
+            return Ok(None);
+        };
+
+        let mut component = if files.iter().any(|file| file == "hie.yaml") {
+            hie_component(&read(&dir.join("hie.yaml"))?)
+        } else {
+            None
+        };
+        if component.is_none()
+            && kind == ProjectKind::Cabal
+            && !packages.iter().any(|package| package.as_str().is_empty())
+        {
+            // A bare `cabal repl` fails if there's no package in the current directory, so load
+            // the first package instead. Cabal picks the package's library if it has one.
+            component = first_package;
+        }
+
+        source_dirs.retain(|source_dir| dir.join(source_dir).is_dir());
+        source_dirs.sort();
+        source_dirs.dedup();
+
+        Ok(Some(Self {
+            kind,
+            component,
+            source_dirs,
+            files,
+            needs_hpack,
+        }))
+    }
+
+    /// The command to start a `ghci` session for this project.
+    pub fn command(&self) -> ClonableCommand {
+        let command = match self.kind {
+            ProjectKind::Cabal => ClonableCommand::new("cabal").arg("repl"),
+            ProjectKind::Stack => ClonableCommand::new("stack").arg("repl"),
+        };
+        match &self.component {
+            Some(component) => command.arg(component),
+            None => command,
+        }
+    }
+
+    /// Globs matching the project files, which restart the `ghci` session when they change.
+    pub fn restart_globs(&self) -> impl Iterator<Item = String> + '_ {
+        self.files.iter().map(|file| format!("/{file}"))
+    }
+}
+
+impl Display for Project {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Detected {} project", self.kind)?;
+        if !self.files.is_empty() {
+            write!(
+                f,
+                " from {}",
+                self.files
+                    .iter()
+                    .map(|file| file.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?;
+        }
+        write!(f, "; running `{}`", self.command())?;
+        if !self.source_dirs.is_empty() {
+            write!(
+                f,
+                " and watching {}",
+                self.source_dirs
+                    .iter()
+                    .map(|dir| dir.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn read(path: &Utf8Path) -> miette::Result<String> {
+    std::fs::read_to_string(path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to read {path}"))
+}
+
+/// Get the names of the `.cabal` files in a directory.
+fn cabal_files(dir: &Utf8Path) -> miette::Result<Vec<Utf8PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let mut files = Vec::new();
+    for entry in dir
+        .read_dir_utf8()
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to list {dir}"))?
+    {
+        let entry = entry.into_diagnostic()?;
+        let path = entry.path();
+        if path.extension() == Some("cabal") && path.is_file() {
+            files.push(Utf8PathBuf::from(entry.file_name()));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Get the values of a field in a `.cabal` or `cabal.project` file.
+///
+/// Field names are case-insensitive, and values may continue onto following lines which are
+/// indented further than the field name.
+fn cabal_field(contents: &str, field: &str) -> Vec<String> {
+    let lines = contents.lines().collect::<Vec<_>>();
+    lines
+        .iter()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let indent = line.len() - line.trim_start().len();
+            let (name, value) = line.trim_start().split_once(':')?;
+            if !name.trim().eq_ignore_ascii_case(field) {
+                return None;
+            }
+            let mut value = value.to_owned();
+            for line in &lines[index + 1..] {
+                let trimmed = line.trim_start();
+                if trimmed.is_empty() || trimmed.starts_with("--") {
+                    continue;
+                }
+                if line.len() - trimmed.len() <= indent {
+                    break;
+                }
+                value.push(' ');
+                value.push_str(trimmed);
+            }
+            Some(value)
+        })
+        .collect()
+}
+
+/// Split a field value into its comma- or whitespace-separated entries.
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value
+        .split(|c: char| c == ',' || c.is_whitespace())
+        .map(|entry| entry.trim_matches('"'))
+        .filter(|entry| !entry.is_empty())
+}
+
+/// Get the package directories listed in a `cabal.project` file.
+///
+/// Globs aren't supported, so packages listed with globs are skipped.
+fn project_packages(contents: &str) -> Vec<Utf8PathBuf> {
+    let mut packages = Vec::new();
+    for value in cabal_field(contents, "packages") {
+        for package in split_list(&value) {
+            if package.contains(['*', '?', '{']) {
+                tracing::debug!(package, "Skipping glob in cabal.project");
+                continue;
+            }
+            let package = Utf8Path::new(package);
+            let package = if package.extension() == Some("cabal") {
+                package.parent().unwrap_or(Utf8Path::new(""))
+            } else {
+                package
+            };
+            let package = package
+                .components()
+                .filter(|component| component.as_str() != ".")
+                .collect::<Utf8PathBuf>();
+            if !packages.contains(&package) {
+                packages.push(package);
+            }
+        }
+    }
+    packages
+}
+
+/// Get the `hs-source-dirs` in a `.cabal` file.
+fn cabal_source_dirs(contents: &str) -> Vec<Utf8PathBuf> {
+    cabal_field(contents, "hs-source-dirs")
+        .into_iter()
+        .flat_map(|value| {
+            split_list(&value)
+                .filter(|dir| *dir != ".")
+                .map(Utf8PathBuf::from)
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Get the `source-dirs` in a `package.yaml` file.
+///
+/// Values may be a scalar, a flow sequence like `[src, app]`, or a block sequence on the
+/// following lines.
+fn package_yaml_source_dirs(contents: &str) -> Vec<Utf8PathBuf> {
+    let mut dirs = Vec::new();
+    let mut lines = contents.lines().peekable();
+    while let Some(line) = lines.next() {
+        let Some(value) = line.trim_start().strip_prefix("source-dirs:") else {
+            continue;
+        };
+        let value = value.trim();
+        if value.is_empty() {
+            while let Some(item) = lines
+                .peek()
+                .and_then(|line| line.trim_start().strip_prefix("- "))
+            {
+                dirs.push(item.trim().trim_matches(['"', '\'']).into());
+                lines.next();
+            }
+        } else {
+            dirs.extend(
+                value
+                    .trim_start_matches('[')
+                    .trim_end_matches(']')
+                    .split(',')
+                    .map(|dir| dir.trim().trim_matches(['"', '\'']))
+                    .filter(|dir| !dir.is_empty() && *dir != ".")
+                    .map(Utf8PathBuf::from),
+            );
+        }
+    }
+    dirs
+}
+
+/// Get the `name` in a `package.yaml` file.
+fn package_yaml_name(contents: &str) -> Option<String> {
+    contents
+        .lines()
+        .find_map(|line| line.strip_prefix("name:"))
+        .map(|name| name.trim().trim_matches(['"', '\'']).to_owned())
+        .filter(|name| !name.is_empty())
+}
+
+/// Get the first `component` in a `hie.yaml` file, preferring library components.
+fn hie_component(contents: &str) -> Option<String> {
+    let components = contents
+        .lines()
+        .filter_map(|line| line.trim_start().strip_prefix("component:"))
+        .map(|value| value.trim().trim_matches(['"', '\'']).to_owned())
+        .filter(|value| !value.is_empty())
+        .collect::<Vec<_>>();
+    components
+        .iter()
+        .find(|component| component.starts_with("lib:") || component.ends_with(":lib"))
+        .or(components.first())
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use indoc::indoc;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_cabal_source_dirs() {
+        assert_eq!(
+            cabal_source_dirs(indoc!(
+                "
+                cabal-version: 3.0
+                name: my-simple-package
+
+                library
+                    HS-Source-Dirs: src, src-gen
+                    exposed-modules: MyLib
+
+                executable my-exe
+                    main-is: Main.hs
+                    hs-source-dirs:
+                        app
+                        -- A comment.
+                        \"exe-src\"
+                    build-depends: base
+
+                test-suite test
+                    hs-source-dirs: .
+                "
+            )),
+            vec![
+                Utf8PathBuf::from("src"),
+                Utf8PathBuf::from("src-gen"),
+                Utf8PathBuf::from("app"),
+                Utf8PathBuf::from("exe-src"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_project_packages() {
+        assert_eq!(
+            project_packages(indoc!(
+                "
+                packages: ./my-lib
+                          my-exe/my-exe.cabal
+                          vendor/*/
+                          .
+                optional-packages: extra
+                "
+            )),
+            vec![
+                Utf8PathBuf::from("my-lib"),
+                Utf8PathBuf::from("my-exe"),
+                Utf8PathBuf::new(),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_package_yaml_source_dirs() {
+        assert_eq!(
+            package_yaml_source_dirs(indoc!(
+                "
+                name: my-package
+                library:
+                  source-dirs: src
+                executables:
+                  my-exe:
+                    source-dirs:
+                      - app
+                      - 'exe-src'
+                tests:
+                  spec:
+                    source-dirs: [test, \"test-utils\"]
+                "
+            )),
+            vec![
+                Utf8PathBuf::from("src"),
+                Utf8PathBuf::from("app"),
+                Utf8PathBuf::from("exe-src"),
+                Utf8PathBuf::from("test"),
+                Utf8PathBuf::from("test-utils"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_hie_component() {
+        assert_eq!(
+            hie_component(indoc!(
+                r#"
+                cradle:
+                  cabal:
+                    - path: "./test"
+                      component: "test:tests"
+                    - path: "./src"
+                      component: "lib:my-package"
+                "#
+            )),
+            Some("lib:my-package".to_owned())
+        );
+        assert_eq!(
+            hie_component("cradle:\n  stack:\n    component: 'my-package:exe:my-exe'\n"),
+            Some("my-package:exe:my-exe".to_owned())
+        );
+        assert_eq!(hie_component("cradle:\n  cabal:\n"), None);
+    }
+
+    #[test]
+    fn test_detect() {
+        let dir = Utf8PathBuf::try_from(
+            std::env::temp_dir().join(format!("ghciwatch-project-{}", std::process::id())),
+        )
+        .unwrap();
+        std::fs::create_dir_all(dir.join("src")).unwrap();
+        std::fs::create_dir_all(dir.join("test")).unwrap();
+
+        assert_eq!(Project::detect(&dir).unwrap(), None);
+
+        std::fs::write(
+            dir.join("my-package.cabal"),
+            "library\n  hs-source-dirs: src, missing\ntest-suite spec\n  hs-source-dirs: test\n",
+        )
+        .unwrap();
+        let project = Project::detect(&dir).unwrap().unwrap();
+        assert_eq!(project.kind, ProjectKind::Cabal);
+        assert_eq!(project.command().to_string(), "cabal repl");
+        assert_eq!(
+            project.source_dirs,
+            vec![Utf8PathBuf::from("src"), Utf8PathBuf::from("test")]
+        );
+        assert_eq!(
+            project.restart_globs().collect::<Vec<_>>(),
+            vec!["/my-package.cabal".to_owned()]
+        );
+
+        std::fs::write(dir.join("stack.yaml"), "resolver: lts-22.0\n").unwrap();
+        std::fs::write(
+            dir.join("hie.yaml"),
+            "cradle:\n  stack:\n    component: \"my-package:lib\"\n",
+        )
+        .unwrap();
+        let project = Project::detect(&dir).unwrap().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(project.kind, ProjectKind::Stack);
+        assert_eq!(project.command().to_string(), "stack repl my-package:lib");
+        assert_eq!(
+            project.to_string(),
+            "Detected Stack project from stack.yaml, hie.yaml, my-package.cabal; \
+             running `stack repl my-package:lib` and watching src, test"
+        );
+    }
+
+    #[test]
+    fn test_detect_multi_package() {
+        let dir = Utf8PathBuf::try_from(
+            std::env::temp_dir().join(format!("ghciwatch-multi-project-{}", std::process::id())),
+        )
+        .unwrap();
+        std::fs::create_dir_all(dir.join("my-lib/src")).unwrap();
+        std::fs::create_dir_all(dir.join("my-exe/app")).unwrap();
+        std::fs::write(dir.join("cabal.project"), "packages: my-lib my-exe\n").unwrap();
+        std::fs::write(
+            dir.join("my-lib/my-lib.cabal"),
+            "name: my-lib\nlibrary\n  hs-source-dirs: src\n",
+        )
+        .unwrap();
+        std::fs::write(
+            dir.join("my-exe/package.yaml"),
+            "name: my-exe\nexecutables:\n  my-exe:\n    source-dirs: app\n",
+        )
+        .unwrap();
+
+        let project = Project::detect(&dir).unwrap().unwrap();
+        assert_eq!(project.kind, ProjectKind::Cabal);
+        assert_eq!(project.command().to_string(), "cabal repl my-lib");
+        assert_eq!(
+            project.source_dirs,
+            vec![
+                Utf8PathBuf::from("my-exe/app"),
+                Utf8PathBuf::from("my-lib/src")
+            ]
+        );
+
+        std::fs::write(dir.join("cabal.project"), "packages: my-exe my-lib\n").unwrap();
+        let project = Project::detect(&dir).unwrap().unwrap();
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(project.command().to_string(), "cabal repl my-exe");
+    }
+}
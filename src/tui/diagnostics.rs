@@ -0,0 +1,183 @@
+//! The TUI's diagnostics pane, listing the errors and warnings from the most recent compilation.
+
+use ratatui::prelude::Buffer;
+use ratatui::prelude::Constraint;
+use ratatui::prelude::Layout;
+use ratatui::prelude::Rect;
+use ratatui::style::Color;
+use ratatui::style::Modifier;
+use ratatui::style::Style;
+use ratatui::text::Line;
+use ratatui::text::Span;
+use ratatui::widgets::Block;
+use ratatui::widgets::Borders;
+use ratatui::widgets::List;
+use ratatui::widgets::ListState;
+use ratatui::widgets::Paragraph;
+use ratatui::widgets::StatefulWidget;
+use ratatui::widgets::Widget;
+use ratatui::widgets::Wrap;
+
+use crate::ghci::parse::GhcDiagnostic;
+use crate::ghci::parse::Severity;
+use crate::ghci::CompilationLog;
+
+/// State for the diagnostics pane.
+#[derive(Debug, Default)]
+pub struct DiagnosticsPane {
+    /// The diagnostics from the most recent compilation.
+    diagnostics: Vec<GhcDiagnostic>,
+    /// The index of the selected diagnostic.
+    selected: usize,
+    /// Whether to show the full message for the selected diagnostic.
+    expanded: bool,
+}
+
+impl DiagnosticsPane {
+    /// Replace the diagnostics with those from a new compilation log.
+    pub fn update(&mut self, log: CompilationLog) {
+        self.diagnostics = log.diagnostics;
+        self.selected = 0;
+        self.expanded = false;
+    }
+
+    /// Are there any diagnostics to show?
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    /// Get the selected diagnostic, if any.
+    pub fn selected(&self) -> Option<&GhcDiagnostic> {
+        self.diagnostics.get(self.selected)
+    }
+
+    /// Select the next diagnostic, wrapping around at the end of the list.
+    pub fn select_next(&mut self) {
+        if !self.diagnostics.is_empty() {
+            self.selected = (self.selected + 1) % self.diagnostics.len();
+        }
+    }
+
+    /// Select the previous diagnostic, wrapping around at the start of the list.
+    pub fn select_previous(&mut self) {
+        if !self.diagnostics.is_empty() {
+            self.selected = self
+                .selected
+                .checked_sub(1)
+                .unwrap_or(self.diagnostics.len() - 1);
+        }
+    }
+
+    /// Show or hide the full message for the selected diagnostic.
+    pub fn toggle_expanded(&mut self) {
+        self.expanded = !self.expanded;
+    }
+
+    /// Draw the list of diagnostics, and the selected diagnostic if it's expanded.
+    pub fn render(&self, area: Rect, buffer: &mut Buffer) {
+        let areas = Layout::vertical([
+            Constraint::Fill(1),
+            if self.expanded {
+                Constraint::Percentage(50)
+            } else {
+                Constraint::Length(0)
+            },
+        ])
+        .split(area);
+
+        let errors = self
+            .diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .count();
+        let warnings = self.diagnostics.len() - errors;
+
+        let items = self.diagnostics.iter().map(list_item);
+        StatefulWidget::render(
+            List::new(items)
+                .block(
+                    Block::new()
+                        .borders(Borders::RIGHT)
+                        .title(format!("{errors} errors, {warnings} warnings")),
+                )
+                .highlight_style(Style::new().add_modifier(Modifier::REVERSED)),
+            areas[0],
+            buffer,
+            &mut ListState::default().with_selected(Some(self.selected)),
+        );
+
+        if self.expanded {
+            if let Some(diagnostic) = self.selected() {
+                Paragraph::new(textwrap::dedent(diagnostic.message_without_code()))
+                    .wrap(Wrap { trim: false })
+                    .block(Block::new().borders(Borders::TOP | Borders::RIGHT))
+                    .render(areas[1], buffer);
+            }
+        }
+    }
+}
+
+/// Format a diagnostic as a single line: a severity icon, its location, and the first line of its
+/// message.
+pub fn list_item(diagnostic: &GhcDiagnostic) -> Line<'_> {
+    let icon = match diagnostic.severity {
+        Severity::Error => Span::styled("✗ ", Style::new().fg(Color::Red)),
+        Severity::Warning => Span::styled("⚠ ", Style::new().fg(Color::Yellow)),
+    };
+
+    let mut location = match &diagnostic.path {
+        Some(path) => path.to_string(),
+        None => "<no location info>".to_owned(),
+    };
+    if !diagnostic.span.is_zero() {
+        let start = diagnostic.span.start();
+        location.push_str(&format!(":{}:{}", start.line(), start.column()));
+    }
+
+    Line::from(vec![
+        icon,
+        Span::styled(location, Style::new().add_modifier(Modifier::BOLD)),
+        Span::raw(" "),
+        Span::raw(diagnostic.headline()),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn diagnostic(path: &str) -> GhcDiagnostic {
+        GhcDiagnostic {
+            path: Some(path.into()),
+            ..GhcDiagnostic::example(Severity::Error)
+        }
+    }
+
+    #[test]
+    fn test_selection_wraps() {
+        let mut pane = DiagnosticsPane::default();
+        pane.select_next();
+        pane.select_previous();
+        assert!(pane.selected().is_none());
+
+        pane.update(CompilationLog {
+            diagnostics: vec![diagnostic("A.hs"), diagnostic("B.hs"), diagnostic("C.hs")],
+            ..Default::default()
+        });
+        assert_eq!(pane.selected(), Some(&diagnostic("A.hs")));
+        pane.select_previous();
+        assert_eq!(pane.selected(), Some(&diagnostic("C.hs")));
+        pane.select_next();
+        pane.select_next();
+        assert_eq!(pane.selected(), Some(&diagnostic("B.hs")));
+
+        // New logs reset the selection.
+        pane.toggle_expanded();
+        pane.update(CompilationLog {
+            diagnostics: vec![diagnostic("D.hs")],
+            ..Default::default()
+        });
+        assert_eq!(pane.selected(), Some(&diagnostic("D.hs")));
+        assert!(!pane.expanded);
+    }
+}
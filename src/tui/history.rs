@@ -0,0 +1,278 @@
+//! The TUI's reload history, for comparing the results of past reloads.
+
+use std::collections::VecDeque;
+
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use ratatui::prelude::Buffer;
+use ratatui::prelude::Rect;
+use ratatui::style::Color;
+use ratatui::style::Modifier;
+use ratatui::style::Style;
+use ratatui::text::Line;
+use ratatui::text::Span;
+use ratatui::text::Text;
+use ratatui::widgets::Block;
+use ratatui::widgets::Borders;
+use ratatui::widgets::Paragraph;
+use ratatui::widgets::Widget;
+use ratatui::widgets::Wrap;
+
+use super::diagnostics::list_item;
+use crate::buffers::TUI_RELOAD_HISTORY_CAPACITY;
+use crate::event_filter::FileEvent;
+use crate::ghci::parse::CompilationResult;
+use crate::ghci::ReloadRecord;
+use crate::StringCase;
+
+/// A bounded history of completed reloads, and which one is being viewed.
+#[derive(Debug)]
+pub struct ReloadHistory {
+    /// Completed reloads, oldest first.
+    records: VecDeque<ReloadRecord>,
+    /// Index into `records` of the reload being viewed, or `None` if the history isn't shown.
+    selected: Option<usize>,
+    /// The current directory, used to shorten paths.
+    cwd: Option<Utf8PathBuf>,
+}
+
+impl Default for ReloadHistory {
+    fn default() -> Self {
+        Self {
+            records: VecDeque::with_capacity(TUI_RELOAD_HISTORY_CAPACITY),
+            selected: None,
+            cwd: crate::current_dir_utf8().ok(),
+        }
+    }
+}
+
+impl ReloadHistory {
+    /// Add a completed reload to the history, dropping the oldest reload if the history is full.
+    ///
+    /// If the history is being viewed, the selected reload stays the same.
+    pub fn push(&mut self, record: ReloadRecord) {
+        if self.records.len() == TUI_RELOAD_HISTORY_CAPACITY {
+            self.records.pop_front();
+            self.selected = self.selected.map(|selected| selected.saturating_sub(1));
+        }
+        self.records.push_back(record);
+    }
+
+    /// Is the history being viewed?
+    pub fn is_open(&self) -> bool {
+        self.selected.is_some()
+    }
+
+    /// Show or hide the history. The history opens at the most recent reload.
+    pub fn toggle(&mut self) {
+        self.selected = match self.selected {
+            Some(_) => None,
+            None => self.records.len().checked_sub(1),
+        };
+    }
+
+    /// Get the reload being viewed, if any.
+    pub fn selected(&self) -> Option<&ReloadRecord> {
+        self.records.get(self.selected?)
+    }
+
+    /// View the next-oldest reload.
+    pub fn select_older(&mut self) {
+        if let Some(selected) = &mut self.selected {
+            *selected = selected.saturating_sub(1);
+        }
+    }
+
+    /// View the next-newest reload.
+    pub fn select_newer(&mut self) {
+        if let Some(selected) = &mut self.selected {
+            *selected = (*selected + 1).min(self.records.len() - 1);
+        }
+    }
+
+    /// Draw the reload being viewed.
+    pub fn render(&self, area: Rect, buffer: &mut Buffer) {
+        let (Some(selected), Some(record)) = (self.selected, self.selected()) else {
+            return;
+        };
+
+        Paragraph::new(self.to_text(record))
+            .wrap(Wrap { trim: false })
+            .block(Block::new().borders(Borders::TOP).title(format!(
+                "Reload {} of {} (h/l: older/newer, H: close)",
+                selected + 1,
+                self.records.len()
+            )))
+            .render(area, buffer);
+    }
+
+    /// Describe a reload: its result, when it happened, the files that triggered it, and its
+    /// diagnostics.
+    fn to_text<'a>(&self, record: &'a ReloadRecord) -> Text<'a> {
+        let (icon, verb, color) = match record.result() {
+            Some(CompilationResult::Ok) => ("✓ ", "succeeded", Color::Green),
+            Some(CompilationResult::Err) => ("✗ ", "failed", Color::Red),
+            None => ("", "finished", Color::Reset),
+        };
+        let outcome = format!(
+            "{icon}{} {verb}",
+            record.event.event_name().first_char_to_ascii_uppercase()
+        );
+
+        let mut lines = vec![
+            Line::from(vec![
+                Span::styled(outcome, Style::new().fg(color).add_modifier(Modifier::BOLD)),
+                Span::raw(format!(" in {:.2?}", record.duration)),
+            ]),
+            Line::raw(format!(
+                "Finished at {}",
+                humantime::format_rfc3339_seconds(record.timestamp)
+            )),
+        ];
+
+        if !record.changes.is_empty() {
+            lines.push(Line::raw("Changed files:"));
+            for change in &record.changes {
+                let path = self.display_path(change.as_path());
+                lines.push(Line::raw(match change {
+                    FileEvent::Modify(_) => format!("• {path}"),
+                    FileEvent::Remove(_) => format!("• {path} (removed)"),
+                }));
+            }
+        }
+
+        lines.push(Line::default());
+        if record.log.diagnostics.is_empty() {
+            lines.push(Line::raw("No errors or warnings"));
+        } else {
+            lines.extend(record.log.diagnostics.iter().map(list_item));
+        }
+
+        Text::from(lines)
+    }
+
+    /// Display a path relative to the current directory, if it's inside it.
+    fn display_path<'a>(&self, path: &'a Utf8Path) -> &'a Utf8Path {
+        self.cwd
+            .as_deref()
+            .and_then(|cwd| path.strip_prefix(cwd).ok())
+            .unwrap_or(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use std::time::SystemTime;
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::ghci::parse::CompilationSummary;
+    use crate::ghci::parse::GhcDiagnostic;
+    use crate::ghci::parse::PositionRange;
+    use crate::ghci::parse::Severity;
+    use crate::ghci::CompilationLog;
+    use crate::hooks::LifecycleEvent;
+    use crate::hooks::When;
+
+    fn record(millis: u64) -> ReloadRecord {
+        ReloadRecord {
+            timestamp: SystemTime::UNIX_EPOCH,
+            event: LifecycleEvent::Reload(When::After),
+            changes: vec![
+                FileEvent::Modify("/project/src/MyLib.hs".into()),
+                FileEvent::Remove("/elsewhere/Foo.hs".into()),
+            ],
+            duration: Duration::from_millis(millis),
+            log: CompilationLog {
+                summary: Some(CompilationSummary {
+                    result: CompilationResult::Err,
+                    modules_loaded: 1,
+                }),
+                diagnostics: vec![GhcDiagnostic {
+                    severity: Severity::Error,
+                    path: Some("src/MyLib.hs".into()),
+                    span: PositionRange::new(4, 12, 4, 12),
+                    message: "\n    • Couldn't match expected type".into(),
+                }],
+                ..Default::default()
+            },
+        }
+    }
+
+    fn history() -> ReloadHistory {
+        ReloadHistory {
+            cwd: Some("/project".into()),
+            ..Default::default()
+        }
+    }
+
+    fn plain_text(text: Text<'_>) -> Vec<String> {
+        text.lines
+            .into_iter()
+            .map(|line| {
+                line.spans
+                    .into_iter()
+                    .map(|span| span.content.into_owned())
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_paging() {
+        let mut history = history();
+        history.toggle();
+        assert!(!history.is_open());
+
+        for millis in 0..TUI_RELOAD_HISTORY_CAPACITY as u64 {
+            history.push(record(millis));
+        }
+        history.toggle();
+        assert_eq!(
+            history.selected().unwrap().duration,
+            Duration::from_millis(TUI_RELOAD_HISTORY_CAPACITY as u64 - 1)
+        );
+        history.select_newer();
+        assert_eq!(
+            history.selected().unwrap().duration,
+            Duration::from_millis(TUI_RELOAD_HISTORY_CAPACITY as u64 - 1)
+        );
+        history.select_older();
+        assert_eq!(
+            history.selected().unwrap().duration,
+            Duration::from_millis(TUI_RELOAD_HISTORY_CAPACITY as u64 - 2)
+        );
+
+        // New reloads drop the oldest record, but don't change the selected reload.
+        history.push(record(1000));
+        assert_eq!(history.records.len(), TUI_RELOAD_HISTORY_CAPACITY);
+        assert_eq!(history.records[0].duration, Duration::from_millis(1));
+        assert_eq!(
+            history.selected().unwrap().duration,
+            Duration::from_millis(TUI_RELOAD_HISTORY_CAPACITY as u64 - 2)
+        );
+
+        history.toggle();
+        assert!(history.selected().is_none());
+    }
+
+    #[test]
+    fn test_to_text() {
+        let history = history();
+        assert_eq!(
+            plain_text(history.to_text(&record(1500))),
+            [
+                "✗ Reload failed in 1.50s",
+                "Finished at 1970-01-01T00:00:00Z",
+                "Changed files:",
+                "• src/MyLib.hs",
+                "• /elsewhere/Foo.hs (removed)",
+                "",
+                "✗ src/MyLib.hs:4:12 Couldn't match expected type",
+            ]
+        );
+    }
+}
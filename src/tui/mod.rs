@@ -1,10 +1,11 @@
 use std::ops::Deref;
 use std::ops::DerefMut;
 
-use ansi_to_tui::IntoText;
 use crossterm::event::Event;
 use crossterm::event::EventStream;
 use crossterm::event::KeyCode;
+use crossterm::event::KeyEvent;
+use crossterm::event::KeyEventKind;
 use crossterm::event::KeyModifiers;
 use crossterm::event::MouseEventKind;
 use miette::miette;
@@ -14,31 +15,57 @@ use ratatui::prelude::Buffer;
 use ratatui::prelude::Constraint;
 use ratatui::prelude::Layout;
 use ratatui::prelude::Rect;
+use ratatui::style::Modifier;
+use ratatui::style::Style;
+use ratatui::text::Line;
+use ratatui::text::Span;
 use ratatui::widgets::Paragraph;
+use ratatui::widgets::Tabs;
 use ratatui::widgets::Widget;
 use ratatui::widgets::Wrap;
 use saturating::Saturating;
 use tokio::io::AsyncBufReadExt;
 use tokio::io::BufReader;
 use tokio::io::DuplexStream;
+use tokio::sync::broadcast;
+use tokio::sync::mpsc;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::watch;
 use tokio_stream::StreamExt;
 use tracing::instrument;
 
+mod diagnostics;
+mod history;
+mod scrollback;
+mod status_bar;
 mod terminal;
 
-use crate::buffers::TUI_SCROLLBACK_CAPACITY;
+use crate::clonable_command::ClonableCommand;
+use crate::ghci::manager::GhciEvent;
+use crate::ghci::GhciStatus;
+use crate::ghci::ReloadRecord;
 use crate::ShutdownHandle;
+use diagnostics::DiagnosticsPane;
+use history::ReloadHistory;
+use scrollback::LineSource;
+use scrollback::Scrollback;
+use scrollback::ScrollbackFilter;
 use terminal::TerminalGuard;
 
 /// Default amount to scroll on mouse wheel events.
 const SCROLL_AMOUNT: usize = 3;
 
-/// State data for drawing the TUI.
+/// State data for drawing one `ghci` session in the TUI.
 #[derive(Debug)]
 struct TuiState {
-    debug: bool,
-    quit: bool,
-    scrollback: Vec<u8>,
+    /// The session's name, if several sessions are running.
+    name: Option<String>,
+    diagnostics: DiagnosticsPane,
+    history: ReloadHistory,
+    status: GhciStatus,
+    scrollback: Scrollback,
+    /// The search query being typed, if the user has pressed `/`.
+    search_input: Option<String>,
     line_count: Saturating<usize>,
     scroll_offset: Saturating<usize>,
 }
@@ -46,9 +73,12 @@ struct TuiState {
 impl Default for TuiState {
     fn default() -> Self {
         Self {
-            debug: false,
-            quit: false,
-            scrollback: Vec::with_capacity(TUI_SCROLLBACK_CAPACITY),
+            name: None,
+            diagnostics: Default::default(),
+            history: Default::default(),
+            status: Default::default(),
+            scrollback: Default::default(),
+            search_input: None,
             line_count: Saturating(1),
             scroll_offset: Saturating(0),
         }
@@ -57,35 +87,67 @@ impl Default for TuiState {
 
 impl TuiState {
     #[instrument(level = "trace", skip_all)]
-    fn render_inner(&self, area: Rect, buffer: &mut Buffer) -> miette::Result<()> {
+    fn render_inner(&self, area: Rect, buffer: &mut Buffer, debug: bool) -> miette::Result<()> {
         if area.width == 0 || area.height == 0 {
             return Ok(());
         }
 
         let areas = Layout::vertical([
             Constraint::Fill(1),
-            Constraint::Length(if self.debug { 1 } else { 0 }),
+            Constraint::Length(1),
+            Constraint::Length(if debug { 1 } else { 0 }),
         ])
         .split(area);
 
-        let text = self.scrollback.into_text().into_diagnostic()?;
-
-        let scroll_offset = u16::try_from(self.scroll_offset.0)
-            .into_diagnostic()
-            .wrap_err("Scroll offset doesn't fit into 16 bits")?;
+        let output_area = if self.diagnostics.is_empty() {
+            areas[0]
+        } else {
+            let panes = Layout::horizontal([Constraint::Percentage(40), Constraint::Fill(1)])
+                .split(areas[0]);
+            self.diagnostics.render(panes[0], buffer);
+            panes[1]
+        };
+
+        if self.history.is_open() {
+            self.history.render(output_area, buffer);
+        } else {
+            let text = self.scrollback.to_text()?;
+
+            let scroll_offset = u16::try_from(self.scroll_offset.0)
+                .into_diagnostic()
+                .wrap_err("Scroll offset doesn't fit into 16 bits")?;
+
+            Paragraph::new(text)
+                .wrap(Wrap::default())
+                .scroll((scroll_offset, 0))
+                .render(output_area, buffer);
+        }
 
-        Paragraph::new(text)
-            .wrap(Wrap::default())
-            .scroll((scroll_offset, 0))
-            .render(areas[0], buffer);
+        let status_line = match &self.search_input {
+            Some(input) => Line::raw(format!("/{input}")),
+            None => {
+                let mut line = status_bar::status_line(&self.status);
+                let filter = self.scrollback.filter();
+                if filter != ScrollbackFilter::All {
+                    line.spans.push(Span::raw(format!(" │ Showing {filter}")));
+                }
+                if let Some(summary) = self.scrollback.search_summary() {
+                    line.spans.push(Span::raw(format!(" │ {summary}")));
+                }
+                line
+            }
+        };
+        Paragraph::new(status_line)
+            .style(Style::new().add_modifier(Modifier::REVERSED))
+            .render(areas[1], buffer);
 
-        if self.debug {
+        if debug {
             let line_count = self.line_count;
             let scroll_offset = self.scroll_offset;
             Paragraph::new(format!(
                 "(☞ ﾟ ヮﾟ )☞  line_count={line_count}, scroll_offset={scroll_offset}"
             ))
-            .render(areas[1], buffer);
+            .render(areas[2], buffer);
         }
 
         Ok(())
@@ -96,30 +158,81 @@ struct Tui {
     terminal: TerminalGuard,
     /// The last terminal size seen. This is updated on every `render` call.
     size: Rect,
-    state: TuiState,
+    debug: bool,
+    quit: bool,
+    /// Set when the user asks to open the selected diagnostic in their editor.
+    ///
+    /// The editor is run from [`run_tui`], which has to stop reading terminal events first.
+    open_editor: bool,
+    /// The state for each session. Only the selected session is drawn.
+    sessions: Vec<TuiState>,
+    /// The index of the selected session.
+    selected: usize,
+    /// Senders for controlling each `ghci` session.
+    ghci_senders: Vec<mpsc::Sender<GhciEvent>>,
 }
 
 impl Deref for Tui {
     type Target = TuiState;
 
     fn deref(&self) -> &Self::Target {
-        &self.state
+        &self.sessions[self.selected]
     }
 }
 
 impl DerefMut for Tui {
     fn deref_mut(&mut self) -> &mut Self::Target {
-        &mut self.state
+        &mut self.sessions[self.selected]
     }
 }
 
 impl Tui {
-    fn new(mut terminal: TerminalGuard) -> Self {
+    fn new(
+        mut terminal: TerminalGuard,
+        names: Vec<Option<String>>,
+        ghci_senders: Vec<mpsc::Sender<GhciEvent>>,
+    ) -> Self {
         let area = terminal.get_frame().size();
         Self {
             terminal,
             size: area,
-            state: Default::default(),
+            debug: false,
+            quit: false,
+            open_editor: false,
+            sessions: names
+                .into_iter()
+                .map(|name| TuiState {
+                    name,
+                    ..Default::default()
+                })
+                .collect(),
+            selected: 0,
+            ghci_senders,
+        }
+    }
+
+    /// Select the next session, wrapping around.
+    fn select_next_session(&mut self) {
+        self.selected = (self.selected + 1) % self.sessions.len();
+    }
+
+    /// Select the previous session, wrapping around.
+    fn select_previous_session(&mut self) {
+        self.selected = (self.selected + self.sessions.len() - 1) % self.sessions.len();
+    }
+
+    /// Send an event to the selected `ghci` session.
+    ///
+    /// If the session is busy and its queue is full, the event is dropped; the user can press the
+    /// key again.
+    fn send_ghci_event(&self, event: GhciEvent) -> miette::Result<()> {
+        match self.ghci_senders[self.selected].try_send(event) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(event)) => {
+                tracing::warn!(?event, "ghci is busy, ignoring event");
+                Ok(())
+            }
+            Err(TrySendError::Closed(_)) => Err(miette!("ghci event channel closed")),
         }
     }
 
@@ -157,11 +270,110 @@ impl Tui {
         }
     }
 
-    fn push_line(&mut self, line: String) {
-        self.scrollback.extend(line.into_bytes());
-        self.scrollback.push(b'\n');
-        self.line_count += Saturating(1);
-        self.maybe_follow();
+    fn push_line(&mut self, source: LineSource, line: String) {
+        if self.scrollback.push(source, line) {
+            self.line_count += Saturating(1);
+            self.maybe_follow();
+        }
+    }
+
+    /// Add a line of output to the given session, which may not be selected.
+    fn push_session_line(&mut self, index: usize, source: LineSource, line: String) {
+        let selected = std::mem::replace(&mut self.selected, index);
+        self.push_line(source, line);
+        self.selected = selected;
+    }
+
+    /// Show the lines matching the next filter, and scroll to the bottom.
+    fn cycle_filter(&mut self) {
+        let filter = self.scrollback.filter().next();
+        self.scrollback.set_filter(filter);
+        self.line_count = Saturating(1 + self.scrollback.visible_len());
+        self.scroll_to(usize::MAX);
+    }
+
+    /// Scroll to a search match, if there is one.
+    fn scroll_to_match(&mut self, line: Option<usize>) {
+        match line {
+            Some(line) => self.scroll_to(line),
+            None => {
+                if let Some(summary) = self.scrollback.search_summary() {
+                    tracing::debug!(summary, "No search matches");
+                }
+            }
+        }
+    }
+
+    /// Handle a keypress while typing a search query.
+    fn handle_search_input(&mut self, key: KeyEvent) {
+        let selected = self.selected;
+        let Some(input) = &mut self.sessions[selected].search_input else {
+            return;
+        };
+
+        match key.code {
+            KeyCode::Enter => {
+                self.search_input = None;
+                return;
+            }
+            KeyCode::Esc => {
+                self.search_input = None;
+                self.scrollback.clear_search();
+                return;
+            }
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Char(c) if !key.modifiers.contains(KeyModifiers::CONTROL) => {
+                input.push(c);
+            }
+            _ => return,
+        }
+
+        // Search incrementally as the query is typed.
+        let query = input.clone();
+        let from = self.scroll_offset.0;
+        let line = self.scrollback.set_search(query, from);
+        self.scroll_to_match(line);
+    }
+
+    /// Open the selected diagnostic's file in `$VISUAL` or `$EDITOR`.
+    ///
+    /// This blocks until the editor exits. Terminal events must not be read while the editor is
+    /// running, or the editor will miss keystrokes.
+    #[instrument(level = "debug", skip(self))]
+    fn open_in_editor(&mut self) -> miette::Result<()> {
+        let Some(diagnostic) = self.diagnostics.selected() else {
+            return Ok(());
+        };
+        let Some(path) = diagnostic.path.clone() else {
+            tracing::warn!("Diagnostic has no location to open");
+            return Ok(());
+        };
+        let line = diagnostic.span.start().line();
+
+        let Some(editor) = ["VISUAL", "EDITOR"]
+            .into_iter()
+            .find_map(|var| std::env::var(var).ok().filter(|value| !value.is_empty()))
+        else {
+            tracing::warn!("Set `$EDITOR` to open files from the TUI");
+            return Ok(());
+        };
+
+        let mut command = editor.parse::<ClonableCommand>()?;
+        if line > 0 {
+            command = command.arg(format!("+{line}"));
+        }
+        let mut command = command.arg(path).as_std();
+
+        let status = terminal::suspend(&mut self.terminal, || command.status())?
+            .into_diagnostic()
+            .wrap_err_with(|| format!("Failed to run editor `{editor}`"))?;
+        if !status.success() {
+            tracing::warn!("Editor `{editor}` failed: {status}");
+        }
+
+        Ok(())
     }
 
     #[instrument(level = "trace", skip(self))]
@@ -171,12 +383,27 @@ impl Tui {
             .draw(|frame| {
                 self.size = frame.size();
                 let buffer = frame.buffer_mut();
-                render_result = self.state.render_inner(self.size, buffer);
+                let mut area = self.size;
+                if self.sessions.len() > 1 && area.height > 0 {
+                    let areas =
+                        Layout::vertical([Constraint::Length(1), Constraint::Fill(1)]).split(area);
+                    Tabs::new(self.sessions.iter().map(|session| {
+                        status_bar::tab_title(
+                            session.name.as_deref().unwrap_or_default(),
+                            &session.status,
+                        )
+                    }))
+                    .select(self.selected)
+                    .highlight_style(Style::new().add_modifier(Modifier::REVERSED))
+                    .render(areas[0], buffer);
+                    area = areas[1];
+                }
+                render_result = self.sessions[self.selected].render_inner(area, buffer, self.debug);
             })
             .into_diagnostic()
             .wrap_err("Failed to draw to terminal")?;
 
-        Ok(())
+        render_result
     }
 
     #[instrument(level = "trace", skip(self))]
@@ -189,6 +416,9 @@ impl Tui {
                 MouseEventKind::ScrollDown => self.scroll_down(SCROLL_AMOUNT),
                 _ => {}
             },
+            // We ask for key release events, so make sure we don't handle keypresses twice.
+            Event::Key(key) if key.kind == KeyEventKind::Release => {}
+            Event::Key(key) if self.search_input.is_some() => self.handle_search_input(key),
             Event::Key(key) => match (key.modifiers, key.code) {
                 (KeyModifiers::NONE, KeyCode::Char('j')) => self.scroll_down(1),
                 (KeyModifiers::NONE, KeyCode::Char('k')) => self.scroll_up(1),
@@ -201,6 +431,39 @@ impl Tui {
                 (KeyModifiers::CONTROL, KeyCode::Char('e')) => self.scroll_down(1),
                 (KeyModifiers::CONTROL, KeyCode::Char('y')) => self.scroll_up(1),
                 (KeyModifiers::CONTROL, KeyCode::Char('c')) => self.quit = true,
+                (KeyModifiers::NONE, KeyCode::Char('q')) => self.quit = true,
+                (KeyModifiers::NONE, KeyCode::Char('r')) => {
+                    self.send_ghci_event(GhciEvent::ReloadAll { reply: None })?
+                }
+                (KeyModifiers::SHIFT, KeyCode::Char('r' | 'R')) => {
+                    self.send_ghci_event(GhciEvent::Restart { reply: None })?
+                }
+                (KeyModifiers::NONE, KeyCode::Char('t')) => {
+                    self.send_ghci_event(GhciEvent::RunTests { reply: None })?
+                }
+                (KeyModifiers::NONE, KeyCode::Char('e')) => {
+                    self.send_ghci_event(GhciEvent::ToggleEval { reply: None })?
+                }
+                (KeyModifiers::NONE, KeyCode::Char(']')) => self.diagnostics.select_next(),
+                (KeyModifiers::NONE, KeyCode::Char('[')) => self.diagnostics.select_previous(),
+                (KeyModifiers::NONE, KeyCode::Enter) => self.diagnostics.toggle_expanded(),
+                (KeyModifiers::NONE, KeyCode::Char('o')) => self.open_editor = true,
+                (KeyModifiers::NONE, KeyCode::Char('/')) => self.search_input = Some(String::new()),
+                (KeyModifiers::NONE, KeyCode::Char('n')) => {
+                    let line = self.scrollback.next_match();
+                    self.scroll_to_match(line);
+                }
+                (KeyModifiers::SHIFT, KeyCode::Char('n' | 'N')) => {
+                    let line = self.scrollback.previous_match();
+                    self.scroll_to_match(line);
+                }
+                (KeyModifiers::NONE, KeyCode::Esc) => self.scrollback.clear_search(),
+                (KeyModifiers::NONE, KeyCode::Char('f')) => self.cycle_filter(),
+                (KeyModifiers::SHIFT, KeyCode::Char('h' | 'H')) => self.history.toggle(),
+                (KeyModifiers::NONE, KeyCode::Char('h')) => self.history.select_older(),
+                (KeyModifiers::NONE, KeyCode::Char('l')) => self.history.select_newer(),
+                (KeyModifiers::NONE, KeyCode::Tab) => self.select_next_session(),
+                (KeyModifiers::SHIFT, KeyCode::BackTab) => self.select_previous_session(),
                 (KeyModifiers::NONE, KeyCode::Char('`')) => self.debug = false,
                 (KeyModifiers::SHIFT, KeyCode::Char('`' | '~')) => self.debug = true,
                 _ => {}
@@ -212,18 +475,104 @@ impl Tui {
     }
 }
 
-/// Start the terminal event loop, reading output from the given readers.
+/// A `ghci` session to draw in the TUI.
+pub struct TuiSession {
+    /// The session's name, if several sessions are running.
+    pub name: Option<String>,
+    /// The session's `ghci` output.
+    pub ghci_reader: DuplexStream,
+    /// Sender for controlling the session, e.g. with `r` (reload) and `t` (run tests).
+    pub ghci_sender: mpsc::Sender<GhciEvent>,
+    /// Receiver for diagnostics and reload history.
+    pub history_receiver: broadcast::Receiver<ReloadRecord>,
+    /// Receiver for the session's status, drawn in the status bar.
+    pub status_receiver: watch::Receiver<GhciStatus>,
+}
+
+/// An update from one of the [`TuiSession`]s.
+enum SessionUpdate {
+    Line(String),
+    Record(ReloadRecord),
+    Status(GhciStatus),
+    Closed,
+}
+
+/// Forward updates from a session to `sender`, tagged with the session's index.
+async fn forward_session_updates(
+    index: usize,
+    ghci_reader: DuplexStream,
+    mut history_receiver: broadcast::Receiver<ReloadRecord>,
+    mut status_receiver: watch::Receiver<GhciStatus>,
+    sender: mpsc::Sender<(usize, miette::Result<SessionUpdate>)>,
+) {
+    let mut ghci_reader = BufReader::new(ghci_reader).lines();
+    loop {
+        let update = tokio::select! {
+            line = ghci_reader.next_line() => {
+                match line.into_diagnostic().wrap_err("Failed to read line from GHCI") {
+                    Ok(Some(line)) => Ok(SessionUpdate::Line(line)),
+                    Ok(None) => Ok(SessionUpdate::Closed),
+                    Err(err) => Err(err),
+                }
+            }
+
+            record = history_receiver.recv() => {
+                match record {
+                    Ok(record) => Ok(SessionUpdate::Record(record)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        tracing::debug!(skipped, "TUI fell behind, skipping reload records");
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => Ok(SessionUpdate::Closed),
+                }
+            }
+
+            changed = status_receiver.changed() => {
+                match changed {
+                    Ok(()) => Ok(SessionUpdate::Status(status_receiver.borrow_and_update().clone())),
+                    Err(_) => Ok(SessionUpdate::Closed),
+                }
+            }
+        };
+
+        let done = matches!(update, Ok(SessionUpdate::Closed) | Err(_));
+        if sender.send((index, update)).await.is_err() || done {
+            break;
+        }
+    }
+}
+
+/// Start the terminal event loop, reading output from the given sessions.
+///
+/// Each session is drawn in its own tab; `Tab` and `Shift-Tab` switch between them. Keypresses
+/// like `r` (reload) and `t` (run tests) are sent to the selected session. Log messages from
+/// `tracing_reader` are shown in every tab.
 #[instrument(level = "debug", skip_all)]
 pub async fn run_tui(
     mut shutdown: ShutdownHandle,
-    ghci_reader: DuplexStream,
     tracing_reader: DuplexStream,
+    sessions: Vec<TuiSession>,
 ) -> miette::Result<()> {
-    let mut ghci_reader = BufReader::new(ghci_reader).lines();
     let mut tracing_reader = BufReader::new(tracing_reader).lines();
 
+    let mut names = Vec::with_capacity(sessions.len());
+    let mut ghci_senders = Vec::with_capacity(sessions.len());
+    let (update_sender, mut update_receiver) = mpsc::channel(sessions.len().max(1));
+    for (index, session) in sessions.into_iter().enumerate() {
+        names.push(session.name);
+        ghci_senders.push(session.ghci_sender);
+        tokio::task::spawn(forward_session_updates(
+            index,
+            session.ghci_reader,
+            session.history_receiver,
+            session.status_receiver,
+            update_sender.clone(),
+        ));
+    }
+    drop(update_sender);
+
     let terminal = terminal::enter()?;
-    let mut tui = Tui::new(terminal);
+    let mut tui = Tui::new(terminal, names, ghci_senders);
 
     let mut event_stream = EventStream::new();
 
@@ -237,22 +586,36 @@ pub async fn run_tui(
                 tui.quit = true;
             }
 
-            line = ghci_reader.next_line() => {
-                let line = line.into_diagnostic().wrap_err("Failed to read line from GHCI")?;
-                match line {
-                    Some(line) => {
-                        tui.push_line(line);
+            update = update_receiver.recv() => {
+                match update {
+                    Some((index, update)) => match update? {
+                        SessionUpdate::Line(line) => {
+                            tui.push_session_line(index, LineSource::Ghci, line);
+                        }
+                        SessionUpdate::Record(record) => {
+                            let session = &mut tui.sessions[index];
+                            session.diagnostics.update(record.log.clone());
+                            session.history.push(record);
+                        }
+                        SessionUpdate::Status(status) => {
+                            tui.sessions[index].status = status;
+                        }
+                        SessionUpdate::Closed => {
+                            tui.quit = true;
+                        }
                     },
                     None => {
                         tui.quit = true;
-                    },
+                    }
                 }
             }
 
             line = tracing_reader.next_line() => {
                 let line = line.into_diagnostic().wrap_err("Failed to read line from tracing")?;
                 if let Some(line) = line {
-                    tui.push_line(line);
+                    for index in 0..tui.sessions.len() {
+                        tui.push_session_line(index, LineSource::Tracing, line.clone());
+                    }
                 }
             }
 
@@ -264,6 +627,14 @@ pub async fn run_tui(
                 // TODO: `get_frame` is an expensive call, delay if possible.
                 // https://github.com/MercuryTechnologies/ghciwatch/pull/206#discussion_r1508364135
                 tui.handle_event(event)?;
+
+                if std::mem::take(&mut tui.open_editor) {
+                    // Stop reading terminal input so the editor gets all of it, and don't block
+                    // the other tasks on this worker while the editor runs.
+                    drop(event_stream);
+                    tokio::task::block_in_place(|| tui.open_in_editor())?;
+                    event_stream = EventStream::new();
+                }
             }
         }
     }
@@ -0,0 +1,407 @@
+//! The TUI's scrollback buffer, containing lines from `ghci` and `tracing` log messages.
+
+use std::fmt::Display;
+
+use ansi_to_tui::IntoText;
+use miette::IntoDiagnostic;
+use ratatui::style::Color;
+use ratatui::style::Modifier;
+use ratatui::style::Style;
+use ratatui::text::Text;
+
+use crate::buffers::TUI_SCROLLBACK_CAPACITY;
+
+/// Where a line in the scrollback came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineSource {
+    /// Output from the `ghci` session.
+    Ghci,
+    /// A `tracing` log message from `ghciwatch` itself.
+    Tracing,
+}
+
+/// Which lines in the scrollback to show.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ScrollbackFilter {
+    /// Show every line.
+    #[default]
+    All,
+    /// Only show output from the `ghci` session.
+    Ghci,
+    /// Only show `tracing` log messages.
+    Tracing,
+    /// Only show warnings and errors, from either source.
+    Problems,
+}
+
+impl ScrollbackFilter {
+    /// Get the next filter in the cycle.
+    pub fn next(self) -> Self {
+        match self {
+            Self::All => Self::Ghci,
+            Self::Ghci => Self::Tracing,
+            Self::Tracing => Self::Problems,
+            Self::Problems => Self::All,
+        }
+    }
+
+    fn matches(self, line: &ScrollbackLine) -> bool {
+        match self {
+            Self::All => true,
+            Self::Ghci => line.source == LineSource::Ghci,
+            Self::Tracing => line.source == LineSource::Tracing,
+            Self::Problems => line.problem,
+        }
+    }
+}
+
+impl Display for ScrollbackFilter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::All => write!(f, "all"),
+            Self::Ghci => write!(f, "ghci"),
+            Self::Tracing => write!(f, "logs"),
+            Self::Problems => write!(f, "warnings and errors"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ScrollbackLine {
+    source: LineSource,
+    /// The line, possibly containing ANSI escapes.
+    text: String,
+    /// The line without ANSI escapes, for searching.
+    plain: String,
+    /// Is this line part of a warning or error?
+    problem: bool,
+}
+
+/// An active search.
+#[derive(Debug)]
+struct Search {
+    query: String,
+    /// Indexes of the visible lines matching the query.
+    matches: Vec<usize>,
+    /// Index into `matches` of the current match.
+    current: usize,
+}
+
+impl Search {
+    fn is_match(&self, plain: &str) -> bool {
+        // Smart case: only match case-sensitively if the query has uppercase letters.
+        if self.query.chars().any(char::is_uppercase) {
+            plain.contains(&self.query)
+        } else {
+            plain.to_lowercase().contains(&self.query)
+        }
+    }
+}
+
+/// Lines of output, with filtering and searching.
+#[derive(Debug)]
+pub struct Scrollback {
+    lines: Vec<ScrollbackLine>,
+    filter: ScrollbackFilter,
+    /// Indexes into `lines` of the lines shown by the current `filter`.
+    visible: Vec<usize>,
+    search: Option<Search>,
+    /// Are we in the middle of a `ghci` diagnostic?
+    in_ghci_problem: bool,
+    /// Are we in the middle of a warning or error `tracing` message?
+    in_tracing_problem: bool,
+}
+
+impl Default for Scrollback {
+    fn default() -> Self {
+        Self {
+            lines: Vec::with_capacity(TUI_SCROLLBACK_CAPACITY),
+            filter: Default::default(),
+            visible: Vec::with_capacity(TUI_SCROLLBACK_CAPACITY),
+            search: None,
+            in_ghci_problem: false,
+            in_tracing_problem: false,
+        }
+    }
+}
+
+impl Scrollback {
+    /// Add a line to the scrollback.
+    ///
+    /// Returns `true` if the line is shown by the current filter.
+    pub fn push(&mut self, source: LineSource, text: String) -> bool {
+        let plain = strip_ansi_escapes::strip_str(&text);
+        let problem = self.is_problem(source, &plain);
+        let line = ScrollbackLine {
+            source,
+            text,
+            plain,
+            problem,
+        };
+
+        let visible = self.filter.matches(&line);
+        if visible {
+            if let Some(search) = &mut self.search {
+                if search.is_match(&line.plain) {
+                    search.matches.push(self.visible.len());
+                }
+            }
+            self.visible.push(self.lines.len());
+        }
+        self.lines.push(line);
+        visible
+    }
+
+    /// Determine if a line is part of a warning or error, tracking multi-line messages.
+    fn is_problem(&mut self, source: LineSource, plain: &str) -> bool {
+        let continues = |plain: &str| plain.starts_with(char::is_whitespace);
+        match source {
+            LineSource::Ghci => {
+                // Diagnostics look like `src/MyLib.hs:4:11: error: [GHC-83865]`, followed by
+                // indented lines and code snippets like `4 | someFunc = ()`.
+                let starts = plain.contains(": error:") || plain.contains(": warning:");
+                let snippet = plain
+                    .split_once(" |")
+                    .is_some_and(|(line, _)| line.chars().all(|c| c.is_ascii_digit()));
+                self.in_ghci_problem =
+                    starts || (self.in_ghci_problem && (continues(plain) || snippet));
+                self.in_ghci_problem
+            }
+            LineSource::Tracing => {
+                // Warnings and errors are formatted like `⚠ Message`, with continuation lines
+                // indented.
+                self.in_tracing_problem =
+                    plain.starts_with('⚠') || (self.in_tracing_problem && continues(plain));
+                self.in_tracing_problem
+            }
+        }
+    }
+
+    /// The number of lines shown by the current filter.
+    pub fn visible_len(&self) -> usize {
+        self.visible.len()
+    }
+
+    /// The current filter.
+    pub fn filter(&self) -> ScrollbackFilter {
+        self.filter
+    }
+
+    /// Change the filter.
+    pub fn set_filter(&mut self, filter: ScrollbackFilter) {
+        self.filter = filter;
+        self.visible = self
+            .lines
+            .iter()
+            .enumerate()
+            .filter(|(_, line)| filter.matches(line))
+            .map(|(i, _)| i)
+            .collect();
+        if let Some(search) = self.search.take() {
+            self.set_search(search.query, 0);
+        }
+    }
+
+    /// Start a new search, returning the first matching line at or after `from`, if any.
+    ///
+    /// An empty query clears the search.
+    pub fn set_search(&mut self, query: String, from: usize) -> Option<usize> {
+        if query.is_empty() {
+            self.search = None;
+            return None;
+        }
+
+        let mut search = Search {
+            query,
+            matches: Vec::new(),
+            current: 0,
+        };
+        search.matches = self
+            .visible
+            .iter()
+            .enumerate()
+            .filter(|(_, &i)| search.is_match(&self.lines[i].plain))
+            .map(|(visible_index, _)| visible_index)
+            .collect();
+        search.current = search
+            .matches
+            .iter()
+            .position(|&line| line >= from)
+            .unwrap_or(0);
+
+        let ret = search.matches.get(search.current).copied();
+        self.search = Some(search);
+        ret
+    }
+
+    /// Clear the search.
+    pub fn clear_search(&mut self) {
+        self.search = None;
+    }
+
+    /// Move to the next match, returning its line.
+    pub fn next_match(&mut self) -> Option<usize> {
+        let search = self.search.as_mut()?;
+        if search.matches.is_empty() {
+            return None;
+        }
+        search.current = (search.current + 1) % search.matches.len();
+        Some(search.matches[search.current])
+    }
+
+    /// Move to the previous match, returning its line.
+    pub fn previous_match(&mut self) -> Option<usize> {
+        let search = self.search.as_mut()?;
+        if search.matches.is_empty() {
+            return None;
+        }
+        search.current = search
+            .current
+            .checked_sub(1)
+            .unwrap_or(search.matches.len() - 1);
+        Some(search.matches[search.current])
+    }
+
+    /// Describe the current search, like `/puppy (2 of 5)`.
+    pub fn search_summary(&self) -> Option<String> {
+        let search = self.search.as_ref()?;
+        Some(if search.matches.is_empty() {
+            format!("/{} (no matches)", search.query)
+        } else {
+            format!(
+                "/{} ({} of {})",
+                search.query,
+                search.current + 1,
+                search.matches.len()
+            )
+        })
+    }
+
+    /// Get the visible lines as styled text, with search matches highlighted.
+    pub fn to_text(&self) -> miette::Result<Text<'static>> {
+        let mut text = Text::default();
+        for &i in &self.visible {
+            text.extend(self.lines[i].text.into_text().into_diagnostic()?);
+        }
+
+        if let Some(search) = &self.search {
+            for (n, &line) in search.matches.iter().enumerate() {
+                let style = if n == search.current {
+                    Style::new().bg(Color::Yellow).fg(Color::Black)
+                } else {
+                    Style::new().add_modifier(Modifier::UNDERLINED)
+                };
+                if let Some(line) = text.lines.get_mut(line) {
+                    *line = std::mem::take(line).patch_style(style);
+                }
+            }
+        }
+
+        Ok(text)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    fn scrollback() -> Scrollback {
+        let mut scrollback = Scrollback::default();
+        for (source, line) in [
+            (LineSource::Tracing, "• Reloading ghci:"),
+            (
+                LineSource::Ghci,
+                "[1 of 2] Compiling MyLib ( src/MyLib.hs, interpreted )",
+            ),
+            (LineSource::Ghci, "src/MyLib.hs:4:12: error: [GHC-83865]"),
+            (LineSource::Ghci, "    • Couldn't match expected type"),
+            (LineSource::Ghci, "  |"),
+            (LineSource::Ghci, "4 | someFunc = ()"),
+            (LineSource::Ghci, "Failed, one module loaded."),
+            (LineSource::Tracing, "⚠ Reload failed in 0.12s"),
+            (LineSource::Tracing, "• Finished"),
+        ] {
+            scrollback.push(source, line.to_owned());
+        }
+        scrollback
+    }
+
+    fn visible(scrollback: &Scrollback) -> Vec<&str> {
+        scrollback
+            .visible
+            .iter()
+            .map(|&i| scrollback.lines[i].plain.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_filter() {
+        let mut scrollback = scrollback();
+        assert_eq!(scrollback.visible_len(), 9);
+
+        scrollback.set_filter(ScrollbackFilter::Tracing);
+        assert_eq!(
+            visible(&scrollback),
+            [
+                "• Reloading ghci:",
+                "⚠ Reload failed in 0.12s",
+                "• Finished"
+            ]
+        );
+
+        scrollback.set_filter(ScrollbackFilter::Problems);
+        assert_eq!(
+            visible(&scrollback),
+            [
+                "src/MyLib.hs:4:12: error: [GHC-83865]",
+                "    • Couldn't match expected type",
+                "  |",
+                "4 | someFunc = ()",
+                "⚠ Reload failed in 0.12s",
+            ]
+        );
+
+        // New lines respect the filter.
+        assert!(!scrollback.push(LineSource::Ghci, "Ok, one module loaded.".to_owned()));
+        assert!(scrollback.push(LineSource::Ghci, "Foo.hs:1:1: warning:".to_owned()));
+        assert_eq!(scrollback.visible_len(), 6);
+    }
+
+    #[test]
+    fn test_search() {
+        let mut scrollback = scrollback();
+        assert_eq!(scrollback.search_summary(), None);
+
+        assert_eq!(scrollback.set_search("mylib".to_owned(), 0), Some(1));
+        assert_eq!(
+            scrollback.search_summary().as_deref(),
+            Some("/mylib (1 of 2)")
+        );
+        assert_eq!(scrollback.next_match(), Some(2));
+        assert_eq!(scrollback.next_match(), Some(1));
+        assert_eq!(scrollback.previous_match(), Some(2));
+
+        // Searches start from the given line.
+        assert_eq!(scrollback.set_search("mylib".to_owned(), 2), Some(2));
+
+        // Uppercase queries are case-sensitive.
+        assert_eq!(scrollback.set_search("Mylib".to_owned(), 0), None);
+        assert_eq!(
+            scrollback.search_summary().as_deref(),
+            Some("/Mylib (no matches)")
+        );
+
+        // Matches are updated when the filter changes.
+        scrollback.set_search("reload".to_owned(), 0);
+        scrollback.set_filter(ScrollbackFilter::Problems);
+        assert_eq!(
+            scrollback.search_summary().as_deref(),
+            Some("/reload (1 of 1)")
+        );
+
+        scrollback.clear_search();
+        assert_eq!(scrollback.search_summary(), None);
+    }
+}
@@ -0,0 +1,133 @@
+//! The TUI's status bar, showing what the `ghci` session is doing.
+
+use ratatui::style::Color;
+use ratatui::style::Modifier;
+use ratatui::style::Style;
+use ratatui::text::Line;
+use ratatui::text::Span;
+
+use crate::ghci::GhciPhase;
+use crate::ghci::GhciStatus;
+
+/// Separator between status bar sections.
+const SEPARATOR: &str = " │ ";
+
+/// Format a session's name as a tab title, colored by its status.
+pub fn tab_title(name: &str, status: &GhciStatus) -> Line<'static> {
+    let color = match status.phase {
+        GhciPhase::Idle if status.errors > 0 => Color::Red,
+        GhciPhase::Idle => Color::Green,
+        GhciPhase::RunningTests => Color::Blue,
+        _ => Color::Yellow,
+    };
+    Line::styled(name.to_owned(), Style::new().fg(color))
+}
+
+/// Format the session status as a single line.
+pub fn status_line(status: &GhciStatus) -> Line<'static> {
+    let (phase, color) = match &status.phase {
+        GhciPhase::Starting => ("Starting".to_owned(), Color::Yellow),
+        GhciPhase::Reloading => ("Reloading".to_owned(), Color::Yellow),
+        GhciPhase::Compiling(progress) => (progress.to_string(), Color::Yellow),
+        GhciPhase::RunningTests => ("Running tests".to_owned(), Color::Blue),
+        GhciPhase::Idle => ("Idle".to_owned(), Color::Green),
+    };
+
+    let mut spans = vec![Span::styled(
+        phase,
+        Style::new().fg(color).add_modifier(Modifier::BOLD),
+    )];
+
+    if let Some(duration) = status.last_duration {
+        spans.push(Span::raw(SEPARATOR));
+        spans.push(Span::raw(format!("Last load {duration:.2?}")));
+    }
+
+    if let Some(summary) = status.summary {
+        spans.push(Span::raw(SEPARATOR));
+        spans.push(Span::raw(pluralize(
+            summary.modules_loaded,
+            "module",
+            "modules",
+        )));
+        spans.push(Span::raw(" loaded"));
+    }
+
+    if status.last_duration.is_some() {
+        spans.push(Span::raw(SEPARATOR));
+        spans.push(Span::styled(
+            pluralize(status.errors, "error", "errors"),
+            if status.errors > 0 {
+                Style::new().fg(Color::Red)
+            } else {
+                Style::new()
+            },
+        ));
+        spans.push(Span::raw(", "));
+        spans.push(Span::styled(
+            pluralize(status.warnings, "warning", "warnings"),
+            if status.warnings > 0 {
+                Style::new().fg(Color::Yellow)
+            } else {
+                Style::new()
+            },
+        ));
+    }
+
+    Line::from(spans)
+}
+
+fn pluralize(count: usize, singular: &str, plural: &str) -> String {
+    if count == 1 {
+        format!("{count} {singular}")
+    } else {
+        format!("{count} {plural}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    use pretty_assertions::assert_eq;
+
+    use crate::ghci::parse::CompilationResult;
+    use crate::ghci::parse::CompilationSummary;
+    use crate::ghci::parse::CompilingProgress;
+    use crate::ghci::ReloadProgress;
+
+    fn plain_text(line: Line<'_>) -> String {
+        line.spans
+            .into_iter()
+            .map(|span| span.content.into_owned())
+            .collect()
+    }
+
+    #[test]
+    fn test_status_line() {
+        assert_eq!(plain_text(status_line(&GhciStatus::default())), "Starting");
+
+        assert_eq!(
+            plain_text(status_line(&GhciStatus {
+                phase: GhciPhase::Compiling(ReloadProgress {
+                    progress: CompilingProgress {
+                        current: 3,
+                        total: 10
+                    },
+                    module: "MyLib".to_owned(),
+                    remaining: Some(Duration::from_secs(4)),
+                }),
+                last_duration: Some(Duration::from_millis(1500)),
+                summary: Some(CompilationSummary {
+                    result: CompilationResult::Err,
+                    modules_loaded: 1,
+                }),
+                errors: 1,
+                warnings: 2,
+            })),
+            "[3/10] Compiling MyLib, ~4s left │ Last load 1.50s │ 1 module loaded │ 1 error, 2 warnings"
+        );
+    }
+}
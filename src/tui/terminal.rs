@@ -54,25 +54,44 @@ static INSIDE: AtomicBool = AtomicBool::new(false);
 /// Enter raw-mode for the terminal on stdout, set up a panic hook, etc.
 #[instrument(level = "debug")]
 pub fn enter() -> miette::Result<TerminalGuard> {
-    use event::KeyboardEnhancementFlags as KEF;
-
     if INSIDE.load(Ordering::SeqCst) {
         return Err(miette!(
             "Cannot enter raw mode; the terminal is already set up"
         ));
     }
 
+    setup()?;
+
+    let previous_hook = panic::take_hook();
+
+    panic::set_hook(Box::new(move |panic_info| {
+        // Ignoring the `Result` because we're already panicking; aborting is undesirable
+        let _ = exit();
+        previous_hook(panic_info);
+    }));
+
+    let backend = CrosstermBackend::new(std::io::stdout());
+
+    let terminal = Terminal::new(backend)
+        .into_diagnostic()
+        .wrap_err("Failed to create ratatui terminal")?;
+
+    Ok(TerminalGuard { terminal })
+}
+
+/// Enable raw mode and the alternate screen, mouse capture, etc.
+fn setup() -> miette::Result<()> {
+    use event::KeyboardEnhancementFlags as KEF;
+
     // Set `INSIDE` immediately so that a partial load is rolled back by `exit()`.
     INSIDE.store(true, Ordering::SeqCst);
 
-    let mut stdout = std::io::stdout();
-
     terminal::enable_raw_mode()
         .into_diagnostic()
         .wrap_err("Failed to enable raw mode")?;
 
     crossterm::execute!(
-        stdout,
+        std::io::stdout(),
         terminal::EnterAlternateScreen,
         cursor::Hide,
         event::EnableMouseCapture,
@@ -87,21 +106,23 @@ pub fn enter() -> miette::Result<TerminalGuard> {
     .into_diagnostic()
     .wrap_err("Failed to execute crossterm commands")?;
 
-    let previous_hook = panic::take_hook();
-
-    panic::set_hook(Box::new(move |panic_info| {
-        // Ignoring the `Result` because we're already panicking; aborting is undesirable
-        let _ = exit();
-        previous_hook(panic_info);
-    }));
-
-    let backend = CrosstermBackend::new(stdout);
+    Ok(())
+}
 
-    let terminal = Terminal::new(backend)
+/// Temporarily leave raw mode to run `f`, then set the terminal up again.
+///
+/// This lets us run interactive programs, like a text editor.
+#[instrument(level = "debug", skip_all)]
+pub fn suspend<T>(terminal: &mut TerminalGuard, f: impl FnOnce() -> T) -> miette::Result<T> {
+    exit()?;
+    let ret = f();
+    setup()?;
+    // The screen contents are gone, so make sure the next frame is drawn in full.
+    terminal
+        .clear()
         .into_diagnostic()
-        .wrap_err("Failed to create ratatui terminal")?;
-
-    Ok(TerminalGuard { terminal })
+        .wrap_err("Failed to clear terminal")?;
+    Ok(ret)
 }
 
 /// Exits terminal raw-mode.
@@ -1,5 +1,11 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::sync::Mutex;
 use std::time::Duration;
 
+use camino::Utf8Path;
+use camino::Utf8PathBuf;
+use itertools::Itertools;
 use miette::miette;
 use miette::IntoDiagnostic;
 use notify_debouncer_full::notify;
@@ -12,24 +18,30 @@ use notify_debouncer_full::Debouncer;
 use notify_debouncer_full::FileIdMap;
 use tokio::runtime::Handle;
 use tokio::sync::mpsc;
+use tokio::sync::watch;
 use tokio::task::block_in_place;
 use tracing::instrument;
 
 use crate::cli::Opts;
 use crate::event_filter::file_events_from_action;
+use crate::event_filter::FileEvent;
 use crate::ghci::manager::GhciEvent;
+use crate::ghci::GhciOpts;
+use crate::ignore::GlobMatcher;
 use crate::normal_path::NormalPath;
 use crate::shutdown::ShutdownHandle;
 
 /// Options for [`run_watcher`]. This is like a lower-effort builder interface, mostly
 /// provided because Rust tragically lacks named arguments.
 pub struct WatcherOpts {
-    /// The paths to watch for changes.
-    pub watch: Vec<NormalPath>,
     /// Debounce duration for filesystem events.
     pub debounce: Duration,
     /// If given, use the polling file watcher with the given duration as the poll interval.
     pub poll: Option<Duration>,
+    /// The configuration file to watch for changes, if any.
+    pub config: Option<NormalPath>,
+    /// The `ghci` sessions to send file events to.
+    pub sessions: Vec<WatchedSession>,
 }
 
 impl WatcherOpts {
@@ -37,33 +49,82 @@ impl WatcherOpts {
     ///
     /// This extracts the bits of an [`Opts`] struct relevant to the [`run_watcher`] session
     /// without cloning or taking ownership of the entire thing.
+    ///
+    /// Sessions to send file events to should be added to [`WatcherOpts::sessions`].
     pub fn from_cli(opts: &Opts) -> Self {
         Self {
-            watch: opts.watch.paths.clone(),
             debounce: opts.watch.debounce,
             poll: opts.watch.poll,
+            config: opts.config.clone(),
+            sessions: Vec::new(),
         }
     }
 }
 
+/// A `ghci` session for [`run_watcher`] to send file events to.
+pub struct WatchedSession {
+    /// The session's name, if several sessions are running.
+    pub name: Option<String>,
+    /// Where to send file events.
+    pub sender: mpsc::Sender<GhciEvent>,
+    /// The paths to watch for changes.
+    pub watch: Vec<NormalPath>,
+    /// Individual files to watch for changes, like the project's `.cabal` files. These may be
+    /// outside of the `watch` paths.
+    pub files: Vec<NormalPath>,
+    /// Changes to paths matching these globs are sent to this session, even if they're outside of
+    /// its `watch` paths.
+    ///
+    /// These are shared with the session, which updates them when the configuration file changes.
+    pub globs: Arc<Mutex<GlobMatcher>>,
+    /// If given, also watch the `ghci` session's module import search paths, updated as they're
+    /// received.
+    pub search_paths: Option<watch::Receiver<Vec<Utf8PathBuf>>>,
+}
+
+impl WatchedSession {
+    /// Construct a session to watch files for from parsed command-line interface arguments as
+    /// [`Opts`] and the session's [`GhciOpts`].
+    pub fn from_cli(
+        opts: &Opts,
+        ghci_opts: &GhciOpts,
+        sender: mpsc::Sender<GhciEvent>,
+    ) -> miette::Result<Self> {
+        let files = match &opts.project {
+            Some(project) => project
+                .files
+                .iter()
+                .map(NormalPath::from_cwd)
+                .collect::<miette::Result<_>>()?,
+            None => Vec::new(),
+        };
+        Ok(Self {
+            name: ghci_opts.session.clone(),
+            sender,
+            watch: opts.watch.paths.clone(),
+            files,
+            globs: ghci_opts.watched_globs.clone(),
+            search_paths: ghci_opts
+                .search_paths_sender
+                .as_ref()
+                .map(|sender| sender.subscribe()),
+        })
+    }
+}
+
 /// A [`notify`] watcher which waits for file changes and sends reload events to the contained
-/// `ghci` session.
+/// `ghci` sessions.
 #[instrument(level = "debug", skip_all)]
-pub async fn run_watcher(
-    handle: ShutdownHandle,
-    ghci_sender: mpsc::Sender<GhciEvent>,
-    opts: WatcherOpts,
-) -> miette::Result<()> {
+pub async fn run_watcher(handle: ShutdownHandle, opts: WatcherOpts) -> miette::Result<()> {
     if opts.poll.is_some() {
-        run_debouncer::<PollWatcher>(handle, ghci_sender, opts).await
+        run_debouncer::<PollWatcher>(handle, opts).await
     } else {
-        run_debouncer::<RecommendedWatcher>(handle, ghci_sender, opts).await
+        run_debouncer::<RecommendedWatcher>(handle, opts).await
     }
 }
 
 async fn run_debouncer<T: notify::Watcher>(
     mut handle: ShutdownHandle,
-    ghci_sender: mpsc::Sender<GhciEvent>,
     opts: WatcherOpts,
 ) -> miette::Result<()> {
     let mut config = notify::Config::default();
@@ -71,10 +132,39 @@ async fn run_debouncer<T: notify::Watcher>(
         config = config.with_poll_interval(interval);
     }
 
+    // Updates to each session's search paths, by index.
+    let (search_paths_sender, mut search_paths_receiver) = mpsc::unbounded_channel();
+    let mut routes = Vec::with_capacity(opts.sessions.len());
+    for (index, session) in opts.sessions.into_iter().enumerate() {
+        if let Some(mut receiver) = session.search_paths {
+            let sender = search_paths_sender.clone();
+            tokio::task::spawn(async move {
+                // If the `ghci` session is gone, we'll be shutting down shortly.
+                while receiver.changed().await.is_ok() {
+                    let search_paths = receiver.borrow_and_update().clone();
+                    if sender.send((index, search_paths)).is_err() {
+                        break;
+                    }
+                }
+            });
+        }
+        routes.push(SessionRoute {
+            name: session.name,
+            sender: session.sender,
+            watch: Arc::new(Mutex::new(session.watch.clone())),
+            static_watch: session.watch,
+            files: session.files,
+            globs: session.globs,
+        });
+    }
+    let routes = Arc::new(routes);
+    let static_roots = roots(routes.iter().flat_map(|route| route.static_watch.iter()));
+
     let event_handler = EventHandler {
         handle: Handle::current(),
-        ghci_sender,
         shutdown: handle.clone(),
+        config: opts.config.clone(),
+        sessions: routes.clone(),
     };
 
     let cache = FileIdMap::new();
@@ -93,31 +183,163 @@ async fn run_debouncer<T: notify::Watcher>(
 
     {
         let watcher = debouncer.watcher();
-        for path in &opts.watch {
+        for path in &static_roots {
             watcher
                 .watch(path.as_std_path(), RecursiveMode::Recursive)
                 .into_diagnostic()?;
         }
         let mut cache = debouncer.cache();
-        for path in &opts.watch {
+        for path in &static_roots {
             cache.add_root(path.as_std_path(), RecursiveMode::Recursive);
         }
     }
 
+    // Watch the directories of the configuration file and the other individual files (rather
+    // than the files themselves, which editors often replace when saving), unless they're already
+    // being watched.
+    let dirs = opts
+        .config
+        .iter()
+        .chain(routes.iter().flat_map(|route| route.files.iter()))
+        .filter_map(|file| file.absolute().parent())
+        .filter(|dir| !static_roots.iter().any(|path| dir.starts_with(path)))
+        .collect::<BTreeSet<_>>();
+    for dir in dirs {
+        debouncer
+            .watcher()
+            .watch(dir.as_std_path(), RecursiveMode::NonRecursive)
+            .into_diagnostic()?;
+    }
+
     tracing::debug!("notify watcher started");
 
-    // Wait for a shutdown request, either from another subsystem or from an error in the handler.
-    let _ = handle.on_shutdown_requested().await;
+    // Each session's search paths.
+    let mut search_paths = vec![BTreeSet::new(); routes.len()];
+    // The search paths we're watching, which aren't covered by the static roots.
+    let mut watched_search_paths = BTreeSet::new();
+    loop {
+        tokio::select! {
+            // Wait for a shutdown request, either from another subsystem or from an error in the
+            // handler.
+            _ = handle.on_shutdown_requested() => {
+                break;
+            }
+            Some((index, new_search_paths)) = search_paths_receiver.recv() => {
+                let route = &routes[index];
+                let new_search_paths = new_search_paths
+                    .into_iter()
+                    .map(NormalPath::from_cwd)
+                    .collect::<miette::Result<BTreeSet<_>>>()?;
+                if new_search_paths != search_paths[index] {
+                    tracing::info!(
+                        "Watching search paths{}: {}",
+                        route.label(),
+                        new_search_paths.iter().map(|path| path.to_string()).join(", ")
+                    );
+                }
+                *route.watch.lock().expect("Watched paths lock was poisoned") = route
+                    .static_watch
+                    .iter()
+                    .chain(&new_search_paths)
+                    .cloned()
+                    .collect();
+                search_paths[index] = new_search_paths;
+
+                let new_watched_search_paths = roots(search_paths.iter().flatten())
+                    .into_iter()
+                    .filter(|path| !static_roots.iter().any(|root| path.starts_with(root)))
+                    .collect::<BTreeSet<_>>();
+                for path in watched_search_paths.difference(&new_watched_search_paths) {
+                    tracing::debug!(%path, "No longer watching search path");
+                    debouncer
+                        .watcher()
+                        .unwatch(path.as_std_path())
+                        .into_diagnostic()?;
+                    debouncer.cache().remove_root(path.as_std_path());
+                }
+                for path in new_watched_search_paths.difference(&watched_search_paths) {
+                    tracing::debug!(%path, "Watching search path");
+                    debouncer
+                        .watcher()
+                        .watch(path.as_std_path(), RecursiveMode::Recursive)
+                        .into_diagnostic()?;
+                    debouncer
+                        .cache()
+                        .add_root(path.as_std_path(), RecursiveMode::Recursive);
+                }
+                watched_search_paths = new_watched_search_paths;
+            }
+        }
+    }
 
     block_in_place(|| debouncer.stop());
 
     Ok(())
 }
 
+/// Get the paths which aren't inside of any of the other paths, so they can be watched
+/// recursively without watching anything twice.
+fn roots<'a>(paths: impl IntoIterator<Item = &'a NormalPath>) -> BTreeSet<NormalPath> {
+    let paths = paths.into_iter().collect::<BTreeSet<_>>();
+    paths
+        .iter()
+        .filter(|path| {
+            !paths
+                .iter()
+                .any(|other| other != *path && path.starts_with(other))
+        })
+        .map(|path| (*path).clone())
+        .collect()
+}
+
+/// Where to send the file events for a session.
+struct SessionRoute {
+    name: Option<String>,
+    sender: mpsc::Sender<GhciEvent>,
+    /// The paths being watched for changes, including any search paths.
+    watch: Arc<Mutex<Vec<NormalPath>>>,
+    /// The paths being watched for changes, not including search paths.
+    static_watch: Vec<NormalPath>,
+    /// Individual files being watched for changes.
+    files: Vec<NormalPath>,
+    /// Paths matching these globs are sent to the session, updated when the configuration file
+    /// changes.
+    globs: Arc<Mutex<GlobMatcher>>,
+}
+
+impl SessionRoute {
+    /// Does this session want events for the given path?
+    fn matches(&self, path: &Utf8Path) -> bool {
+        self.watch
+            .lock()
+            .expect("Watched paths lock was poisoned")
+            .iter()
+            .any(|watch| path.starts_with(watch))
+            || self.files.iter().any(|file| path == file.absolute())
+            || self
+                .globs
+                .lock()
+                .expect("Watched globs lock was poisoned")
+                .matched(path)
+                .is_whitelist()
+    }
+
+    /// A label for log messages, like ` for lib`.
+    fn label(&self) -> String {
+        match &self.name {
+            Some(name) => format!(" for {name}"),
+            None => String::new(),
+        }
+    }
+}
+
 struct EventHandler {
     handle: Handle,
-    ghci_sender: mpsc::Sender<GhciEvent>,
     shutdown: ShutdownHandle,
+    /// The configuration file, if any.
+    config: Option<NormalPath>,
+    /// The sessions to send events to.
+    sessions: Arc<Vec<SessionRoute>>,
 }
 
 impl EventHandler {
@@ -145,15 +367,47 @@ impl EventHandler {
         // TODO: On Linux, sometimes we get a "new directory" event but none of the events for
         // files inside of it. When we get new directories, we should paw through them with
         // `walkdir` or something to check for files.
-        let events = file_events_from_action(events)?;
-        if events.is_empty() {
-            tracing::debug!("No relevant file events");
-        } else {
-            tracing::trace!(?events, "Processed events");
-            self.ghci_sender
-                .send(GhciEvent::Reload { events })
-                .await
-                .into_diagnostic()?;
+        let mut events = file_events_from_action(events)?;
+
+        let changed_config = self.config.as_ref().filter(|config| {
+            events
+                .iter()
+                .any(|event| matches!(event, FileEvent::Modify(path) if path == config.absolute()))
+        });
+        events.retain(|event| {
+            self.config.as_ref().map(NormalPath::absolute) != Some(event.as_path())
+        });
+
+        if let Some(config) = changed_config {
+            tracing::info!("Configuration file {config} changed");
+            for session in self.sessions.iter() {
+                session
+                    .sender
+                    .send(GhciEvent::ReloadConfig)
+                    .await
+                    .into_diagnostic()?;
+            }
+        }
+
+        for session in self.sessions.iter() {
+            // We may be watching the directories of individual files or other sessions' paths, so
+            // ignore events for anything else.
+            let events = events
+                .iter()
+                .filter(|event| session.matches(event.as_path()))
+                .cloned()
+                .collect::<BTreeSet<_>>();
+
+            if events.is_empty() {
+                tracing::debug!("No relevant file events{}", session.label());
+            } else {
+                tracing::trace!(?events, "Processed events{}", session.label());
+                session
+                    .sender
+                    .send(GhciEvent::Reload { events })
+                    .await
+                    .into_diagnostic()?;
+            }
         }
 
         Ok(())
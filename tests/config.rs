@@ -0,0 +1,47 @@
+use test_harness::test;
+use test_harness::Fs;
+use test_harness::GhciWatchBuilder;
+
+/// Test that a `reload-glob` added to the configuration file while `ghciwatch` is running
+/// triggers reloads for paths outside of the `--watch` paths.
+#[test]
+async fn can_reload_glob_from_changed_config() {
+    let mut session = GhciWatchBuilder::new("tests/data/simple")
+        .before_start(|project_root| async move {
+            Fs::new()
+                .write(project_root.join("ghciwatch.toml"), "")
+                .await
+        })
+        .start()
+        .await
+        .expect("ghciwatch starts");
+    session
+        .wait_until_ready()
+        .await
+        .expect("ghciwatch loads ghci");
+
+    session
+        .fs()
+        .write(
+            session.path("ghciwatch.toml"),
+            "reload-glob = [\"**/*.graphql\"]\n",
+        )
+        .await
+        .unwrap();
+    session
+        .wait_for_log("Reloaded configuration")
+        .await
+        .expect("ghciwatch reloads the configuration file");
+
+    // The project root isn't a `--watch` path, but it's watched for changes to the configuration
+    // file.
+    session
+        .fs()
+        .touch(session.path("schema.graphql"))
+        .await
+        .unwrap();
+    session
+        .wait_until_reload()
+        .await
+        .expect("ghciwatch reloads when a file matching the new glob is created");
+}
@@ -31,6 +31,37 @@ async fn can_write_error_log() {
     .assert_eq(&error_contents);
 }
 
+/// Test that `ghciwatch --error-file-json ...` can write the JSON error log.
+#[test]
+async fn can_write_json_error_log() {
+    let error_path = "ghciwatch.json";
+    let mut session = GhciWatchBuilder::new("tests/data/simple")
+        .with_args(["--error-file-json", error_path])
+        .start()
+        .await
+        .expect("ghciwatch starts");
+    let error_path = session.path(error_path);
+    session
+        .wait_until_ready()
+        .await
+        .expect("ghciwatch loads ghci");
+    let error_contents = session
+        .fs()
+        .read(&error_path)
+        .await
+        .expect("ghciwatch writes ghciwatch.json");
+    expect![[r#"
+        {
+          "summary": {
+            "result": "ok",
+            "modules_loaded": 3
+          },
+          "diagnostics": []
+        }
+    "#]]
+    .assert_eq(&error_contents);
+}
+
 /// Test that `ghciwatch --errors ...` can write compilation errors.
 /// Then, test that it can reload when modules are changed and will correctly rewrite the error log
 /// once it's fixed.
@@ -254,6 +254,60 @@ async fn hooks_can_observe_error_log() {
         .unwrap();
 }
 
+/// Test that a `when-changed` hook only runs when a matching path triggers a reload, e.g. to
+/// regenerate code when a schema changes.
+#[test]
+async fn can_run_hooks_when_changed() {
+    let mut session = GhciWatchBuilder::new("tests/data/simple")
+        .with_args([
+            "--reload-glob",
+            "**/*.graphql",
+            "--after-reload-shell",
+            "when-changed=**/*.graphql:touch codegen-ran",
+        ])
+        .start()
+        .await
+        .expect("ghciwatch starts");
+    let codegen_ran = session.path("codegen-ran");
+    session
+        .wait_until_ready()
+        .await
+        .expect("ghciwatch loads ghci");
+
+    // Changing a module doesn't run the hook.
+    session
+        .fs()
+        .touch(session.path("src/MyLib.hs"))
+        .await
+        .unwrap();
+    session
+        .wait_for_log(BaseMatcher::reload_completes())
+        .await
+        .expect("ghciwatch reloads");
+    assert!(
+        !codegen_ran.exists(),
+        "ghciwatch doesn't run the hook when no matching path changed"
+    );
+
+    session
+        .fs()
+        .touch(session.path("src/Query.graphql"))
+        .await
+        .unwrap();
+    session
+        .wait_for_log(
+            BaseMatcher::message("Running after-reload command")
+                .with_field("command", "touch codegen-ran"),
+        )
+        .await
+        .expect("ghciwatch runs the hook when a matching path changes");
+    session
+        .fs()
+        .wait_for_path(Duration::from_secs(10), &codegen_ran)
+        .await
+        .unwrap();
+}
+
 fn shell_requote(cmd: &str) -> String {
     shell_words::join(shell_words::split(cmd).unwrap())
 }
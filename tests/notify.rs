@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use test_harness::test;
+use test_harness::GhciWatchBuilder;
+
+/// Test that `ghciwatch --notify-command ...` runs the command when compilation starts failing and
+/// when it's fixed, but not for the first compilation.
+#[test]
+async fn can_notify_when_broken_and_fixed() {
+    let module_path = "src/MyModule.hs";
+    let notification_path = "notification.txt";
+    let mut session = GhciWatchBuilder::new("tests/data/simple")
+        .with_args([
+            "--notify-command",
+            // Write the notification atomically so we don't read a partial file.
+            "sh -c 'cat > notification.tmp && mv notification.tmp notification.txt'",
+        ])
+        .start()
+        .await
+        .expect("ghciwatch starts");
+    let module_path = session.path(module_path);
+    let notification_path = session.path(notification_path);
+    let wait_duration = Duration::from_secs(10);
+
+    session
+        .wait_until_ready()
+        .await
+        .expect("ghciwatch loads ghci");
+    assert!(
+        !notification_path.exists(),
+        "ghciwatch doesn't notify for the first compilation"
+    );
+
+    session
+        .fs()
+        .replace(&module_path, "example :: String", "example :: ()")
+        .await
+        .unwrap();
+    session
+        .wait_for_log("Compilation failed")
+        .await
+        .expect("ghciwatch reloads broken modules");
+    session
+        .fs()
+        .wait_for_path(wait_duration, &notification_path)
+        .await
+        .expect("ghciwatch notifies when compilation fails");
+    assert_eq!(
+        session.fs().read(&notification_path).await.unwrap(),
+        "ghciwatch: Compilation failed: 1 error, 0 warnings\n"
+    );
+
+    session.fs().remove(&notification_path).await.unwrap();
+    session
+        .fs()
+        .replace(&module_path, "example :: ()", "example :: String")
+        .await
+        .unwrap();
+    session
+        .wait_for_log("Compilation succeeded")
+        .await
+        .expect("ghciwatch reloads fixed modules");
+    session
+        .fs()
+        .wait_for_path(wait_duration, &notification_path)
+        .await
+        .expect("ghciwatch notifies when compilation is fixed");
+    assert_eq!(
+        session.fs().read(&notification_path).await.unwrap(),
+        "ghciwatch: Compilation fixed: 3 modules loaded\n"
+    );
+}